@@ -0,0 +1,9 @@
+// Struct literals spell out every field (field: field)
+#![allow(clippy::redundant_field_names)]
+
+#[macro_use]
+extern crate lazy_static;
+
+#[macro_use]
+pub mod util;
+pub mod png_convert;
@@ -1,18 +1,25 @@
+// Struct literals spell out every field (field: field)
+#![allow(clippy::redundant_field_names)]
+
 #[macro_use]
 extern crate clap;
 
 #[macro_use]
-extern crate lazy_static;
+extern crate pngifier;
 
-use std::io::{self, Write};
-use std::time::Instant;
+use std::io::{self, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use std::path::{Path, PathBuf};
 
-#[macro_use]
-mod util;
-mod png_convert;
-use util::{Error, ReadFile, WriteFile};
-use util::color_type::*;
+use pngifier::{util, png_convert};
+mod watch;
+mod options;
+mod serve;
+mod signal;
+use util::{Config, Error, HoleMap, Metadata, Preserve, ReadFile, WriteFile};
+use png_convert::payload::Container;
+use png_convert::container::Format;
+use png_convert::resume::Resume;
 
 macro_rules! check {
 	($t: expr ) => {
@@ -39,218 +46,994 @@ fn process() -> Result<(), Error> {
 			(display_order: 1)
 			(@arg WIDTH: -w --width +takes_value !empty_values "Sets the width of the image in pixels")
 	        (@arg HEIGHT: -h --height +takes_value !empty_values "Sets the height of the image in pixels")
+	        (@arg ASPECT: --aspect +takes_value !empty_values "Sets the aspect ratio of the image (ie: 16:9, 4:3, 1:1)")
+	        (@arg FIT: --fit +takes_value !empty_values "Sets a fixed image size or preset (ie: 1920x1080, 4k, instagram, a4-300dpi)")
+	        (@arg APNG: --apng "Spreads the input across the frames of an animated PNG")
+	        (@arg FRAME_SIZE: --("frame-size") +takes_value !empty_values "Sets the size of each frame used with --apng (ie: 1920x1080, 4k)")
 	        (@arg BYTES: -b --buffer +takes_value !empty_values "Sets the limiting buffer size (ie: 100, 1kb, 10mb, 1gb)")
+	        (@arg IDAT_SIZE: --("idat-size") +takes_value !empty_values "Sets the size of the data of each IDAT chunk (ie: 8kb, 1mb; default: 1mb)")
+	        (@arg MTIME: --mtime +takes_value !empty_values "Stores a modification time (seconds since the unix epoch or now; default: SOURCE_DATE_EPOCH or none)")
 	        (@arg DEPTH: -d --depth +takes_value !empty_values "Sets color depth. Bit depths of 8-bit and 16-bit are supported")
 	        (@arg COLOR_TYPE: -t --type +takes_value !empty_values "Sets the color type (0, 2, 4, 6, g, ga, rgb, rgba)")
+	        (@arg FORMAT: -f --format +takes_value !empty_values "Sets the image format (png, qoi, bmp, tiff, tiff-deflate, webp; default: from the output extension or png)")
+	        (@arg CARRIER: --carrier +takes_value !empty_values "Hides the input inside an existing PNG instead of generating noise")
+	        (@arg BITS: --("bits-per-channel") +takes_value !empty_values "Sets the low bits of each channel used with --carrier (1 to 4, default: 1)")
+	        (@arg ROBUST: --robust "Encodes into flat blocks protected by error correction that survive JPEG recompression")
+	        (@arg LEVELS: --levels +takes_value !empty_values "Sets the brightness levels of each block used with --robust (2 or 4, default: 4)")
+	        (@arg BANNER: --banner +takes_value !empty_values conflicts_with[CARRIER ROBUST] "Renders a title, the file name, size & date above the data")
+	        (@arg BANNER_QR: --("banner-qr") requires[BANNER] "Adds a QR code of the SHA-256 of the input to the banner")
+	        (@arg RESUME: --resume conflicts_with[STREAM CARRIER ROBUST APNG] "Checkpoints to a sidecar file and continues from the last checkpoint after a crash")
+	        (@arg CHECKPOINT_INTERVAL: --("checkpoint-interval") +takes_value !empty_values requires[RESUME] "Sets the input encoded between checkpoints of --resume (ie: 16mb, 1gb; default: 64mb)")
+	        (@arg MMAP: --mmap "Maps the input into memory instead of reading it (the input must not shrink meanwhile)")
+	        (@arg NO_SPARSE: --("no-sparse") "Encodes the holes of sparse files as zeros instead of skipping them")
+	        (@arg PRESERVE: --preserve +takes_value min_values(0) require_equals(true) conflicts_with[CARRIER ROBUST] "Stores the attributes of the input to restore on decode (xattr, acl, owner, mode; default: all)")
+	        (@arg TAG: --tag +takes_value +multiple number_of_values(1) !empty_values conflicts_with[CARRIER ROBUST] "Stores a key=value tag in an iTXt chunk (repeatable; ie: project=apollo)")
+	        (@arg CONTAINER: --container +takes_value !empty_values conflicts_with[CARRIER ROBUST APNG BANNER RESUME] "Sets where the file is stored (pixels, or chunk for ancillary chunks next to a 1x1 placeholder; default: pixels)")
+	        (@arg POLYGLOT: --polyglot +takes_value !empty_values conflicts_with[CARRIER ROBUST RESUME] "Makes the PNG double as an archive of the input that opens without pngifier (zip)")
         	(@arg INPUT: +required "Sets the input file to use")
        		(@arg ACCEPT: -y --yes "Override all values with yes")
+       		(@arg NO_CLOBBER: -n --("no-clobber") conflicts_with[BACKUP] "Fails instead of overriding an existing output")
+       		(@arg BACKUP: --backup +takes_value min_values(0) require_equals(true) "Renames an existing output to its name + suffix before replacing it (default suffix: ~)")
        		(@arg VERIFY: --verify "Verifies the file as a png before attempting to read it")
         	(@arg VERBOSE: -v --verbose "Verbose output")
         	(@arg SILENT: -s --silent "Prevents all outputs")
         	(@arg PROGRESS: -p --progress "Displays the progress")
         	(@arg STREAM: --stream "Streams the output to stdout")
         	(@arg TRIM: --trim "Trims the output (removes trailing null bytes)")
+        	(@arg PROFILE: --profile +takes_value !empty_values "Uses the defaults of a named profile from the config files")
+        	(@arg RESET: --reset +takes_value +multiple number_of_values(1) !empty_values "Ignores the config files & environment for an option, leaving it off or at its default (repeatable; ie: verbose)")
         	(@arg OUTPUT: "Sets the output file")   
 		)
 		(@subcommand decode =>
 			(display_order: 2)
 			(@arg BYTES: -b --buffer +takes_value !empty_values "Sets the limiting buffer size (ie: 100, 1kb, 10mb, 1gb)")
-        	(@arg INPUT: +required "Sets the input file to use")
+			(@arg CARRIER_MODE: --("carrier-mode") "Extracts a file hidden inside an existing PNG with --carrier")
+			(@arg ROBUST: --robust "Recovers a file encoded with --robust, even after lossy recompression")
+			(@arg RESUME: --resume conflicts_with[STREAM CARRIER_MODE ROBUST] "Checkpoints at the flush points of PNGs encoded with --resume and continues after a crash")
+			(@arg MMAP: --mmap "Maps the input into memory instead of reading it (the input must not shrink meanwhile)")
+			(@arg MAX_CHUNK_BYTES: --("max-chunk-bytes") +takes_value !empty_values "Fails on chunks with more data than this (ie: 16mb; default: 2gb - 1b)")
+			(@arg MAX_OUTPUT_BYTES: --("max-output-bytes") +takes_value !empty_values "Fails once the output grows past this (ie: 1gb; default: unlimited)")
+			(@arg MAX_DIMENSIONS: --("max-dimensions") +takes_value !empty_values "Fails on images (or frames) larger than this (ie: 16384x16384, 8k; default: unlimited)")
+			(@arg MAX_RATIO: --("max-ratio") +takes_value !empty_values "Fails once image data inflates past this many times its size (ie: 1000; default: unlimited)")
+			(@arg PRESERVE: --preserve +takes_value min_values(0) require_equals(true) conflicts_with[STREAM CARRIER_MODE ROBUST] "Restores the attributes stored with --preserve onto the output (xattr, acl, owner, mode; default: all)")
+        	(@arg INPUT: +required "Sets the input file to use (- reads a PNG from stdin)")
        		(@arg ACCEPT: -y --yes "Override all values with yes")
+       		(@arg NO_CLOBBER: -n --("no-clobber") conflicts_with[BACKUP] "Fails instead of overriding an existing output")
+       		(@arg BACKUP: --backup +takes_value min_values(0) require_equals(true) "Renames an existing output to its name + suffix before replacing it (default suffix: ~)")
        		(@arg VERIFY: --verify "Verifies the file as a png before attempting to read it")
         	(@arg VERBOSE: -v --verbose "Verbose output")
         	(@arg SILENT: -s --silent "Prevents all outputs")
         	(@arg PROGRESS: -p --progress "Displays the progress")
         	(@arg STREAM: --stream "Streams the output to stdout")
         	(@arg TRIM: --trim "Trims the output (removes trailing null bytes)")
+        	(@arg PROFILE: --profile +takes_value !empty_values "Uses the defaults of a named profile from the config files")
+        	(@arg RESET: --reset +takes_value +multiple number_of_values(1) !empty_values "Ignores the config files & environment for an option, leaving it off or at its default (repeatable; ie: verbose)")
         	(@arg OUTPUT: "Sets the output file")
 		)
+		(@subcommand watch =>
+			(display_order: 3)
+			(about: "Continuously encodes (or decodes) files added to a directory")
+			(@arg IN_DIR: +required "Sets the directory to watch")
+			(@arg OUT_DIR: +required "Sets the directory to write outputs to")
+			(@arg DECODE: --decode "Decodes images added to the directory instead of encoding")
+			(@arg DEBOUNCE: --debounce +takes_value !empty_values "Sets the seconds a file must stay unchanged before it is converted (default: 2)")
+			(@arg STATE: --state +takes_value !empty_values "Sets the state file of processed files (default: OUT_DIR/.pngifier-watch)")
+			(@arg ERRORS: --errors +takes_value !empty_values "Sets the directory failed inputs are moved to (default: IN_DIR/errors)")
+			(@arg WIDTH: -w --width +takes_value !empty_values "Sets the width of the image in pixels")
+	        (@arg HEIGHT: -h --height +takes_value !empty_values "Sets the height of the image in pixels")
+	        (@arg ASPECT: --aspect +takes_value !empty_values "Sets the aspect ratio of the image (ie: 16:9, 4:3, 1:1)")
+	        (@arg FIT: --fit +takes_value !empty_values "Sets a fixed image size or preset (ie: 1920x1080, 4k, instagram, a4-300dpi)")
+	        (@arg APNG: --apng "Spreads the input across the frames of an animated PNG")
+	        (@arg FRAME_SIZE: --("frame-size") +takes_value !empty_values "Sets the size of each frame used with --apng (ie: 1920x1080, 4k)")
+	        (@arg BYTES: -b --buffer +takes_value !empty_values "Sets the limiting buffer size (ie: 100, 1kb, 10mb, 1gb)")
+	        (@arg IDAT_SIZE: --("idat-size") +takes_value !empty_values "Sets the size of the data of each IDAT chunk (ie: 8kb, 1mb; default: 1mb)")
+	        (@arg MTIME: --mtime +takes_value !empty_values "Stores a modification time (seconds since the unix epoch or now; default: SOURCE_DATE_EPOCH or none)")
+	        (@arg DEPTH: -d --depth +takes_value !empty_values "Sets color depth. Bit depths of 8-bit and 16-bit are supported")
+	        (@arg COLOR_TYPE: -t --type +takes_value !empty_values "Sets the color type (0, 2, 4, 6, g, ga, rgb, rgba)")
+	        (@arg FORMAT: -f --format +takes_value !empty_values "Sets the image format (png, qoi, bmp, tiff, tiff-deflate, webp; default: from the output extension or png)")
+       		(@arg VERIFY: --verify "Verifies the file as a png before attempting to read it")
+        	(@arg VERBOSE: -v --verbose "Verbose output")
+        	(@arg SILENT: -s --silent "Prevents all outputs")
+        	(@arg TRIM: --trim "Trims the output (removes trailing null bytes)")
+        	(@arg PROFILE: --profile +takes_value !empty_values "Uses the defaults of a named profile from the config files")
+        	(@arg RESET: --reset +takes_value +multiple number_of_values(1) !empty_values "Ignores the config files & environment for an option, leaving it off or at its default (repeatable; ie: verbose)")
+		)
+		(@subcommand serve =>
+			(display_order: 4)
+			(about: "Serves encoding and decoding over HTTP")
+			(@arg BIND: --bind +takes_value !empty_values "Sets the address to listen on (default: 127.0.0.1:8080)")
+	        (@arg BYTES: -b --buffer +takes_value !empty_values "Sets the limiting buffer size (ie: 100, 1kb, 10mb, 1gb)")
+	        (@arg IDAT_SIZE: --("idat-size") +takes_value !empty_values "Sets the size of the data of each IDAT chunk (ie: 8kb, 1mb; default: 1mb)")
+	        (@arg DEPTH: -d --depth +takes_value !empty_values "Sets color depth. Bit depths of 8-bit and 16-bit are supported")
+	        (@arg COLOR_TYPE: -t --type +takes_value !empty_values "Sets the color type (0, 2, 4, 6, g, ga, rgb, rgba)")
+	        (@arg FORMAT: -f --format +takes_value !empty_values "Sets the image format (png, qoi, bmp, tiff, tiff-deflate, webp; default: from the output extension or png)")
+	        (@arg MAX_CHUNK_BYTES: --("max-chunk-bytes") +takes_value !empty_values "Fails decodes of chunks with more data than this (ie: 16mb; default: 2gb - 1b)")
+	        (@arg MAX_OUTPUT_BYTES: --("max-output-bytes") +takes_value !empty_values "Fails decodes once the output grows past this (ie: 1gb; default: unlimited)")
+	        (@arg MAX_DIMENSIONS: --("max-dimensions") +takes_value !empty_values "Fails decodes of images (or frames) larger than this (ie: 16384x16384, 8k; default: unlimited)")
+	        (@arg MAX_RATIO: --("max-ratio") +takes_value !empty_values "Fails decodes once image data inflates past this many times its size (ie: 1000; default: unlimited)")
+	        (@arg MAX_INPUT_BYTES: --("max-input-bytes") +takes_value !empty_values "Fails encodes of request bodies larger than this (ie: 16gb; default: 1gb)")
+        	(@arg VERBOSE: -v --verbose "Verbose output")
+        	(@arg SILENT: -s --silent "Prevents all outputs")
+        	(@arg PROFILE: --profile +takes_value !empty_values "Uses the defaults of a named profile from the config files")
+        	(@arg RESET: --reset +takes_value +multiple number_of_values(1) !empty_values "Ignores the config files & environment for an option, leaving it off or at its default (repeatable; ie: verbose)")
+		)
+		(@subcommand inspect =>
+			(display_order: 5)
+			(about: "Displays the header and chunks of a PNG")
+        	(@arg INPUT: +required "Sets the input file to use")
+        	(@arg JSON: --json "Displays the result as JSON")
+        	(@arg MMAP: --mmap "Maps the input into memory instead of reading it (the input must not shrink meanwhile)")
+		)
+		(@subcommand tags =>
+			(display_order: 6)
+			(about: "Displays the tags stored in a PNG with --tag")
+        	(@arg INPUT: +required "Sets the input file to use")
+        	(@arg JSON: --json "Displays the result as JSON")
+		)
+		(@subcommand find =>
+			(display_order: 7)
+			(about: "Lists the PNGs in a directory (and its subdirectories) with the given tags")
+			(@arg DIR: +required "Sets the directory to search")
+			(@arg TAG: --tag +required +takes_value +multiple number_of_values(1) !empty_values "Matches a key=value tag, or a key with any value (repeatable; every tag must match)")
+		)
+		(@subcommand verify =>
+			(display_order: 8)
+			(about: "Verifies the signature & CRCs of a PNG, or (with --strict) every rule of the PNG specification")
+        	(@arg INPUT: +required "Sets the input file to use")
+        	(@arg STRICT: --strict "Checks chunk ordering, the critical chunks, IHDR, the zlib stream & the size of the image data")
+        	(@arg JSON: --json "Displays the issues found by --strict as JSON")
+        	(@arg MMAP: --mmap "Maps the input into memory instead of reading it (the input must not shrink meanwhile)")
+		)
 	).get_matches();
 
 	if let Some(encode) = matches.subcommand_matches("encode") {
+		let encode = Config::load(encode, "encode")?;
 		initialize(&encode);
 
-		let (mut read_file, mut write_file) = parse_input_output(&encode, false)?;
+		let (read_file, write_file) = parse_input_output(&encode, false)?;
+		signal::install();
+		return encode_file(&encode, read_file, write_file);
+	} else if let Some(decode) = matches.subcommand_matches("decode") {
+		let decode = Config::load(decode, "decode")?;
+		initialize(&decode);
 
-		let mut color_type: u8 = 2;
-		let mut bit_depth: u8 = 8;
-		let mut bytes_per_px = total_bytes(color_type, bit_depth) as u64;
+		if decode.value_of("INPUT").as_deref() == Some("-") {
+			return decode_stdin(&decode);
+		}
+		let (read_file, write_file) = parse_input_output(&decode, true)?;
+		signal::install();
+		return decode_file(&decode, read_file, write_file);
+	} else if let Some(serve) = matches.subcommand_matches("serve") {
+		let encode = Config::load(serve, "encode")?;
+		let decode = Config::load(serve, "decode")?;
+		initialize(&encode);
+		unsafe {
+			util::verbosity::PROGRESS = false;
+		}
 
-		if let Some(custom_depth) = encode.value_of("DEPTH") {
-			match custom_depth.parse::<u8>() {
-				Ok(custom_depth) => {
-					if !(custom_depth == 8 || custom_depth == 16) {
-						error!(ParseBitDepth, custom_depth);
-					}
-					bit_depth = custom_depth;
-					bytes_per_px = total_bytes(color_type, bit_depth) as u64;
-				},
-				_ => error!(ParseBitDepth, custom_depth)
-			};
+		let limits = options::decode_limits(&decode)?;
+		let bind = encode.value_of("BIND").unwrap_or_else(|| String::from("127.0.0.1:8080"));
+		return serve::serve(&encode, &decode, &limits, &bind);
+	} else if let Some(inspect) = matches.subcommand_matches("inspect") {
+		let input = inspect.value_of("INPUT").unwrap();
+		let read_file = error_exp!(ReadFail, input, ReadFile::from_string(input.to_string()));
+		let inspection = match map_input(inspect.is_present("MMAP"), &read_file) {
+			Some(map) => error_exp!(InvalidHeader, input, png_convert::inspect_bytes(&map)),
+			None => error_exp!(InvalidHeader, input, png_convert::inspect(read_file))
+		};
+		match inspect.is_present("JSON") {
+			true => println!("{}", inspection.to_json()),
+			false => print!("{}", inspection)
+		};
+		return Ok(());
+	} else if let Some(tags) = matches.subcommand_matches("tags") {
+		let input = tags.value_of("INPUT").unwrap();
+		let mut read_file = error_exp!(ReadFail, input, ReadFile::from_string(input.to_string()));
+		let tags_found = error_exp!(InvalidHeader, input, png_convert::tags::read_tags(&mut read_file, true));
+		match tags.is_present("JSON") {
+			true => println!("{}", png_convert::tags::to_json(&tags_found)),
+			false => for (key, value) in tags_found.iter() {
+				println!("\x1b[1;33m{}: \x1b[1;36m{}\x1b[0m", key, value);
+			}
+		};
+		return Ok(());
+	} else if let Some(find) = matches.subcommand_matches("find") {
+		let dir = PathBuf::from(find.value_of("DIR").unwrap());
+		if !dir.is_dir() {
+			error!(InputNotADirectory, dir.display());
+		}
+		let mut wanted = Vec::new();
+		for tag in find.values_of("TAG").unwrap() {
+			wanted.push(match tag.split_once('=') {
+				Some((key, value)) if png_convert::tags::valid_key(key) => (key.to_string(), Some(value.to_string())),
+				None if png_convert::tags::valid_key(tag) => (tag.to_string(), None),
+				_ => error!(ParseTag, tag)
+			});
+		}
+		find_tagged(&dir, &wanted);
+		return Ok(());
+	} else if let Some(verify_matches) = matches.subcommand_matches("verify") {
+		let input = verify_matches.value_of("INPUT").unwrap();
+		let mut read_file = error_exp!(ReadFail, input, ReadFile::from_string(input.to_string()));
+		if !verify_matches.is_present("STRICT") {
+			return verify(&mut read_file, verify_matches.is_present("MMAP"));
+		}
+		let lint = match map_input(verify_matches.is_present("MMAP"), &read_file) {
+			Some(map) => error_exp!(ReadFail, input, png_convert::lint(&map[..])),
+			None => error_exp!(ReadFail, input, png_convert::lint(&mut read_file))
+		};
+		match verify_matches.is_present("JSON") {
+			true => println!("{}", lint.to_json()),
+			false => print!("{}", lint)
+		};
+		if lint.errors() != 0 {
+			error!(NotConformant, input);
+		}
+		return Ok(());
+	} else if let Some(watch) = matches.subcommand_matches("watch") {
+		let decode = watch.is_present("DECODE");
+		let watch = Config::load(watch, if decode { "decode" } else { "encode" })?;
+		initialize(&watch);
+		unsafe {
+			util::verbosity::SKIP = true;
 		}
 
-		if let Some(custom_color_type) = encode.value_of("COLOR_TYPE") {
-			match custom_color_type.parse::<u8>() {
-				Ok(custom_color_type) => {
-					if !(type_exists(custom_color_type)) {
-						error!(ParseColorType, custom_color_type);
-					}
-					color_type = custom_color_type;
-					bytes_per_px = total_bytes(color_type, bit_depth) as u64;
-				},
-				_ => {
-					match type_str_translate(custom_color_type) {
-						Some(custom_color_type) => {
-							let custom_color_type = *custom_color_type;
-							if !(type_exists(custom_color_type)) {
-								error!(ParseColorType, custom_color_type);
-							}
-							color_type = custom_color_type;
-							bytes_per_px = total_bytes(color_type, bit_depth) as u64;
-						},
-						_ => error!(ParseColorType, custom_color_type)
-					}
-				}
+		let input_dir = PathBuf::from(watch.value_of("IN_DIR").unwrap());
+		if !input_dir.is_dir() {
+			error!(InputNotADirectory, input_dir.display());
+		}
+		let output_dir = PathBuf::from(watch.value_of("OUT_DIR").unwrap());
+
+		let mut debounce: u64 = 2;
+		if let Some(custom_debounce) = watch.value_of("DEBOUNCE") {
+			debounce = match custom_debounce.parse::<u64>() {
+				Ok(d) => d,
+				_ => error!(ParseDebounce, custom_debounce)
 			};
 		}
 
-		let max_bytes: u64 = read_file.size;
-		let mut width: u64 = ((max_bytes / bytes_per_px) as f64).sqrt() as u64;
-		let mut chunk_size: u64 = width * bytes_per_px;
-		let mut height: u64 = max_bytes / chunk_size;
-		let mut buffer_size = chunk_size;
-		if max_bytes != chunk_size {
-			height += 1;
+		let options = watch::WatchOptions {
+			state_path: match watch.value_of("STATE") {
+				Some(s) => PathBuf::from(s),
+				None => output_dir.join(".pngifier-watch")
+			},
+			error_dir: match watch.value_of("ERRORS") {
+				Some(s) => PathBuf::from(s),
+				None => input_dir.join("errors")
+			},
+			input_dir: input_dir,
+			output_dir: output_dir,
+			debounce: Duration::from_secs(debounce),
+			decode: decode,
+			extension: options::output_format(&watch)?.extension()
+		};
+
+		return watch::watch(&options, |input, output| {
+			let read_file = error_exp!(ReadFail, input.display(), ReadFile::from_pathbuf(input.to_path_buf()));
+			let write_file = error_exp!(WriteFail, output.display(), WriteFile::atomic(output.to_path_buf()));
+			match decode {
+				true => decode_file(&watch, read_file, write_file),
+				false => encode_file(&watch, read_file, write_file)
+			}
+		});
+	}
+	std::process::exit(1)
+}
+
+// Encodes the input file into the output file using the resolved options
+fn encode_file(encode: &Config, mut read_file: ReadFile, mut write_file: WriteFile) -> Result<(), Error> {
+	if encode.is_present("RESUME") && (encode.value_of("CARRIER").is_some() || encode.is_present("ROBUST")) {
+		error!(UnsupportedResume, "carrier or robust images");
+	}
+	if let Some(carrier) = encode.value_of("CARRIER") {
+		return carrier_encode_file(encode, &carrier, read_file, write_file);
+	}
+	if encode.is_present("ROBUST") {
+		return robust_encode_file(encode, read_file, write_file);
+	}
+
+	let polyglot = options::polyglot(encode)?;
+	// Only PNGs record holes (other containers get them as zeros, and so do the ZIP archives of polyglots and files
+	// with more holes than decoders read)
+	let holes = match encode.is_present("NO_SPARSE") || polyglot.is_some() || options::output_format(encode)? != png_convert::OutputFormat::Png {
+		true => None,
+		false => HoleMap::new(read_file.size, error_exp!(ReadFail, &read_file, read_file.holes()))
+			.filter(|holes| holes.to_data().len() as u64 <= util::limits::MAX_PRIVATE_CHUNK_LENGTH)
+	};
+	let mut options = options::encode_options(encode, &read_file.to_string(), holes.as_ref().map_or(read_file.size, |holes| holes.data_size()))?;
+	options.holes = holes;
+	if polyglot.is_some() {
+		if options.format != png_convert::OutputFormat::Png {
+			error!(UnsupportedPolyglot, format!("{} images", options.format));
+		}
+		if options.max_bytes > png_convert::polyglot::MAX_BYTES {
+			error!(UnsupportedPolyglot, format!("an input of {} bytes", options.max_bytes));
 		}
+		let name = PathBuf::from(read_file.to_string());
+		options.zip = Some(name.file_name().map_or(String::from("file"), |name| name.to_string_lossy().to_string()));
+	}
+	if let Some(preserve) = options::preserve(encode)? {
+		match options.format {
+			png_convert::OutputFormat::Png => options.metadata = Some(capture_metadata(&read_file, &preserve)?),
+			format => silent!({println!("\x1b[1;33mWarning: Skipping --preserve, as only PNGs store attributes (not {} images).\x1b[0m", format)})
+		};
+	}
+	if !options.tags.is_empty() && options.format != png_convert::OutputFormat::Png {
+		silent!({println!("\x1b[1;33mWarning: Skipping --tag, as only PNGs store tags (not {} images).\x1b[0m", options.format)});
+		options.tags.clear();
+	}
+	if let Some(title) = encode.value_of("BANNER") {
+		options.banner = Some(render_banner(encode, &title, &mut read_file, &options)?);
+	}
+
+	verbose!({
+		println!(
+			"\n\x1b[1;36mConfiguration:\x1b[1;33m \n\
+			[{}\x1b[1;33m] Verification Mode{} \n\
+			[{}\x1b[1;33m] Trimming{} \n\
+			[{}\x1b[1;33m] Buffer Size: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] IDAT Size: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Modification Time: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Width: \x1b[1;36m{}px\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Height: \x1b[1;36m{}px\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Aspect Ratio: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Fit: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Color Type: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Bit Depth: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Format: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Container: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Frames: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Banner: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Holes: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Preserve: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Tags: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Polyglot: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Resume: \x1b[1;36m{}\x1b[1;33m{}\x1b[0m\n",
+		check!(encode.is_present("VERIFY")), encode.origin("VERIFY"),
+		check!(encode.is_present("TRIM")), encode.origin("TRIM"),
+		check!(encode.source("BYTES").is_some()), options.buffer_size, encode.origin("BYTES"),
+		check!(encode.source("IDAT_SIZE").is_some()), options.idat_size, encode.origin("IDAT_SIZE"),
+		check!(options.mtime.is_some()), options.mtime.map_or(String::from("none"), |seconds| seconds.to_string()), encode.origin("MTIME"),
+		check!(encode.source("WIDTH").is_some()), options.width, encode.origin("WIDTH"),
+		check!(encode.source("HEIGHT").is_some()), options.height, encode.origin("HEIGHT"),
+		check!(encode.source("ASPECT").is_some()), encode.value_of("ASPECT").unwrap_or_else(|| String::from("none")), encode.origin("ASPECT"),
+		check!(encode.source("FIT").is_some()), encode.value_of("FIT").unwrap_or_else(|| String::from("none")), encode.origin("FIT"),
+		check!(encode.source("COLOR_TYPE").is_some()), options.color_type, encode.origin("COLOR_TYPE"),
+		check!(encode.source("DEPTH").is_some()), options.bit_depth, encode.origin("DEPTH"),
+		check!(encode.source("FORMAT").is_some()), options.format, encode.origin("FORMAT"),
+		check!(encode.source("CONTAINER").is_some()), options.container, encode.origin("CONTAINER"),
+		check!(options.frames.is_some()), options.frames.unwrap_or(1), encode.origin("APNG"),
+		check!(options.banner.is_some()), options.banner.as_ref().map_or(String::from("none"), |banner| format!("{} rows", banner.rows)), encode.origin("BANNER"),
+		check!(options.holes.is_some()), options.holes.as_ref().map_or(String::from("none"), |holes| format!("{} bytes in {} holes skipped", holes.hole_bytes(), holes.holes.len())), encode.origin("NO_SPARSE"),
+		check!(options.metadata.is_some()), options.metadata.as_ref().map_or(String::from("none"), |metadata| metadata.to_string()), encode.origin("PRESERVE"),
+		check!(!options.tags.is_empty()), match options.tags.is_empty() {
+			true => String::from("none"),
+			false => options.tags.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<String>>().join(", ")
+		}, encode.origin("TAG"),
+		check!(polyglot.is_some()), polyglot.map_or(String::from("none"), |polyglot| polyglot.to_string()), encode.origin("POLYGLOT"),
+		check!(encode.is_present("RESUME")), match encode.is_present("RESUME") {
+			true => format!("every {} bytes", options::checkpoint_interval(encode)?),
+			false => String::from("none")
+		}, encode.origin("RESUME")
+		);
+	});
 
-		if encode.is_present("WIDTH") && encode.is_present("HEIGHT") {
-			error!(WidthAndHeightDefined);
+	if options.container == Container::Chunk {
+		verbose!({println!("\x1b[1;33mWarning: {}.\x1b[0m", png_convert::payload::STRIP_WARNING)});
+	}
+
+	let mut resume = None;
+	if encode.is_present("RESUME") {
+		if options.container == Container::Chunk {
+			error!(UnsupportedResume, "chunk containers");
+		}
+		if polyglot.is_some() {
+			error!(UnsupportedResume, "ZIP polyglots");
+		}
+		if options.format != png_convert::OutputFormat::Png {
+			error!(UnsupportedResume, format!("{} images", options.format));
 		}
+		if options.frames.is_some() {
+			error!(UnsupportedResume, "animations");
+		}
+		let fingerprint = format!(
+			"encode {}x{} {} {} {} {} {} {} {} {} {}",
+			options.width, options.height, options.color_type, options.bit_depth, options.idat_size,
+			options.banner.as_ref().map_or(0, |banner| banner.rows), options.mtime.unwrap_or(0), options.trim,
+			options.holes.as_ref().map_or(0, |holes| holes.hole_bytes()), options.metadata.as_ref().map_or(0, |metadata| metadata.to_data().len()),
+			options.tags.len()
+		);
+		let loaded = load_resume(encode, &read_file, &mut write_file, fingerprint)?;
+		if let Some(checkpoint) = &loaded.checkpoint {
+			// Checkpoints count the input without its holes
+			let offset = std::cmp::min(checkpoint.input_offset, options.max_bytes);
+			error_exp!(ReadFail, &read_file, read_file.seek(SeekFrom::Start(options.holes.as_ref().map_or(offset, |holes| holes.unpacked(offset)))));
+		}
+		resume = Some(loaded);
+	}
 
-		if let Some(custom_width) = parse_arg_u64(encode, "WIDTH", false)? {
-			if custom_width != width {
-				width = custom_width;
-				chunk_size = width * bytes_per_px;
-				height = max_bytes / chunk_size;
-				if max_bytes != chunk_size {
-					height += 1;
-				}	
-			}
+	if !encode.is_present("STREAM") {
+		let rows = options.height * options.frames.unwrap_or(1) + options.banner.as_ref().map_or(0, |banner| banner.rows as u64);
+		error_exp!(WriteFail, &write_file, write_file.preallocate((options.chunk_size + 1) * rows));
+	}
+	let map = map_input(encode.is_present("MMAP"), &read_file);
+	let mut mapped = map.as_ref().map(|map| io::Cursor::new(&map[..]));
+	let position = error_exp!(ReadFail, &read_file, read_file.stream_position());
+	let source = match mapped.as_mut() {
+		Some(cursor) => {
+			cursor.set_position(position);
+			skip_holes(cursor, options.holes.as_ref(), position)
+		},
+		None => skip_holes(&mut read_file, options.holes.as_ref(), position)
+	};
+	let source = signal::token().reader(source);
+
+	let start = Instant::now();
+	error_exp!(
+		Encode,
+		&read_file,
+		match resume.as_mut() {
+			Some(resume) => png_convert::encode_resumable(
+				source,
+				png_convert::sink::new(options.format, &mut write_file),
+				&options,
+				resume,
+				options::checkpoint_interval(encode)?
+			),
+			None => png_convert::encode(
+				source,
+				png_convert::sink::new(options.format, &mut write_file),
+				&options
+			)
 		}
+	);
+	if !encode.is_present("STREAM") {
+		error_exp!(WriteFail, &write_file, write_file.release());
+	}
+	silent!({println!("Encoded \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, &write_file, start.elapsed())});
+
+
+	if encode.is_present("VERIFY") && !encode.is_present("STREAM") {
+		match options.format {
+			png_convert::OutputFormat::Png => {
+				let mut write_file = error_exp!(ReadFail, &write_file, write_file.read());
+				verify(&mut write_file, encode.is_present("MMAP"))?;
+			},
+			format => verbose!({println!("\x1b[1;33mWarning: Skipping verification of the {} image '\x1b[1;36m{}\x1b[1;33m'.\x1b[0m", format, &write_file)})
+		};
+	}
+
+	commit(encode, &mut write_file)?;
+	Ok(())
+}
+
+// Decodes a PNG piped into stdin as it arrives (there is nothing to seek, map or verify ahead of time)
+fn decode_stdin(decode: &Config) -> Result<(), Error> {
+	let unsupported: Vec<&str> = ["VERIFY", "RESUME", "CARRIER_MODE", "ROBUST", "PRESERVE"].iter()
+		.filter(|name| decode.is_present(name))
+		.copied()
+		.collect();
+	if !unsupported.is_empty() {
+		error!(UnsupportedStdin, unsupported.iter().map(|name| format!("--{}", name.to_lowercase().replace('_', "-"))).collect::<Vec<String>>().join(", "));
+	}
+	let buffer_size = options::decode_buffer_size(decode)?;
+	let limits = options::decode_limits(decode)?;
 
-		if let Some(custom_height) = parse_arg_u64(encode, "HEIGHT", true)? {
-			if custom_height != height {
-				height = custom_height;	
-				width = max_bytes / (height * bytes_per_px);
-				if max_bytes % (height * bytes_per_px) != 0 {
-					width += 1;
+	let output = decode.value_of("OUTPUT");
+	let write_file = match (decode.is_present("STREAM"), &output) {
+		(true, _) => WriteFile::stdout(),
+		(false, Some(output)) => {
+			// Stdin is taken by the PNG, so there is no asking before overriding
+			if Path::new(output).exists() {
+				if decode.is_present("NO_CLOBBER") {
+					error!(NoClobber, output);
+				}
+				if !unsafe { util::verbosity::SKIP } {
+					error!(OutputExists, output);
 				}
-				chunk_size = width * bytes_per_px;
 			}
-		}
+			WriteFile::atomic(PathBuf::from(output))
+		},
+		(false, None) => error!(MissingStdinOutput)
+	};
+	let mut write_file = match write_file {
+		Ok(file) => file,
+		_ => error!(WriteFail, output.unwrap_or_else(|| String::from("stdout")))
+	};
+	// The size of the input is unknown
+	unsafe {
+		util::verbosity::PROGRESS = false;
+	}
 
-		if let Some(custom_buffer) = parse_byte_string(encode)? {
-			buffer_size = custom_buffer;
-		}
+	signal::install();
+	let start = Instant::now();
+	let stdin = signal::token().reader(io::stdin().lock());
+	let hole_bytes = error_exp!(Decode, "stdin", within_limits(&"stdin", png_convert::decode(stdin, 0, &mut write_file, buffer_size, &limits))?);
+	error_exp!(WriteFail, &write_file, write_file.flush());
+	silent!({println!("Decoded \x1b[1;36m'stdin'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &write_file, start.elapsed())});
+	report_holes(decode, &write_file, hole_bytes);
 
-		verbose!({
-			println!(
-				"\n\x1b[1;36mConfiguration:\x1b[1;33m \n\
-				[{}\x1b[1;33m] Verification Mode \n\
-				[{}\x1b[1;33m] Trimming \n\
-				[{}\x1b[1;33m] Buffer Size: \x1b[1;36m{}\x1b[1;33m \n\
-				[{}\x1b[1;33m] Width: \x1b[1;36m{}px\x1b[1;33m \n\
-				[{}\x1b[1;33m] Height: \x1b[1;36m{}px\x1b[1;33m \n\
-				[{}\x1b[1;33m] Color Type: \x1b[1;36m{}\x1b[1;33m \n\
-				[{}\x1b[1;33m] Bit Depth: \x1b[1;36m{}\x1b[0m\n",
-			check!(encode.is_present("VERIFY")),
-			check!(encode.is_present("TRIM")),
-			check!(encode.is_present("BYTES")), buffer_size,
-			check!(encode.is_present("WIDTH")), width,
-			check!(encode.is_present("HEIGHT")), height,
-			check!(encode.is_present("COLOR_TYPE")), color_type,
-			check!(encode.is_present("DEPTH")), bit_depth			
-			);
-		});
+	// Sparse outputs are already cut to the size of the original file (their trailing holes read as zeros)
+	if decode.is_present("TRIM") && !decode.is_present("STREAM") && hole_bytes == 0 {
+		write_file.trim(buffer_size)?;
+	}
+	commit(decode, &mut write_file)?;
+	Ok(())
+}
 
-		let start = Instant::now();
-		error_exp!(
-			Encode,
-			&read_file,
-			png_convert::encode(
-				&mut read_file,
-				&mut write_file,
-				width,
-				height,
-				chunk_size,
-				max_bytes,
-				buffer_size as usize,
-				bit_depth,
-				color_type,
-				encode.is_present("TRIM")
-			)
+// Decodes the input file into the output file using the resolved options
+fn decode_file(decode: &Config, mut read_file: ReadFile, mut write_file: WriteFile) -> Result<(), Error> {
+	if decode.is_present("RESUME") && (decode.is_present("CARRIER_MODE") || decode.is_present("ROBUST")) {
+		error!(UnsupportedResume, "carrier or robust images");
+	}
+	if decode.is_present("CARRIER_MODE") {
+		return carrier_decode_file(decode, read_file, write_file);
+	}
+	if decode.is_present("ROBUST") {
+		return robust_decode_file(decode, read_file, write_file);
+	}
+
+	let buffer_size = options::decode_buffer_size(decode)?;
+	let limits = options::decode_limits(decode)?;
+	let size = read_file.size;
+	let (format, lossy) = input_format(&mut read_file)?;
+
+	verbose!({
+		println!(
+			"\n\x1b[1;36mConfiguration:\x1b[1;33m \n\
+			[{}\x1b[1;33m] Verification Mode{} \n\
+			[{}\x1b[1;33m] Trimming{} \n\
+			[{}\x1b[1;33m] Buffer Size: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Resume{} \n\
+			[{}\x1b[1;33m] Limits: \x1b[1;36m{}\x1b[1;33m \n\
+			[ \x1b[1;33m] Format: \x1b[1;36m{}\x1b[0m\n",
+		check!(decode.is_present("VERIFY")), decode.origin("VERIFY"),
+		check!(decode.is_present("TRIM")), decode.origin("TRIM"),
+		check!(decode.source("BYTES").is_some()), buffer_size, decode.origin("BYTES"),
+		check!(decode.is_present("RESUME")), decode.origin("RESUME"),
+		check!(limits != util::Limits::default()), describe_limits(&limits),
+		format
 		);
-		silent!({println!("Encoded \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, &write_file, start.elapsed())});
+	});
+
+	if lossy {
+		silent!({println!("\x1b[1;33mWarning: '\x1b[1;36m{}\x1b[1;33m' is a lossy {} image. Unless it was encoded with --robust, the output is likely corrupted.\x1b[0m", &read_file, format)});
+	}
+	if format != Format::Png {
+		if decode.is_present("PRESERVE") {
+			silent!({println!("\x1b[1;33mWarning: Skipping --preserve, as only PNGs store attributes (not {} images).\x1b[0m", format)});
+		}
+		if decode.is_present("RESUME") {
+			error!(UnsupportedResume, format!("{} images", format));
+		}
+		return container_decode_file(decode, format, buffer_size, &limits, read_file, write_file);
+	}
+
+	if decode.is_present("VERIFY") {
+		verify(&mut read_file, decode.is_present("MMAP"))?;	
+	}
+
+	let mut resume = None;
+	if decode.is_present("RESUME") {
+		resume = Some(load_resume(decode, &read_file, &mut write_file, String::from("decode"))?);
+	}
 
+	// Outputs are about as large as the image data (except for sparse files, whose holes would be allocated too)
+	let holes = png_convert::read_holes(&mut read_file, &limits);
+	let holes = error_exp!(Decode, &read_file, within_limits(&read_file, holes)?);
+	if !decode.is_present("STREAM") && holes.is_none() {
+		error_exp!(WriteFail, &write_file, write_file.preallocate(size));
+	}
+	let mut metadata = None;
+	if let Some(preserve) = options::preserve(decode)? {
+		let read = png_convert::read_metadata(&mut read_file, &limits);
+		metadata = Some((preserve, error_exp!(Decode, &read_file, within_limits(&read_file, read)?)));
+	}
+	let map = map_input(decode.is_present("MMAP"), &read_file);
 
-		if encode.is_present("VERIFY") && !encode.is_present("STREAM") {
-			let mut write_file = error_exp!(ReadFail, &write_file, write_file.read());
-			verify(&mut write_file)?;			
+	let start = Instant::now();
+	let token = signal::token();
+	let result = match (map.as_ref(), resume.as_mut()) {
+		(Some(map), Some(resume)) => png_convert::decode_resumable(token.reader(io::Cursor::new(&map[..])), size, &mut write_file, buffer_size, &limits, resume),
+		(Some(map), None) => png_convert::decode(token.reader(&map[..]), size, &mut write_file, buffer_size, &limits),
+		(None, Some(resume)) => png_convert::decode_resumable(token.reader(&mut read_file), size, &mut write_file, buffer_size, &limits, resume),
+		(None, None) => png_convert::decode(token.reader(&mut read_file), size, &mut write_file, buffer_size, &limits)
+	};
+	let hole_bytes = error_exp!(Decode, &read_file, within_limits(&read_file, result)?);
+	if !decode.is_present("STREAM") {
+		error_exp!(WriteFail, &write_file, write_file.release());
+	}
+	silent!({println!("Decoded \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, &write_file, start.elapsed())});
+	report_holes(decode, &write_file, hole_bytes);
+
+	silent!({
+		if decode.is_present("TRIM") && !decode.is_present("STREAM") && hole_bytes == 0 {
+			write_file.trim(buffer_size)?
 		}
+	});
 
-		return Ok(());
-	} else if let Some(decode) = matches.subcommand_matches("decode") {
-		initialize(&decode);
+	commit(decode, &mut write_file)?;
+	if let Some((preserve, metadata)) = metadata {
+		restore_metadata(&read_file, &write_file, metadata.as_ref(), &preserve);
+	}
+	Ok(())
+}
 
-		let (mut read_file, mut write_file) = parse_input_output(&decode, true)?;
+// Reads the input without the holes of a sparse file, starting from position (where the source already is)
+fn skip_holes<'a, R: Read + Seek + 'a>(source: R, holes: Option<&'a HoleMap>, position: u64) -> Box<dyn Read + 'a> {
+	match holes {
+		Some(holes) => Box::new(util::sparse::SkipHoles::new(source, holes, position)),
+		None => Box::new(source)
+	}
+}
 
-		let mut buffer_size: usize = 1024 * 1024 * 100;
-		if let Some(custom_buffer) = parse_byte_string(decode)? {
-			buffer_size = custom_buffer as usize;
+// Reports the disk space saved by leaving the holes of a sparse file unallocated (streams get them as zeros)
+fn report_holes(decode: &Config, write_file: &WriteFile, hole_bytes: u64) {
+	if hole_bytes != 0 && !decode.is_present("STREAM") {
+		silent!({println!("Left \x1b[1;36m{}\x1b[0m bytes of holes in \x1b[1;36m'{}'\x1b[0m unallocated.", hole_bytes, write_file)});
+	}
+}
+
+// Captures the attributes of the input for --preserve, warning about any that can't be read
+fn capture_metadata(read_file: &ReadFile, preserve: &Preserve) -> Result<Metadata, Error> {
+	let (metadata, warnings) = error_exp!(ReadFail, &read_file, Metadata::capture(Path::new(&read_file.to_string()), preserve));
+	for warning in warnings {
+		silent!({println!("\x1b[1;33mWarning: Not preserving an attribute of '\x1b[1;36m{}\x1b[1;33m': {}.\x1b[0m", &read_file, warning)});
+	}
+	Ok(metadata)
+}
+
+// Applies the attributes stored with --preserve to the committed output, warning about any that can't be restored
+fn restore_metadata(read_file: &ReadFile, write_file: &WriteFile, metadata: Option<&Metadata>, preserve: &Preserve) {
+	let metadata = match metadata {
+		Some(metadata) => metadata,
+		None => {
+			silent!({println!("\x1b[1;33mWarning: '\x1b[1;36m{}\x1b[1;33m' has no attributes stored with --preserve.\x1b[0m", &read_file)});
+			return;
 		}
+	};
+	for warning in metadata.restore(Path::new(&write_file.to_string()), preserve) {
+		silent!({println!("\x1b[1;33mWarning: Not restoring an attribute of '\x1b[1;36m{}\x1b[1;33m': {}.\x1b[0m", &write_file, warning)});
+	}
+}
 
-		verbose!({
-			println!(
-				"\n\x1b[1;36mConfiguration:\x1b[1;33m \n\
-				[{}\x1b[1;33m] Verification Mode \n\
-				[{}\x1b[1;33m] Trimming \n\
-				[{}\x1b[1;33m] Buffer Size: \x1b[1;36m{}\x1b[0m\n",
-			check!(decode.is_present("VERIFY")),
-			check!(decode.is_present("TRIM")),
-			check!(decode.is_present("BYTES")), buffer_size,
-			);
-		});
+// Prints the PNGs under dir (in order, without following links to directories) with every wanted tag, skipping files
+// that can't be read or aren't PNGs
+fn find_tagged(dir: &Path, wanted: &[(String, Option<String>)]) {
+	let mut entries: Vec<std::fs::DirEntry> = match std::fs::read_dir(dir) {
+		Ok(entries) => entries.filter_map(|entry| entry.ok()).collect(),
+		Err(_) => return
+	};
+	entries.sort_by_key(|entry| entry.file_name());
+	for entry in entries {
+		let path = entry.path();
+		match entry.file_type() {
+			Ok(file_type) if file_type.is_dir() => find_tagged(&path, wanted),
+			// Leaves out pipes & devices, which could block
+			Ok(_) if path.is_file() => {
+				let found = std::fs::File::open(&path).and_then(|mut file| png_convert::tags::read_tags(&mut file, false));
+				if let Ok(found) = found {
+					if png_convert::tags::matches(&found, wanted) {
+						println!("{}", path.display());
+					}
+				}
+			},
+			_ => ()
+		};
+	}
+}
 
-		if decode.is_present("VERIFY") {
-			verify(&mut read_file)?;	
+// Maps the input into memory with --mmap (None otherwise or when it can't be mapped, so it is read as usual). Off
+// by default: an input truncated while it is mapped kills the process with SIGBUS, for no measurable gain
+fn map_input(mmap: bool, read_file: &ReadFile) -> Option<util::Mmap> {
+	match mmap {
+		true => read_file.map().ok(),
+		false => None
+	}
+}
+
+// Loads the sidecar of --resume, truncating the output to the last checkpoint (fingerprint identifies the options)
+fn load_resume(subcommand: &Config, read_file: &ReadFile, write_file: &mut WriteFile, fingerprint: String) -> Result<Resume, Error> {
+	if subcommand.is_present("STREAM") {
+		error!(UnsupportedResume, "streams");
+	}
+	// Checkpoints are dropped once the input changes
+	let modified = std::fs::metadata(read_file.to_string())
+		.and_then(|metadata| metadata.modified())
+		.map(|modified| modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0))
+		.unwrap_or(0);
+	let fingerprint = format!("{} {} {}", fingerprint, read_file.size, modified);
+	let output = PathBuf::from(write_file.to_string());
+	let sidecar = Resume::sidecar(&output);
+	let resume = match Resume::load(sidecar.clone(), fingerprint) {
+		Ok(resume) => resume,
+		Err(ref e) if e.kind() == io::ErrorKind::InvalidData => error!(ResumeMismatch, sidecar.display()),
+		Err(_) => error!(ReadFail, sidecar.display())
+	};
+
+	if let Some(checkpoint) = &resume.checkpoint {
+		let written = std::fs::metadata(&output).map(|metadata| metadata.len()).unwrap_or(0);
+		if written < checkpoint.output_offset {
+			error!(ResumeMismatch, sidecar.display());
 		}
+		error_exp!(WriteFail, &write_file, write_file.truncate(checkpoint.output_offset));
+		silent!({println!("Resuming \x1b[1;36m'{}'\x1b[0m from byte \x1b[1;36m{}\x1b[0m of \x1b[1;36m'{}'\x1b[0m.", &read_file, checkpoint.input_offset, &write_file)});
+	}
+	Ok(resume)
+}
 
-		let start = Instant::now();
-		error_exp!(
-			Decode,
-			&read_file,
-			png_convert::decode(
-				&mut read_file,
-				&mut write_file,
-				buffer_size
-			)
+// Renders the --banner header describing the input file above the data
+fn render_banner(encode: &Config, title: &str, read_file: &mut ReadFile, options: &png_convert::EncodeOptions) -> Result<png_convert::banner::Banner, Error> {
+	let path = read_file.to_string();
+	let name = match Path::new(&path).file_name() {
+		Some(name) => name.to_string_lossy().to_string(),
+		None => path.clone()
+	};
+	// Dated only by --mtime (or SOURCE_DATE_EPOCH) so touching the input doesn't change the image
+	let date = match options.mtime {
+		Some(seconds) => format!("Encoded {}", util::timestamp::date(seconds)),
+		None => String::from("Encoded with pngifier")
+	};
+	let lines = vec![
+		title.to_string(),
+		format!("{} ({} bytes)", name, read_file.size),
+		date,
+		String::from("Decode with pngifier")
+	];
+
+	let mut digest = None;
+	if encode.is_present("BANNER_QR") {
+		digest = Some(error_exp!(ReadFail, &read_file, png_convert::banner::sha256(read_file)));
+		read_file.reset()?;
+	}
+	let banner = match png_convert::banner::render(&lines, digest.as_deref(), options.width, options.color_type, options.bit_depth) {
+		Some(banner) => banner,
+		None => error!(BannerDoesNotFit, options.width)
+	};
+	if !util::dimensions::is_valid(options.width, options.height + banner.rows as u64) {
+		error!(InvalidDimensions, format!("{}x{}", options.width, options.height + banner.rows as u64));
+	}
+	Ok(banner)
+}
+
+// Hides the input file in the low bits of each channel of a carrier image
+fn carrier_encode_file(encode: &Config, carrier: &str, mut read_file: ReadFile, mut write_file: WriteFile) -> Result<(), Error> {
+	let mut bits: u8 = 1;
+	if let Some(custom_bits) = encode.value_of("BITS") {
+		bits = match custom_bits.parse::<u8>() {
+			Ok(b) if (1..=4).contains(&b) => b,
+			_ => error!(ParseBitsPerChannel, custom_bits)
+		};
+	}
+
+	let carrier_file = error_exp!(ReadFail, carrier, ReadFile::from_string(carrier.to_string()));
+	let mut image = error_exp!(Carrier, carrier, png_convert::Image::read(carrier_file));
+	let format = image_format(encode, &image)?;
+	let capacity = png_convert::carrier::capacity(&image, bits);
+	if read_file.size > capacity {
+		error!(InputDoesNotFit, format!("{} ({} bytes) into the carrier '{}' ({} bytes at {} bits per channel)", &read_file, read_file.size, carrier, capacity, bits));
+	}
+
+	verbose!({
+		println!(
+			"\n\x1b[1;36mConfiguration:\x1b[1;33m \n\
+			[{}\x1b[1;33m] Verification Mode{} \n\
+			[{}\x1b[1;33m] Carrier: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[{}\x1b[1;33m] Bits Per Channel: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[ \x1b[1;33m] Capacity: \x1b[1;36m{} bytes\x1b[0m\n",
+		check!(encode.is_present("VERIFY")), encode.origin("VERIFY"),
+		check!(true), carrier, encode.origin("CARRIER"),
+		check!(encode.source("BITS").is_some()), bits, encode.origin("BITS"),
+		capacity
 		);
-		silent!({println!("Decoded \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, &write_file, start.elapsed())});
+	});
 
-		silent!({
-			if decode.is_present("TRIM") && !decode.is_present("STREAM") {
-				write_file.trim(buffer_size)?
-			}
-		});
+	let start = Instant::now();
+	let mut payload = Vec::with_capacity(read_file.size as usize);
+	error_exp!(ReadFail, &read_file, read_file.read_to_end(&mut payload));
+	png_convert::carrier::embed(&mut image, &payload, bits);
+	if let Some(seconds) = options::mtime(encode)? {
+		image.set_time(seconds);
+	}
+	error_exp!(Encode, &read_file, image.write_as(format, &mut write_file));
+	silent!({println!("Encoded \x1b[1;36m'{}'\x1b[0m into \x1b[1;36m'{}'\x1b[0m as \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, carrier, &write_file, start.elapsed())});
 
-		return Ok(());
+	if encode.is_present("VERIFY") && !encode.is_present("STREAM") {
+		let mut write_file = error_exp!(ReadFail, &write_file, write_file.read());
+		verify(&mut write_file, encode.is_present("MMAP"))?;
+	}
+
+	commit(encode, &mut write_file)?;
+	Ok(())
+}
+
+// Extracts a file hidden with --carrier from the low bits of each channel
+fn carrier_decode_file(decode: &Config, mut read_file: ReadFile, mut write_file: WriteFile) -> Result<(), Error> {
+	let (format, _) = input_format(&mut read_file)?;
+	if decode.is_present("VERIFY") && format == Format::Png {
+		verify(&mut read_file, decode.is_present("MMAP"))?;
+	}
+
+	let limits = options::decode_limits(decode)?;
+	let start = Instant::now();
+	let image = png_convert::container::read_carrier(&mut read_file, &limits);
+	let image = error_exp!(Carrier, &read_file, within_limits(&read_file, image)?);
+	let payload = error_exp!(CarrierPayload, &read_file, png_convert::carrier::extract(&image));
+	error_exp!(CarrierPayload, &read_file, within_limits(&read_file, limits.check_output(payload.len() as u64))?);
+	error_exp!(WriteFail, &write_file, write_file.write_all(&payload).and_then(|_| write_file.flush()));
+	silent!({println!("Decoded \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, &write_file, start.elapsed())});
+
+	commit(decode, &mut write_file)?;
+	Ok(())
+}
+
+// Encodes the input file into flat blocks that survive lossy recompression
+fn robust_encode_file(encode: &Config, mut read_file: ReadFile, mut write_file: WriteFile) -> Result<(), Error> {
+	let mut levels: u8 = 4;
+	if let Some(custom_levels) = encode.value_of("LEVELS") {
+		levels = match custom_levels.parse::<u8>() {
+			Ok(l) if l == 2 || l == 4 => l,
+			_ => error!(ParseLevels, custom_levels)
+		};
+	}
+
+	let (grid_width, grid_height) = png_convert::robust::grid_for(read_file.size, levels);
+	let (width, height) = (grid_width * png_convert::robust::BLOCK_SIZE, grid_height * png_convert::robust::BLOCK_SIZE);
+	let format = options::output_format(encode)?;
+	if !format.supports(0, 8) {
+		error!(UnsupportedFormat, format!("a robust (greyscale) image as {}", format));
+	}
+	if !util::dimensions::is_valid(width as u64, height as u64) || width as u64 > format.max_dimension() || height as u64 > format.max_dimension() {
+		error!(InputDoesNotFit, format!("{} ({} bytes) into a robust {} image", &read_file, read_file.size, format));
+	}
+
+	verbose!({
+		println!(
+			"\n\x1b[1;36mConfiguration:\x1b[1;33m \n\
+			[{}\x1b[1;33m] Verification Mode{} \n\
+			[{}\x1b[1;33m] Robust{} \n\
+			[{}\x1b[1;33m] Levels: \x1b[1;36m{}\x1b[1;33m{} \n\
+			[ \x1b[1;33m] Width: \x1b[1;36m{}px\x1b[1;33m \n\
+			[ \x1b[1;33m] Height: \x1b[1;36m{}px\x1b[0m\n",
+		check!(encode.is_present("VERIFY")), encode.origin("VERIFY"),
+		check!(true), encode.origin("ROBUST"),
+		check!(encode.source("LEVELS").is_some()), levels, encode.origin("LEVELS"),
+		width,
+		height
+		);
+	});
+
+	let start = Instant::now();
+	let mut payload = Vec::with_capacity(read_file.size as usize);
+	error_exp!(ReadFail, &read_file, read_file.read_to_end(&mut payload));
+	let mut image = png_convert::robust::encode(&payload, levels);
+	if let Some(seconds) = options::mtime(encode)? {
+		image.set_time(seconds);
+	}
+	error_exp!(Encode, &read_file, image.write_as(format, &mut write_file));
+	silent!({println!("Encoded \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, &write_file, start.elapsed())});
+
+	if encode.is_present("VERIFY") && !encode.is_present("STREAM") {
+		let mut write_file = error_exp!(ReadFail, &write_file, write_file.read());
+		verify(&mut write_file, encode.is_present("MMAP"))?;
+	}
+
+	commit(encode, &mut write_file)?;
+	Ok(())
+}
+
+// Recovers a file encoded with --robust, correcting damage from lossy recompression
+fn robust_decode_file(decode: &Config, mut read_file: ReadFile, mut write_file: WriteFile) -> Result<(), Error> {
+	let (format, _) = input_format(&mut read_file)?;
+	if decode.is_present("VERIFY") && format == Format::Png {
+		verify(&mut read_file, decode.is_present("MMAP"))?;
+	}
+
+	let limits = options::decode_limits(decode)?;
+	let start = Instant::now();
+	let image = png_convert::container::read_image(&mut read_file, &limits);
+	let image = error_exp!(Robust, &read_file, within_limits(&read_file, image)?);
+	let payload = error_exp!(Robust, &read_file, png_convert::robust::decode(&image));
+	error_exp!(Robust, &read_file, within_limits(&read_file, limits.check_output(payload.len() as u64))?);
+	error_exp!(WriteFail, &write_file, write_file.write_all(&payload).and_then(|_| write_file.flush()));
+	silent!({println!("Decoded \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, &write_file, start.elapsed())});
+
+	commit(decode, &mut write_file)?;
+	Ok(())
+}
+
+// Recovers the pixel rows of a re-hosted image (JPEG, WebP, BMP, TIFF) as the output file
+fn container_decode_file(decode: &Config, format: Format, buffer_size: usize, limits: &util::Limits, mut read_file: ReadFile, mut write_file: WriteFile) -> Result<(), Error> {
+	if decode.is_present("VERIFY") {
+		verbose!({println!("\x1b[1;33mWarning: Skipping verification of the {} image '\x1b[1;36m{}\x1b[1;33m'.\x1b[0m", format, &read_file)});
+	}
+
+	let start = Instant::now();
+	let image = png_convert::container::read_image(&mut read_file, limits);
+	let image = error_exp!(Decode, &read_file, within_limits(&read_file, image)?);
+	error_exp!(Decode, &read_file, within_limits(&read_file, limits.check_output(image.pixels.len() as u64))?);
+	error_exp!(WriteFail, &write_file, write_file.write_all(&image.pixels).and_then(|_| write_file.flush()));
+	silent!({println!("Decoded \x1b[1;36m'{}'\x1b[0m ({}) to \x1b[1;36m'{}'\x1b[0m in \x1b[1;36m{:?}\x1b[0m.", &read_file, format, &write_file, start.elapsed())});
+
+	if decode.is_present("TRIM") && !decode.is_present("STREAM") {
+		write_file.trim(buffer_size)?
+	}
+
+	commit(decode, &mut write_file)?;
+	Ok(())
+}
+
+// Resolves the container a decoded image (ie: a carrier) is written in
+fn image_format(encode: &Config, image: &png_convert::Image) -> Result<png_convert::OutputFormat, Error> {
+	let format = options::output_format(encode)?;
+	if !format.supports(image.color_type, image.bit_depth) {
+		error!(UnsupportedFormat, format!("color type {} at {}-bit in a {} image", image.color_type, image.bit_depth, format));
+	}
+	let max_dimension = format.max_dimension();
+	if image.width as u64 > max_dimension || image.height as u64 > max_dimension {
+		error!(InvalidDimensions, format!("{}x{} for a {} image", image.width, image.height, format));
+	}
+	Ok(format)
+}
+
+// Moves a finished output into place (renaming the file it replaces with --backup, and never replacing one with
+// --no-clobber, even if it was created while the output was written)
+fn commit(subcommand: &Config, write_file: &mut WriteFile) -> Result<(), Error> {
+	let backup = options::backup_suffix(subcommand);
+	let no_clobber = subcommand.is_present("NO_CLOBBER");
+	match write_file.commit(backup.as_deref(), no_clobber) {
+		Err(ref e) if no_clobber && e.kind() == io::ErrorKind::AlreadyExists => error!(NoClobber, &write_file),
+		result => error_exp!(WriteFail, &write_file, result)
+	};
+	Ok(())
+}
+
+// Reports running into a --max-* limit as its own error, leaving any other result to the caller
+fn within_limits<T>(name: &dyn std::fmt::Display, result: io::Result<T>) -> Result<io::Result<T>, Error> {
+	match result.as_ref().err().and_then(util::Exceeded::find) {
+		Some(exceeded) => Err(exceeded.to_error(name)),
+		None => Ok(result)
+	}
+}
+
+// Lists the limits set on decoding for the verbose configuration
+fn describe_limits(limits: &util::Limits) -> String {
+	let mut described = Vec::new();
+	if limits.max_chunk_bytes != util::limits::MAX_CHUNK_LENGTH {
+		described.push(format!("{} bytes per chunk", limits.max_chunk_bytes));
+	}
+	if let Some(max_output_bytes) = limits.max_output_bytes {
+		described.push(format!("{} bytes of output", max_output_bytes));
+	}
+	if let Some((width, height)) = limits.max_dimensions {
+		described.push(format!("{}x{}", width, height));
+	}
+	if let Some(max_ratio) = limits.max_ratio {
+		described.push(format!("{}:1 inflated", max_ratio));
+	}
+	match described.is_empty() {
+		true => String::from("none"),
+		false => described.join(", ")
+	}
+}
+
+// Detects the container of the input file and whether it is lossy
+fn input_format(read_file: &mut ReadFile) -> Result<(Format, bool), Error> {
+	let magic = error_exp!(ReadFail, &read_file, png_convert::container::read_magic(read_file));
+	match Format::detect(&magic) {
+		Some(format) => Ok((format, format.is_lossy(&magic))),
+		None => error!(UnknownFormat, &read_file)
 	}
-	std::process::exit(1)
 }
 
 fn main() {
 	// Ignore default "Error: " boilerplate code
 	if let Err(e) = process() {
+		// Whatever failed was stopped by Ctrl-C or SIGTERM
+		if let Some(code) = signal::exit_code() {
+			eprint!("{:?}", Error::Cancelled);
+			std::process::exit(code);
+		}
 		eprint!("{:?}", e);
 		std::process::exit(1);
 	}
@@ -258,8 +1041,8 @@ fn main() {
 }
 
 // Set verbose levels and enable ANSI escape codes
-fn initialize(subcommand: &clap::ArgMatches) {
-	set_verbose_levels(&subcommand);
+fn initialize(subcommand: &Config) {
+	set_verbose_levels(subcommand);
 	match util::enable_ansi_support() {
 		Ok(_) => (),
 		_ => verbose!({println!("\x1b[1;33mWarning: Unable to enable ANSI support for Windows.\x1b[0m")})
@@ -268,7 +1051,7 @@ fn initialize(subcommand: &clap::ArgMatches) {
 }
 
 // Set respective verbosity levels
-fn set_verbose_levels(subcommand: &clap::ArgMatches) {
+fn set_verbose_levels(subcommand: &Config) {
 	if subcommand.is_present("ACCEPT") {
 		unsafe {
 			util::verbosity::SKIP = true;
@@ -299,76 +1082,20 @@ fn set_verbose_levels(subcommand: &clap::ArgMatches) {
 	}
 }
 
-//Convert string to u64
-fn parse_arg_u64(subcommand: &clap::ArgMatches, option_name: &str, height: bool) -> Result<Option<u64>, Error> {
-	match subcommand.value_of(option_name) {
-		Some(c) => {
-			match c.parse::<u64>() {
-				Ok(c) => {
-					return Ok(Some(c));
-				},
-				_ => {
-					if height {
-						error!(ParseHeight, c);
-					}
-					error!(ParseWidth, c);
-				}
-			};
-		}
-		_ => {
-			return Ok(None);
-		}
-	};
-}
-
-// Convert byte to u64
-fn parse_byte_string(subcommand: &clap::ArgMatches) -> Result<Option<u64>, Error> {
-	match subcommand.value_of("BYTES") {
-		Some(input_str) => {
-		    let t = input_str.clone();
-		    let t = t.to_lowercase();
-		    let mut byte_match = "b";
-		    let mut byte_match_index = input_str.len();
-		    for i in &["gb", "mb", "kb", "b"] {
-		        match t.rfind(i) {
-		            Some(s) => {
-		                byte_match = i;
-		                byte_match_index = s;
-		                break;
-		            }
-		            None => ()
-		        }
-		    }
-		    let num = match t[..byte_match_index].trim().parse::<u64>() {
-		        Ok(n) => n,
-		        Err(_) => error!(ParseBuffer, input_str)
-		    };
-		    match byte_match {
-		        "gb" => Ok(Some(num * 1024 * 1024 * 1024)),
-		        "mb" => Ok(Some(num * 1024 * 1024)),
-		        "kb" => Ok(Some(num * 1024)),
-		        "b" => Ok(Some(num)),
-		        _ => error!(ParseBuffer, input_str)
-		    }
-		},
-		None => Ok(None)
-	}
-}
-
 // Convert input & output choices to respective ReadFile and WriteFile
-fn parse_input_output<'a>(subcommand: &'a clap::ArgMatches, decode: bool) -> Result<(ReadFile, WriteFile), Error>{
+fn parse_input_output(subcommand: &Config, decode: bool) -> Result<(ReadFile, WriteFile), Error>{
 	let input = subcommand.value_of("INPUT").unwrap();
-	let input: &str = &format!("{}", input);
+	let input: &str = &input[..];
 
-	let mut output: &str = &format!("{}.png", input)[..];
+	let mut output: &str = &format!("{}.{}", input, options::output_format(subcommand)?.extension())[..];
 	if decode {
-		match input.rfind(".png") {
-			Some(index) => output = &input[..index],
-			None => ()
-		};
+		if let Some(stripped) = png_convert::container::strip_extension(input).or_else(|| input.rfind(".png").map(|index| &input[..index])) {
+			output = stripped;
+		}
 	}
 
-	if let Some(s) = subcommand.value_of("OUTPUT") {
+	let custom_output = subcommand.value_of("OUTPUT");
+	if let Some(s) = &custom_output {
 		output = s;
 	}
 
@@ -388,12 +1115,22 @@ fn parse_input_output<'a>(subcommand: &'a clap::ArgMatches, decode: bool) -> Res
 		_ => error!(ReadFail, input)
 	};
 
+	// An output with checkpoints is continued instead of overwritten
+	let resuming = subcommand.is_present("RESUME") && Resume::sidecar(Path::new(output)).exists();
 	let write_file = match subcommand.is_present("STREAM") {
 		true => WriteFile::stdout(),
+		false if resuming => WriteFile::append(PathBuf::from(output)),
 		false => {
+			let write_path = Path::new(output);
+			if write_path.exists() && subcommand.is_present("NO_CLOBBER") {
+				error!(NoClobber, output);
+			}
 			skip!({
-				let write_path = Path::new(output);
 				if write_path.exists() {
+					// There is no one to answer when the input is piped or scripted
+					if !io::stdin().is_terminal() {
+						error!(OutputExists, output);
+					}
 					let mut user_input = String::new();
 					print!("\x1b[1;33mWarning: The output file of '\x1b[1;36m{}\x1b[0m\x1b[1;33m' currently exists. Would you like to override it? (\x1b[1;32my\x1b[1;33m/\x1b[1;31mN\x1b[1;33m): \x1b[0m", output);
 					io::stdout().flush().expect("Unable to Flush to stdout.");
@@ -405,7 +1142,11 @@ fn parse_input_output<'a>(subcommand: &'a clap::ArgMatches, decode: bool) -> Res
 					};
 				}
 			});
-			WriteFile::from_string(output.to_string())
+			// Checkpoints need the partial output in place, everything else is renamed into place once complete
+			match subcommand.is_present("RESUME") {
+				true => WriteFile::from_string(output.to_string()),
+				false => WriteFile::atomic(PathBuf::from(output))
+			}
 		}
 	};
 
@@ -417,11 +1158,11 @@ fn parse_input_output<'a>(subcommand: &'a clap::ArgMatches, decode: bool) -> Res
 	Ok((read_file, write_file))
 }
 
-// Verifies output/input file as a PNG
-fn verify(read_file: &mut ReadFile) -> Result<(), Error> {
+// Verifies output/input file as a PNG (mapping it into memory with --mmap)
+fn verify(read_file: &mut ReadFile, mmap: bool) -> Result<(), Error> {
 	read_file.reset()?;
 	let start = Instant::now();
-	read_file.verify_png()?;
+	read_file.verify_png(mmap)?;
 	silent!({println!("Verified '\x1b[1;36m{}\x1b[0m' in \x1b[1;36m{:?}\x1b[0m.", &read_file, start.elapsed())});
 	read_file.reset()?;
 	Ok(())
@@ -0,0 +1,461 @@
+use crate::util;
+use crate::png_convert::{banner, resume, tags, EncodeOptions, OutputFormat};
+use crate::png_convert::payload::Container;
+use crate::png_convert::polyglot::Polyglot;
+use util::{Config, Source, Error, Limits, Preserve};
+use util::color_type::*;
+use util::{dimensions, timestamp};
+
+use std::env;
+
+// Resolves the image layout used to encode max_bytes of the named input
+pub fn encode_options(encode: &Config, name: &str, max_bytes: u64) -> Result<EncodeOptions, Error> {
+	if container(encode)? == Container::Chunk {
+		return chunk_options(encode, max_bytes);
+	}
+
+	let mut color_type: u8 = 2;
+	let mut bit_depth: u8 = 8;
+	let mut bytes_per_px = total_bytes(color_type, bit_depth) as u64;
+
+	if let Some(custom_depth) = encode.value_of("DEPTH") {
+		let custom_depth = &custom_depth[..];
+		match custom_depth.parse::<u8>() {
+			Ok(custom_depth) => {
+				if !(custom_depth == 8 || custom_depth == 16) {
+					error!(ParseBitDepth, custom_depth);
+				}
+				bit_depth = custom_depth;
+				bytes_per_px = total_bytes(color_type, bit_depth) as u64;
+			},
+			_ => error!(ParseBitDepth, custom_depth)
+		};
+	}
+
+	if let Some(custom_color_type) = encode.value_of("COLOR_TYPE") {
+		let custom_color_type = &custom_color_type[..];
+		match custom_color_type.parse::<u8>() {
+			Ok(custom_color_type) => {
+				if !(type_exists(custom_color_type)) {
+					error!(ParseColorType, custom_color_type);
+				}
+				color_type = custom_color_type;
+				bytes_per_px = total_bytes(color_type, bit_depth) as u64;
+			},
+			_ => {
+				match type_str_translate(custom_color_type) {
+					Some(custom_color_type) => {
+						let custom_color_type = *custom_color_type;
+						if !(type_exists(custom_color_type)) {
+							error!(ParseColorType, custom_color_type);
+						}
+						color_type = custom_color_type;
+						bytes_per_px = total_bytes(color_type, bit_depth) as u64;
+					},
+					_ => error!(ParseColorType, custom_color_type)
+				}
+			}
+		};
+	}
+
+	let mut width: u64 = std::cmp::max(1, ((max_bytes / bytes_per_px) as f64).sqrt() as u64);
+	// A square root of the input can't overflow a row
+	let mut height: u64 = dimensions::rows_for(max_bytes, width, bytes_per_px).unwrap();
+
+	// The frame size only sets the dimensions of animations
+	let apng = encode.is_present("APNG");
+	let dimension_options: &[&str] = match apng {
+		true => &["WIDTH", "HEIGHT", "ASPECT", "FIT", "FRAME_SIZE"],
+		false => &["WIDTH", "HEIGHT", "ASPECT", "FIT"]
+	};
+
+	// Dimension options given as flags take precedence over the ones from config files
+	let mut defined: Vec<&str> = dimension_options.iter()
+		.filter(|name| matches!(encode.source(name), Some(Source::Flag)))
+		.copied()
+		.collect();
+	if defined.is_empty() {
+		defined = dimension_options.iter()
+			.filter(|name| encode.source(name).is_some())
+			.copied()
+			.collect();
+	}
+	if defined.len() > 1 {
+		error!(ConflictingDimensions, defined.join(", ").to_lowercase().replace('_', "-"));
+	}
+	if apng && defined.first() != Some(&"FRAME_SIZE") {
+		error!(MissingFrameSize);
+	}
+
+	let mut frames: Option<u64> = None;
+
+	if defined.is_empty() && encode.value_of("BANNER").is_some() && width < banner::MIN_WIDTH {
+		width = banner::MIN_WIDTH;
+		height = dimensions::rows_for(max_bytes, width, bytes_per_px).unwrap();
+	}
+
+	match defined.first() {
+		Some(&"WIDTH") => {
+			width = parse_arg_u64(encode, "WIDTH", false)?.unwrap();
+			if width == 0 {
+				error!(ParseWidth, width);
+			}
+			height = match dimensions::rows_for(max_bytes, width, bytes_per_px) {
+				Some(height) if width <= dimensions::MAX_DIMENSION => height,
+				_ => error!(InvalidDimensions, format!("{}px wide", width))
+			};
+		},
+		Some(&"HEIGHT") => {
+			height = parse_arg_u64(encode, "HEIGHT", true)?.unwrap();
+			if height == 0 {
+				error!(ParseHeight, height);
+			}
+			width = match dimensions::columns_for(max_bytes, height, bytes_per_px) {
+				Some(width) if height <= dimensions::MAX_DIMENSION => width,
+				_ => error!(InvalidDimensions, format!("{}px high", height))
+			};
+		},
+		Some(&"ASPECT") => {
+			let aspect = encode.value_of("ASPECT").unwrap();
+			let ratio = match dimensions::parse_pair(&aspect, &[':', 'x', '/']) {
+				Some(ratio) => ratio,
+				None => error!(ParseAspect, aspect)
+			};
+			let (w, h) = match dimensions::fit_aspect(max_bytes, bytes_per_px, ratio) {
+				Some(size) => size,
+				None => error!(InvalidDimensions, format!("{} bytes at an aspect ratio of {}:{}", max_bytes, ratio.0, ratio.1))
+			};
+			width = w;
+			height = h;
+		},
+		Some(&"FIT") => {
+			let fit = encode.value_of("FIT").unwrap();
+			let (w, h) = match dimensions::preset(&fit).or_else(|| dimensions::parse_pair(&fit, &['x'])) {
+				Some(size) => size,
+				None => error!(ParseFit, fit)
+			};
+			width = w;
+			height = h;
+			let capacity = (width as u128) * (height as u128) * (bytes_per_px as u128);
+			if (max_bytes as u128) > capacity {
+				error!(InputDoesNotFit, format!("{} ({} bytes) into {}x{} ({} bytes)", name, max_bytes, width, height, capacity));
+			}
+		},
+		Some(&"FRAME_SIZE") => {
+			let frame_size = encode.value_of("FRAME_SIZE").unwrap();
+			let (w, h) = match dimensions::preset(&frame_size).or_else(|| dimensions::parse_pair(&frame_size, &['x'])) {
+				Some(size) => size,
+				None => error!(ParseFrameSize, frame_size)
+			};
+			width = w;
+			height = h;
+			let frame_bytes = std::cmp::max(1, (width as u128) * (height as u128) * (bytes_per_px as u128));
+			let count = std::cmp::max(1, (max_bytes as u128).div_ceil(frame_bytes));
+			if count > i32::MAX as u128 / 2 {
+				error!(InputDoesNotFit, format!("{} ({} bytes) into {}x{} frames", name, max_bytes, width, height));
+			}
+			frames = Some(count as u64);
+		},
+		_ => ()
+	}
+
+	if !dimensions::is_valid(width, height) {
+		error!(InvalidDimensions, format!("{}x{}", width, height));
+	}
+
+	let format = output_format(encode)?;
+	if apng && format != OutputFormat::Png {
+		error!(UnsupportedFormat, format!("animations in a {} image", format));
+	}
+	if encode.value_of("BANNER").is_some() && (apng || format != OutputFormat::Png) {
+		error!(UnsupportedBanner, if apng { String::from("animations") } else { format!("{} images", format) });
+	}
+	if !format.supports(color_type, bit_depth) {
+		error!(UnsupportedFormat, format!("color type {} at {}-bit in a {} image", color_type, bit_depth, format));
+	}
+	let max_dimension = format.max_dimension();
+	if width > max_dimension || height > max_dimension || width as u128 * height as u128 * bytes_per_px as u128 > format.max_size() as u128 {
+		error!(InputDoesNotFit, format!("{} ({} bytes) into a {}x{} {} image (at most {}x{} pixels)", name, max_bytes, width, height, format, max_dimension, max_dimension));
+	}
+	let chunk_size: u64 = width * bytes_per_px;
+	let mut buffer_size = chunk_size;
+	if let Some(custom_buffer) = parse_byte_string(encode, "BYTES")? {
+		buffer_size = custom_buffer;
+	}
+
+	Ok(EncodeOptions {
+		width: width,
+		height: height,
+		chunk_size: chunk_size,
+		max_bytes: max_bytes,
+		buffer_size: buffer_size as usize,
+		idat_size: idat_size(encode)?,
+		bit_depth: bit_depth,
+		color_type: color_type,
+		trim: encode.is_present("TRIM"),
+		format: format,
+		container: Container::Pixels,
+		frames: frames,
+		banner: None,
+		mtime: mtime(encode)?,
+		holes: None,
+		metadata: None,
+		tags: tags(encode)?,
+		zip: None
+	})
+}
+
+// Layout of --container chunk: a 1x1 greyscale placeholder next to the payload (dimension options don't apply)
+fn chunk_options(encode: &Config, max_bytes: u64) -> Result<EncodeOptions, Error> {
+	let format = output_format(encode)?;
+	if format != OutputFormat::Png {
+		error!(UnsupportedFormat, format!("a chunk container in a {} image", format));
+	}
+	if encode.is_present("APNG") {
+		error!(UnsupportedFormat, "a chunk container in an animation");
+	}
+	if encode.value_of("BANNER").is_some() {
+		error!(UnsupportedBanner, "chunk containers");
+	}
+	let mut buffer_size = util::IDAT_SIZE as u64;
+	if let Some(custom_buffer) = parse_byte_string(encode, "BYTES")? {
+		buffer_size = custom_buffer;
+	}
+
+	Ok(EncodeOptions {
+		width: 1,
+		height: 1,
+		chunk_size: 1,
+		max_bytes: max_bytes,
+		buffer_size: buffer_size as usize,
+		idat_size: idat_size(encode)?,
+		bit_depth: 8,
+		color_type: 0,
+		trim: encode.is_present("TRIM"),
+		format: format,
+		container: Container::Chunk,
+		frames: None,
+		banner: None,
+		mtime: mtime(encode)?,
+		holes: None,
+		metadata: None,
+		tags: tags(encode)?,
+		zip: None
+	})
+}
+
+// Resolves where --container puts the bytes of the file (the pixels by default)
+pub fn container(encode: &Config) -> Result<Container, Error> {
+	match encode.value_of("CONTAINER") {
+		Some(name) => match Container::parse(&name) {
+			Some(container) => Ok(container),
+			None => error!(ParseContainer, name)
+		},
+		None => Ok(Container::Pixels)
+	}
+}
+
+// Resolves the archive --polyglot makes the PNG double as (none by default)
+pub fn polyglot(encode: &Config) -> Result<Option<Polyglot>, Error> {
+	match encode.value_of("POLYGLOT") {
+		Some(name) => match Polyglot::parse(&name) {
+			Some(polyglot) => Ok(Some(polyglot)),
+			None => error!(ParsePolyglot, name)
+		},
+		None => Ok(None)
+	}
+}
+
+// Resolves the size of the data of each IDAT (or payload) chunk
+fn idat_size(encode: &Config) -> Result<usize, Error> {
+	let mut idat_size = util::IDAT_SIZE as u64;
+	if let Some(custom_idat_size) = parse_byte_string(encode, "IDAT_SIZE")? {
+		// Chunk lengths are limited to 2^31 - 1 bytes
+		if custom_idat_size == 0 || custom_idat_size > i32::MAX as u64 {
+			error!(ParseIdatSize, encode.value_of("IDAT_SIZE").unwrap());
+		}
+		idat_size = custom_idat_size;
+	}
+	Ok(idat_size as usize)
+}
+
+// Resolves the key=value pairs of --tag
+pub fn tags(encode: &Config) -> Result<Vec<(String, String)>, Error> {
+	let mut tags = Vec::new();
+	for value in encode.values_of("TAG") {
+		match tags::parse(&value) {
+			Some(tag) => tags.push(tag),
+			None => error!(ParseTag, value)
+		};
+	}
+	Ok(tags)
+}
+
+// Resolves the time stored in tIME chunks from --mtime or SOURCE_DATE_EPOCH (none by default)
+pub fn mtime(encode: &Config) -> Result<Option<u64>, Error> {
+	let value = match encode.value_of("MTIME") {
+		Some(value) => value,
+		None => match env::var("SOURCE_DATE_EPOCH") {
+			Ok(value) => value,
+			Err(_) => return Ok(None)
+		}
+	};
+	if value == "now" {
+		return Ok(Some(timestamp::now()));
+	}
+	match value.trim().parse::<u64>() {
+		Ok(seconds) => Ok(Some(seconds)),
+		_ => error!(ParseMtime, value)
+	}
+}
+
+// Resolves the container from --format or the extension of the output file
+pub fn output_format(encode: &Config) -> Result<OutputFormat, Error> {
+	if let Some(name) = encode.value_of("FORMAT") {
+		return match OutputFormat::parse(&name) {
+			Some(format) => Ok(format),
+			None => error!(ParseFormat, name)
+		};
+	}
+	Ok(encode.value_of("OUTPUT")
+		.and_then(|output| OutputFormat::from_path(&output))
+		.unwrap_or(OutputFormat::Png))
+}
+
+// Resolves the suffix --backup renames an existing output with (None keeps no backup)
+pub fn backup_suffix(subcommand: &Config) -> Option<String> {
+	if !subcommand.is_present("BACKUP") {
+		return None;
+	}
+	match subcommand.value_of("BACKUP") {
+		Some(ref suffix) if suffix != "true" => Some(suffix.clone()),
+		_ => Some(String::from("~"))
+	}
+}
+
+// Resolves the attributes of --preserve (everything when none are listed)
+pub fn preserve(subcommand: &Config) -> Result<Option<Preserve>, Error> {
+	if !subcommand.is_present("PRESERVE") {
+		return Ok(None);
+	}
+	match subcommand.value_of("PRESERVE") {
+		Some(ref value) if value != "true" => match Preserve::parse(value) {
+			Some(preserve) => Ok(Some(preserve)),
+			None => error!(ParsePreserve, value)
+		},
+		_ => Ok(Some(Preserve::all()))
+	}
+}
+
+// Resolves the buffer size used when decoding
+pub fn decode_buffer_size(decode: &Config) -> Result<usize, Error> {
+	let mut buffer_size: usize = 1024 * 1024 * 100;
+	if let Some(custom_buffer) = parse_byte_string(decode, "BYTES")? {
+		buffer_size = custom_buffer as usize;
+	}
+	Ok(buffer_size)
+}
+
+// Resolves the --max-* limits on decoding untrusted images (unset limits leave only the chunk length of the spec)
+pub fn decode_limits(decode: &Config) -> Result<Limits, Error> {
+	let mut limits = Limits::default();
+	match parse_byte_string(decode, "MAX_CHUNK_BYTES")? {
+		Some(0) => error!(ParseLimit, decode.value_of("MAX_CHUNK_BYTES").unwrap()),
+		Some(max_chunk_bytes) => limits.max_chunk_bytes = std::cmp::min(max_chunk_bytes, util::limits::MAX_CHUNK_LENGTH),
+		None => ()
+	};
+	limits.max_output_bytes = match parse_byte_string(decode, "MAX_OUTPUT_BYTES")? {
+		Some(0) => error!(ParseLimit, decode.value_of("MAX_OUTPUT_BYTES").unwrap()),
+		max_output_bytes => max_output_bytes
+	};
+	// Only given to serve, for the bodies of encode requests
+	limits.max_input_bytes = match parse_byte_string(decode, "MAX_INPUT_BYTES")? {
+		Some(0) => error!(ParseLimit, decode.value_of("MAX_INPUT_BYTES").unwrap()),
+		max_input_bytes => max_input_bytes
+	};
+	if let Some(max_dimensions) = decode.value_of("MAX_DIMENSIONS") {
+		limits.max_dimensions = match dimensions::preset(&max_dimensions).or_else(|| dimensions::parse_pair(&max_dimensions, &['x'])) {
+			Some(pair) => Some(pair),
+			None => error!(ParseLimit, max_dimensions)
+		};
+	}
+	if let Some(max_ratio) = decode.value_of("MAX_RATIO") {
+		limits.max_ratio = match max_ratio.trim_end_matches(":1").parse::<u64>() {
+			Ok(ratio) if ratio > 0 => Some(ratio),
+			_ => error!(ParseLimit, max_ratio)
+		};
+	}
+	Ok(limits)
+}
+
+//Convert string to u64
+pub fn parse_arg_u64(subcommand: &Config, option_name: &str, height: bool) -> Result<Option<u64>, Error> {
+	match subcommand.value_of(option_name) {
+		Some(c) => {
+			match c.parse::<u64>() {
+				Ok(c) => Ok(Some(c)),
+				_ => {
+					if height {
+						error!(ParseHeight, c);
+					}
+					error!(ParseWidth, c);
+				}
+			}
+		}
+		_ => Ok(None)
+	}
+}
+
+// Convert byte to u64
+pub fn parse_byte_string(subcommand: &Config, option_name: &str) -> Result<Option<u64>, Error> {
+	match subcommand.value_of(option_name) {
+		Some(input_str) => {
+		    let input_str = &input_str[..];
+		    let t = input_str.to_lowercase();
+		    let mut byte_match = "b";
+		    let mut byte_match_index = input_str.len();
+		    for i in &["gb", "mb", "kb", "b"] {
+		        if let Some(s) = t.rfind(i) {
+		            byte_match = i;
+		            byte_match_index = s;
+		            break;
+		        }
+		    }
+		    let num = match t[..byte_match_index].trim().parse::<u64>() {
+		        Ok(n) => n,
+		        Err(_) => return parse_byte_error(option_name, input_str)
+		    };
+		    let unit: u64 = match byte_match {
+		        "gb" => 1024 * 1024 * 1024,
+		        "mb" => 1024 * 1024,
+		        "kb" => 1024,
+		        "b" => 1,
+		        _ => return parse_byte_error(option_name, input_str)
+		    };
+		    match num.checked_mul(unit) {
+		        Some(bytes) => Ok(Some(bytes)),
+		        None => parse_byte_error(option_name, input_str)
+		    }
+		},
+		None => Ok(None)
+	}
+}
+
+// Errors name the option the byte string was given to
+fn parse_byte_error(option_name: &str, input_str: &str) -> Result<Option<u64>, Error> {
+	match option_name {
+		"IDAT_SIZE" => error!(ParseIdatSize, input_str),
+		"CHECKPOINT_INTERVAL" => error!(ParseCheckpointInterval, input_str),
+		"MAX_CHUNK_BYTES" | "MAX_OUTPUT_BYTES" | "MAX_INPUT_BYTES" => error!(ParseLimit, input_str),
+		_ => error!(ParseBuffer, input_str)
+	};
+}
+
+// Resolves the bytes of input encoded between checkpoints of --resume
+pub fn checkpoint_interval(encode: &Config) -> Result<u64, Error> {
+	match parse_byte_string(encode, "CHECKPOINT_INTERVAL")? {
+		Some(0) => error!(ParseCheckpointInterval, encode.value_of("CHECKPOINT_INTERVAL").unwrap()),
+		Some(interval) => Ok(interval),
+		None => Ok(resume::CHECKPOINT_INTERVAL)
+	}
+}
@@ -1,5 +1,29 @@
-pub use self::decode::decode;
+pub use self::decode::{decode, decode_resumable, read_holes, read_metadata, Decoder, Event};
 mod decode;
 
-pub use self::encode::encode;
-mod encode;
\ No newline at end of file
+pub use self::encode::{encode, encode_resumable, EncodeOptions, Encoder};
+mod encode;
+
+pub use self::sink::OutputFormat;
+pub mod sink;
+
+pub use self::inspect::{inspect, inspect_bytes};
+mod inspect;
+
+pub use self::lint::{lint, Lint};
+pub mod lint;
+
+pub use self::image::Image;
+mod image;
+mod filter;
+
+pub mod banner;
+pub mod carrier;
+pub mod container;
+pub mod payload;
+pub mod polyglot;
+pub mod resume;
+pub mod robust;
+pub mod tags;
+#[cfg(feature = "tokio")]
+pub mod async_io;
\ No newline at end of file
@@ -0,0 +1,518 @@
+extern crate tokio;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+
+use crate::util::{self, Limits, WriteFile};
+use crate::util::sparse::Skip;
+use crate::png_convert::{sink, Decoder, EncodeOptions, Encoder};
+
+// Output of an encoder or decoder waiting to be handed on (nothing new is produced until it is, which is what
+// applies backpressure to the input)
+struct Pending {
+	buffer: Arc<Mutex<Vec<u8>>>,
+	// Bytes of the buffer already handed on
+	position: usize,
+	// Holes of a sparse file left by a decoder, as the offset in the buffer they come before & the zeros left of them
+	// (handed on a slice at a time instead of filling the buffer)
+	holes: VecDeque<(usize, u64)>
+}
+
+impl Pending {
+	fn new() -> Self {
+		Self {
+			buffer: Arc::new(Mutex::new(Vec::new())),
+			position: 0,
+			holes: VecDeque::new()
+		}
+	}
+
+	// Pushes input into decoder, leaving what it decodes pending
+	fn decode(&mut self, decoder: &mut Decoder, input: &[u8]) -> io::Result<()> {
+		let mut buffer = lock(&self.buffer)?;
+		decoder.push_sparse(input, &mut Unpacked {
+			buffer: &mut buffer,
+			holes: &mut self.holes
+		})
+	}
+
+	// Copies as much as fits into buf, returning false if nothing is pending
+	fn read(&mut self, buf: &mut ReadBuf) -> io::Result<bool> {
+		let mut buffer = lock(&self.buffer)?;
+		let end = match self.holes.front_mut() {
+			Some((offset, zeros)) if *offset == self.position => {
+				let size = std::cmp::min(buf.remaining() as u64, *zeros) as usize;
+				buf.initialize_unfilled_to(size).fill(0);
+				buf.advance(size);
+				*zeros -= size as u64;
+				if *zeros == 0 {
+					self.holes.pop_front();
+				}
+				return Ok(true);
+			},
+			Some((offset, _)) => *offset,
+			None => buffer.len()
+		};
+		if self.position == end {
+			return Ok(false);
+		}
+		let size = std::cmp::min(buf.remaining(), end - self.position);
+		buf.put_slice(&buffer[self.position..self.position + size]);
+		self.position += size;
+		if self.position == buffer.len() && self.holes.is_empty() {
+			buffer.clear();
+			self.position = 0;
+		}
+		Ok(true)
+	}
+
+	// Writes everything pending to destination
+	fn poll_write<W: AsyncWrite + Unpin>(&mut self, destination: &mut W, cx: &mut Context) -> Poll<io::Result<()>> {
+		let mut buffer = lock(&self.buffer)?;
+		loop {
+			let (data, hole) = match self.holes.front() {
+				Some(&(offset, zeros)) if offset == self.position => (&ZEROS[..std::cmp::min(zeros, ZEROS.len() as u64) as usize], true),
+				Some(&(offset, _)) => (&buffer[self.position..offset], false),
+				None if self.position < buffer.len() => (&buffer[self.position..], false),
+				None => break
+			};
+			let written = match Pin::new(&mut *destination).poll_write(cx, data) {
+				Poll::Ready(result) => result?,
+				Poll::Pending => return Poll::Pending
+			};
+			if written == 0 {
+				return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero)));
+			}
+			match (hole, self.holes.front_mut()) {
+				(true, Some((_, zeros))) if *zeros == written as u64 => {
+					self.holes.pop_front();
+				},
+				(true, Some((_, zeros))) => *zeros -= written as u64,
+				_ => self.position += written
+			};
+		}
+		buffer.clear();
+		self.position = 0;
+		Poll::Ready(Ok(()))
+	}
+}
+
+// Output of a decoder that records the holes of a sparse file instead of writing their zeros
+struct Unpacked<'a> {
+	buffer: &'a mut Vec<u8>,
+	holes: &'a mut VecDeque<(usize, u64)>
+}
+
+impl<'a> Write for Unpacked<'a> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.buffer.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl<'a> Skip for Unpacked<'a> {
+	fn skip(&mut self, length: u64) -> io::Result<()> {
+		match self.holes.back_mut() {
+			Some((offset, zeros)) if *offset == self.buffer.len() => *zeros += length,
+			_ => self.holes.push_back((self.buffer.len(), length))
+		};
+		Ok(())
+	}
+}
+
+// Most of the PNG pushed into a decoder at once, as a step inflates to at most about 1mb (deflate stops at 1032:1,
+// and holes are left pending as counts of zeros)
+static DECODE_STEP: usize = 1024;
+// Zeros of holes written to a destination at a time
+static ZEROS: [u8; 16 * 1024] = [0u8; 16 * 1024];
+
+fn lock(buffer: &Mutex<Vec<u8>>) -> io::Result<MutexGuard<'_, Vec<u8>>> {
+	buffer.lock().map_err(|_| io::Error::other("poisoned output buffer"))
+}
+
+// Reads a file from source up to the end of what options can hold, encoding it into the image read from this
+pub struct EncodeReader<R: AsyncRead + Unpin> {
+	source: R,
+	encoder: Option<Encoder<'static>>,
+	pending: Pending,
+	buffer: Vec<u8>
+}
+
+impl<R: AsyncRead + Unpin> EncodeReader<R> {
+	pub fn new(source: R, options: &EncodeOptions) -> io::Result<Self> {
+		let pending = Pending::new();
+		let write_file = WriteFile::memory(pending.buffer.clone());
+		Ok(Self {
+			source: source,
+			encoder: Some(Encoder::new(sink::new(options.format, write_file), options)?),
+			pending: pending,
+			buffer: vec![0u8; std::cmp::max(1, options.buffer_size)]
+		})
+	}
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for EncodeReader<R> {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		loop {
+			if this.pending.read(buf)? {
+				return Poll::Ready(Ok(()));
+			}
+			let encoder = match this.encoder.as_mut() {
+				Some(encoder) => encoder,
+				None => return Poll::Ready(Ok(()))
+			};
+			let read_size = std::cmp::min(this.buffer.len() as u64, encoder.remaining()) as usize;
+			let mut input = ReadBuf::new(&mut this.buffer[..read_size]);
+			if read_size != 0 {
+				match Pin::new(&mut this.source).poll_read(cx, &mut input) {
+					Poll::Ready(result) => result?,
+					Poll::Pending => return Poll::Pending
+				};
+			}
+			match input.filled().len() {
+				0 => this.encoder.take().unwrap().finish()?,
+				size => {
+					encoder.push(&this.buffer[..size])?;
+				}
+			};
+		}
+	}
+}
+
+// Encodes a file written to this into an image written to destination (shutting down finishes the image)
+pub struct EncodeWriter<W: AsyncWrite + Unpin> {
+	destination: W,
+	encoder: Option<Encoder<'static>>,
+	pending: Pending,
+	buffer_size: usize
+}
+
+impl<W: AsyncWrite + Unpin> EncodeWriter<W> {
+	pub fn new(destination: W, options: &EncodeOptions) -> io::Result<Self> {
+		let pending = Pending::new();
+		let write_file = WriteFile::memory(pending.buffer.clone());
+		Ok(Self {
+			destination: destination,
+			encoder: Some(Encoder::new(sink::new(options.format, write_file), options)?),
+			pending: pending,
+			buffer_size: std::cmp::max(1, options.buffer_size)
+		})
+	}
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for EncodeWriter<W> {
+	// Takes nothing once the image is full
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context, data: &[u8]) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		if this.pending.poll_write(&mut this.destination, cx)?.is_pending() {
+			return Poll::Pending;
+		}
+		match this.encoder.as_mut() {
+			Some(encoder) => Poll::Ready(encoder.push(&data[..std::cmp::min(data.len(), this.buffer_size)])),
+			None => Poll::Ready(Err(io::Error::other("the image is already finished")))
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		if this.pending.poll_write(&mut this.destination, cx)?.is_pending() {
+			return Poll::Pending;
+		}
+		Pin::new(&mut this.destination).poll_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		if let Some(encoder) = this.encoder.take() {
+			encoder.finish()?;
+		}
+		if this.pending.poll_write(&mut this.destination, cx)?.is_pending() {
+			return Poll::Pending;
+		}
+		Pin::new(&mut this.destination).poll_shutdown(cx)
+	}
+}
+
+// Reads a PNG from source, decoding it into the file read from this
+pub struct DecodeReader<R: AsyncRead + Unpin> {
+	source: R,
+	decoder: Decoder,
+	pending: Pending,
+	buffer: Vec<u8>,
+	// Part of the buffer read from source but not pushed yet
+	start: usize,
+	end: usize
+}
+
+impl<R: AsyncRead + Unpin> DecodeReader<R> {
+	pub fn new(source: R, buffer_size: usize) -> Self {
+		Self::with_limits(source, buffer_size, Limits::default())
+	}
+
+	// Fails reads as soon as the PNG runs into any of limits (ie: uploaded by users)
+	pub fn with_limits(source: R, buffer_size: usize, limits: Limits) -> Self {
+		Self {
+			source: source,
+			decoder: Decoder::with_limits(buffer_size, limits),
+			pending: Pending::new(),
+			// buffer_size limits the inflated data, the input only needs about a chunk at a time
+			buffer: vec![0u8; std::cmp::max(1, std::cmp::min(buffer_size, util::IDAT_SIZE))],
+			start: 0,
+			end: 0
+		}
+	}
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecodeReader<R> {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		loop {
+			if this.pending.read(buf)? {
+				return Poll::Ready(Ok(()));
+			}
+			if this.decoder.ended() {
+				return Poll::Ready(Ok(()));
+			}
+			// Source is only read once everything read before was decoded & handed on
+			if this.start == this.end {
+				let mut input = ReadBuf::new(&mut this.buffer);
+				match Pin::new(&mut this.source).poll_read(cx, &mut input) {
+					Poll::Ready(result) => result?,
+					Poll::Pending => return Poll::Pending
+				};
+				let size = input.filled().len();
+				if size == 0 {
+					// Fails unless the end of the PNG was reached
+					this.decoder.finish()?;
+				}
+				this.start = 0;
+				this.end = size;
+			}
+			let size = std::cmp::min(this.end - this.start, DECODE_STEP);
+			this.pending.decode(&mut this.decoder, &this.buffer[this.start..this.start + size])?;
+			this.start += size;
+		}
+	}
+}
+
+// Decodes a PNG written to this into the file written to destination (anything after the end of the PNG is
+// ignored & shutting down fails unless it was reached)
+pub struct DecodeWriter<W: AsyncWrite + Unpin> {
+	destination: W,
+	decoder: Decoder,
+	pending: Pending,
+	// Most of the PNG taken by a write
+	step: usize
+}
+
+impl<W: AsyncWrite + Unpin> DecodeWriter<W> {
+	pub fn new(destination: W, buffer_size: usize) -> Self {
+		Self::with_limits(destination, buffer_size, Limits::default())
+	}
+
+	// Fails writes as soon as the PNG runs into any of limits
+	pub fn with_limits(destination: W, buffer_size: usize, limits: Limits) -> Self {
+		Self {
+			destination: destination,
+			decoder: Decoder::with_limits(buffer_size, limits),
+			pending: Pending::new(),
+			step: std::cmp::max(1, std::cmp::min(buffer_size, DECODE_STEP))
+		}
+	}
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for DecodeWriter<W> {
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context, data: &[u8]) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		if this.pending.poll_write(&mut this.destination, cx)?.is_pending() {
+			return Poll::Pending;
+		}
+		let size = std::cmp::min(data.len(), this.step);
+		this.pending.decode(&mut this.decoder, &data[..size])?;
+		Poll::Ready(Ok(size))
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		if this.pending.poll_write(&mut this.destination, cx)?.is_pending() {
+			return Poll::Pending;
+		}
+		Pin::new(&mut this.destination).poll_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		this.decoder.finish()?;
+		if this.pending.poll_write(&mut this.destination, cx)?.is_pending() {
+			return Poll::Pending;
+		}
+		Pin::new(&mut this.destination).poll_shutdown(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::task::Waker;
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+	use crate::png_convert::encode;
+	use crate::util::{Hole, HoleMap};
+	use crate::util::testing::bytes;
+
+	// Inflating a step can't outgrow this (a step is at most DECODE_STEP bytes of deflate at 1032:1)
+	static MAX_PENDING: usize = DECODE_STEP * 1032;
+
+	// Image of data encoded the blocking way
+	fn png(data: &[u8]) -> Vec<u8> {
+		sparse_png(data, None)
+	}
+
+	// Image of the data of a file with holes (which the data is without)
+	fn sparse_png(data: &[u8], holes: Option<HoleMap>) -> Vec<u8> {
+		let buffer = Arc::new(Mutex::new(Vec::new()));
+		let mut options = EncodeOptions::for_size(data.len() as u64);
+		options.holes = holes;
+		encode(data, sink::new(options.format, WriteFile::memory(buffer.clone())), &options).unwrap();
+		let png = lock(&buffer).unwrap().clone();
+		png
+	}
+
+	fn pending_len(pending: &Pending) -> usize {
+		lock(&pending.buffer).unwrap().len() - pending.position
+	}
+
+	// Destination that never takes anything, like a client that stopped reading
+	struct Stalled;
+
+	impl AsyncWrite for Stalled {
+		fn poll_write(self: Pin<&mut Self>, _cx: &mut Context, _data: &[u8]) -> Poll<io::Result<usize>> {
+			Poll::Pending
+		}
+
+		fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+			Poll::Pending
+		}
+
+		fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+			Poll::Pending
+		}
+	}
+
+	#[tokio::test]
+	async fn readers_round_trip() {
+		let data = bytes(39, 100_000);
+		let options = EncodeOptions::for_size(data.len() as u64);
+		let mut image = Vec::new();
+		EncodeReader::new(&data[..], &options).unwrap().read_to_end(&mut image).await.unwrap();
+		assert!(image == png(&data), "the image differs from a blocking encode");
+
+		let mut decoded = Vec::new();
+		DecodeReader::new(&image[..], 4096).read_to_end(&mut decoded).await.unwrap();
+		assert!(decoded[..data.len()] == *data);
+	}
+
+	#[tokio::test]
+	async fn writers_round_trip() {
+		let data = bytes(39, 100_000);
+		let options = EncodeOptions::for_size(data.len() as u64);
+		let mut encoder = EncodeWriter::new(Vec::new(), &options).unwrap();
+		encoder.write_all(&data).await.unwrap();
+		encoder.shutdown().await.unwrap();
+		assert!(encoder.destination == png(&data), "the image differs from a blocking encode");
+
+		let mut decoder = DecodeWriter::new(Vec::new(), 4096);
+		decoder.write_all(&encoder.destination).await.unwrap();
+		decoder.shutdown().await.unwrap();
+		assert!(decoder.destination[..data.len()] == *data);
+	}
+
+	#[tokio::test]
+	async fn decode_reader_holds_back_input_while_output_is_pending() {
+		// 16mb of zeros deflates to a few kb, all of it read from the source at once
+		let data = vec![0u8; 16 * 1024 * 1024];
+		let image = png(&data);
+		let mut reader = DecodeReader::new(&image[..], 1024 * 1024);
+		let mut buffer = vec![0u8; 64 * 1024];
+		let mut decoded = 0;
+		loop {
+			let size = reader.read(&mut buffer).await.unwrap();
+			assert!(pending_len(&reader.pending) <= MAX_PENDING, "{} bytes pending", pending_len(&reader.pending));
+			if size == 0 {
+				break;
+			}
+			assert!(buffer[..size].iter().all(|&b| b == 0));
+			decoded += size;
+		}
+		assert!(decoded >= data.len());
+	}
+
+	#[test]
+	fn decode_writer_takes_nothing_while_output_is_pending() {
+		let image = png(&vec![0u8; 16 * 1024 * 1024]);
+		let mut writer = DecodeWriter::new(Stalled, 1024 * 1024);
+		let mut cx = Context::from_waker(Waker::noop());
+		let mut taken = 0;
+		while let Poll::Ready(size) = Pin::new(&mut writer).poll_write(&mut cx, &image[taken..]) {
+			taken += size.unwrap();
+			assert!(taken < image.len(), "took the whole image without handing anything on");
+		}
+		assert!(taken <= DECODE_STEP, "took {} bytes", taken);
+		assert!(pending_len(&writer.pending) <= MAX_PENDING, "{} bytes pending", pending_len(&writer.pending));
+	}
+
+	// 100kb of data around a 64mb hole, ending in another hole
+	fn sparse_file() -> (Vec<u8>, Vec<u8>) {
+		let data = bytes(39, 100_000);
+		let holes = vec![Hole { offset: 50_000, length: 64 * 1024 * 1024 }, Hole { offset: 64 * 1024 * 1024 + 100_000, length: 1000 }];
+		let image = sparse_png(&data, HoleMap::new(64 * 1024 * 1024 + 101_000, holes));
+		let mut file = data[..50_000].to_vec();
+		file.resize(64 * 1024 * 1024 + 50_000, 0);
+		file.extend_from_slice(&data[50_000..]);
+		file.resize(64 * 1024 * 1024 + 101_000, 0);
+		(image, file)
+	}
+
+	#[tokio::test]
+	async fn decode_reader_hands_holes_on_a_slice_at_a_time() {
+		let (image, file) = sparse_file();
+		let mut reader = DecodeReader::new(&image[..], 1024 * 1024);
+		let mut buffer = vec![0u8; 64 * 1024];
+		let mut decoded = Vec::new();
+		loop {
+			let size = reader.read(&mut buffer).await.unwrap();
+			assert!(pending_len(&reader.pending) <= MAX_PENDING, "{} bytes pending", pending_len(&reader.pending));
+			if size == 0 {
+				break;
+			}
+			decoded.extend_from_slice(&buffer[..size]);
+		}
+		assert!(decoded == file);
+	}
+
+	#[test]
+	fn decode_writer_hands_holes_on_a_slice_at_a_time() {
+		let (image, file) = sparse_file();
+		let mut writer = DecodeWriter::new(Vec::new(), 1024 * 1024);
+		let mut cx = Context::from_waker(Waker::noop());
+		let mut taken = 0;
+		while taken < image.len() {
+			match Pin::new(&mut writer).poll_write(&mut cx, &image[taken..]) {
+				Poll::Ready(size) => taken += size.unwrap(),
+				Poll::Pending => panic!("a Vec took nothing")
+			};
+			assert!(pending_len(&writer.pending) <= MAX_PENDING, "{} bytes pending", pending_len(&writer.pending));
+		}
+		assert!(Pin::new(&mut writer).poll_shutdown(&mut cx).is_ready());
+		assert!(writer.destination == file);
+	}
+}
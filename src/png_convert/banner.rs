@@ -0,0 +1,164 @@
+extern crate qrcodegen;
+extern crate sha2;
+use qrcodegen::{QrCode, QrCodeEcc};
+use sha2::{Digest, Sha256};
+
+use std::io::{self, Read};
+use std::convert::TryInto;
+
+use crate::util::color_type::*;
+
+// Private ancillary chunk holding the number of banner rows (must precede the image data)
+pub static CHUNK_TYPE: &[u8; 4] = b"pgBN";
+
+// Glyphs are 5x7 pixels with a column of spacing, lines have 3 rows of spacing
+static GLYPH_WIDTH: u64 = 6;
+static LINE_HEIGHT: u64 = 10;
+static MARGIN: u64 = 4;
+static QUIET_ZONE: u64 = 4;
+static MAX_SCALE: u64 = 4;
+// Width of images sized automatically, leaving room for the banner of small files
+pub static MIN_WIDTH: u64 = 320;
+// Fewest characters per line before the image counts as too narrow
+static MIN_COLUMNS: u64 = 8;
+
+// Printable ASCII (0x20 to 0x7e), one byte per column with the top row in the lowest bit
+static FONT: [[u8; 5]; 95] = [
+	[0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5f, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7f, 0x14, 0x7f, 0x14],
+	[0x24, 0x2a, 0x7f, 0x2a, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+	[0x00, 0x1c, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1c, 0x00], [0x14, 0x08, 0x3e, 0x08, 0x14], [0x08, 0x08, 0x3e, 0x08, 0x08],
+	[0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+	[0x3e, 0x51, 0x49, 0x45, 0x3e], [0x00, 0x42, 0x7f, 0x40, 0x00], [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4b, 0x31],
+	[0x18, 0x14, 0x12, 0x7f, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3c, 0x4a, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+	[0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1e], [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+	[0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+	[0x32, 0x49, 0x79, 0x41, 0x3e], [0x7e, 0x11, 0x11, 0x11, 0x7e], [0x7f, 0x49, 0x49, 0x49, 0x36], [0x3e, 0x41, 0x41, 0x41, 0x22],
+	[0x7f, 0x41, 0x41, 0x22, 0x1c], [0x7f, 0x49, 0x49, 0x49, 0x41], [0x7f, 0x09, 0x09, 0x09, 0x01], [0x3e, 0x41, 0x49, 0x49, 0x7a],
+	[0x7f, 0x08, 0x08, 0x08, 0x7f], [0x00, 0x41, 0x7f, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3f, 0x01], [0x7f, 0x08, 0x14, 0x22, 0x41],
+	[0x7f, 0x40, 0x40, 0x40, 0x40], [0x7f, 0x02, 0x0c, 0x02, 0x7f], [0x7f, 0x04, 0x08, 0x10, 0x7f], [0x3e, 0x41, 0x41, 0x41, 0x3e],
+	[0x7f, 0x09, 0x09, 0x09, 0x06], [0x3e, 0x41, 0x51, 0x21, 0x5e], [0x7f, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+	[0x01, 0x01, 0x7f, 0x01, 0x01], [0x3f, 0x40, 0x40, 0x40, 0x3f], [0x1f, 0x20, 0x40, 0x20, 0x1f], [0x3f, 0x40, 0x38, 0x40, 0x3f],
+	[0x63, 0x14, 0x08, 0x14, 0x63], [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7f, 0x41, 0x41, 0x00],
+	[0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7f, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+	[0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], [0x7f, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+	[0x38, 0x44, 0x44, 0x48, 0x7f], [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7e, 0x09, 0x01, 0x02], [0x0c, 0x52, 0x52, 0x52, 0x3e],
+	[0x7f, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7d, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3d, 0x00], [0x7f, 0x10, 0x28, 0x44, 0x00],
+	[0x00, 0x41, 0x7f, 0x40, 0x00], [0x7c, 0x04, 0x18, 0x04, 0x78], [0x7c, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+	[0x7c, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7c], [0x7c, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+	[0x04, 0x3f, 0x44, 0x40, 0x20], [0x3c, 0x40, 0x40, 0x20, 0x7c], [0x1c, 0x20, 0x40, 0x20, 0x1c], [0x3c, 0x40, 0x30, 0x40, 0x3c],
+	[0x44, 0x28, 0x10, 0x28, 0x44], [0x0c, 0x50, 0x50, 0x50, 0x3c], [0x44, 0x64, 0x54, 0x4c, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+	[0x00, 0x00, 0x7f, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08]
+];
+
+// Rows of pixels shown above the encoded data
+#[derive(Debug, Clone)]
+pub struct Banner {
+	pub rows: u32,
+	pub pixels: Vec<u8>
+}
+
+// Black on white pixels of a fixed width
+struct Canvas {
+	width: u64,
+	ink: Vec<bool>
+}
+
+impl Canvas {
+	fn fill(&mut self, x: u64, y: u64, size: u64) {
+		for row in y..y + size {
+			let start = (row * self.width + x) as usize;
+			for pixel in self.ink[start..start + size as usize].iter_mut() {
+				*pixel = true;
+			}
+		}
+	}
+}
+
+// Renders lines of text (and a QR code of qr) across the full width of the image, None if it is too narrow
+pub fn render(lines: &[String], qr: Option<&str>, width: u64, color_type: u8, bit_depth: u8) -> Option<Banner> {
+	let code = match qr {
+		Some(text) => Some(QrCode::encode_text(text, QrCodeEcc::Medium).ok()?),
+		None => None
+	};
+	// QR modules are twice the size of font pixels so phones can scan them
+	let qr_modules = code.as_ref().map_or(0, |code| code.size() as u64 + 2 * QUIET_ZONE) * 2;
+	let longest = lines.iter().map(|line| line.chars().count() as u64).max().unwrap_or(0);
+
+	// Largest scale everything fits at, otherwise lines are cut short at the smallest one
+	let scale = (1..=MAX_SCALE).rev()
+		.find(|scale| scale * (2 * MARGIN + longest * GLYPH_WIDTH + qr_modules) <= width)
+		.unwrap_or(1);
+	let qr_size = qr_modules * scale;
+	let columns = width.saturating_sub(2 * MARGIN * scale + qr_size) / (GLYPH_WIDTH * scale);
+	if columns < MIN_COLUMNS {
+		return None;
+	}
+	let rows = std::cmp::max(2 * MARGIN * scale + lines.len() as u64 * LINE_HEIGHT * scale, qr_size);
+
+	let mut canvas = Canvas {
+		width: width,
+		ink: vec![false; (width * rows) as usize]
+	};
+	for (i, line) in lines.iter().enumerate() {
+		let top = MARGIN * scale + i as u64 * LINE_HEIGHT * scale;
+		for (j, c) in line.chars().take(columns as usize).enumerate() {
+			let glyph = match c {
+				' '..='~' => FONT[c as usize - 0x20],
+				_ => FONT['?' as usize - 0x20]
+			};
+			let left = MARGIN * scale + j as u64 * GLYPH_WIDTH * scale;
+			for (x, column) in glyph.iter().enumerate() {
+				for y in 0..7 {
+					if column >> y & 1 == 1 {
+						canvas.fill(left + x as u64 * scale, top + y * scale, scale);
+					}
+				}
+			}
+		}
+	}
+	if let Some(code) = code {
+		let module = 2 * scale;
+		let left = width - qr_size + QUIET_ZONE * module;
+		let top = QUIET_ZONE * module;
+		for y in 0..code.size() {
+			for x in 0..code.size() {
+				if code.get_module(x, y) {
+					canvas.fill(left + x as u64 * module, top + y as u64 * module, module);
+				}
+			}
+		}
+	}
+
+	// Alpha channels stay opaque, every other channel is black or white
+	let step = (bit_depth / 8) as usize;
+	let channels = total_bytes(color_type, bit_depth) / step;
+	let has_alpha = color_type == 4 || color_type == 6;
+	let pixel = |value: u8| -> Vec<u8> {
+		(0..channels * step).map(|i| if has_alpha && i / step == channels - 1 { 0xff } else { value }).collect()
+	};
+	let (black, white) = (pixel(0), pixel(0xff));
+	let mut pixels = Vec::with_capacity(canvas.ink.len() * channels * step);
+	for &ink in canvas.ink.iter() {
+		pixels.extend_from_slice(if ink { &black } else { &white });
+	}
+
+	Some(Banner {
+		rows: rows as u32,
+		pixels: pixels
+	})
+}
+
+// Reads the number of banner rows from the data of a banner chunk
+pub fn rows(data: &[u8]) -> Option<u32> {
+	match data.len() {
+		4 => Some(u32::from_be_bytes(data.try_into().unwrap())),
+		_ => None
+	}
+}
+
+// Hex digest of everything left in the source
+pub fn sha256<R: Read>(source: &mut R) -> io::Result<String> {
+	let mut hasher = Sha256::new();
+	io::copy(source, &mut hasher)?;
+	Ok(format!("{:x}", hasher.finalize()))
+}
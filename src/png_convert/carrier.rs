@@ -0,0 +1,151 @@
+extern crate crc32fast;
+use crc32fast::Hasher;
+
+use std::io;
+use std::convert::TryInto;
+
+use crate::png_convert::image::Image;
+
+static MAGIC: &[u8; 4] = b"pngF";
+// Magic + payload length + crc32 of the payload
+static HEADER_SIZE: usize = 16;
+// Samples holding the bits per channel (1 bit each)
+static MODE_SAMPLES: usize = 8;
+
+// Positions of the least significant byte of every sample
+fn sample_indices(image: &Image) -> impl Iterator<Item = usize> {
+	let step = (image.bit_depth / 8) as usize;
+	let len = image.pixels.len();
+	(step - 1..len).step_by(step)
+}
+
+// Bytes of payload that fit in the low bits of a carrier
+pub fn capacity(image: &Image, bits: u8) -> u64 {
+	let samples = (image.pixels.len() / (image.bit_depth / 8) as usize) as u64;
+	let usable = samples.saturating_sub(MODE_SAMPLES as u64) * bits as u64 / 8;
+	usable.saturating_sub(HEADER_SIZE as u64)
+}
+
+// Embeds the payload into the lowest bits of each channel of the carrier
+pub fn embed(image: &mut Image, payload: &[u8], bits: u8) {
+	let mut hasher = Hasher::new();
+	hasher.update(payload);
+	let header = [
+		&MAGIC[..],
+		&(payload.len() as u64).to_be_bytes()[..],
+		&hasher.finalize().to_be_bytes()[..]
+	].concat();
+
+	let indices: Vec<usize> = sample_indices(image).collect();
+	for (i, &index) in indices[..MODE_SAMPLES].iter().enumerate() {
+		let bit = (bits >> (MODE_SAMPLES - 1 - i)) & 1;
+		image.pixels[index] = (image.pixels[index] & !1) | bit;
+	}
+
+	let mask = (1u8 << bits) - 1;
+	let mut stream = BitStream::new(header.iter().chain(payload.iter()).copied());
+	for &index in indices[MODE_SAMPLES..].iter() {
+		match stream.take(bits) {
+			Some(value) => image.pixels[index] = (image.pixels[index] & !mask) | value,
+			None => break
+		};
+	}
+}
+
+// Extracts a payload embedded with embed, verifying its length and crc
+pub fn extract(image: &Image) -> io::Result<Vec<u8>> {
+	let indices: Vec<usize> = sample_indices(image).collect();
+	if indices.len() < MODE_SAMPLES {
+		return Err(invalid_data());
+	}
+	let mut bits = 0u8;
+	for &index in indices[..MODE_SAMPLES].iter() {
+		bits = (bits << 1) | (image.pixels[index] & 1);
+	}
+	if !(1..=4).contains(&bits) {
+		return Err(invalid_data());
+	}
+
+	let mask = (1u8 << bits) - 1;
+	let mut bytes: Vec<u8> = Vec::new();
+	let mut accumulator: u32 = 0;
+	let mut filled: u8 = 0;
+	let mut expected = HEADER_SIZE;
+	for &index in indices[MODE_SAMPLES..].iter() {
+		accumulator = (accumulator << bits) | (image.pixels[index] & mask) as u32;
+		filled += bits;
+		if filled >= 8 {
+			filled -= 8;
+			bytes.push((accumulator >> filled) as u8);
+			accumulator &= (1 << filled) - 1;
+
+			if bytes.len() == HEADER_SIZE {
+				if bytes[..4] != MAGIC[..] {
+					return Err(invalid_data());
+				}
+				let length = u64::from_be_bytes(bytes[4..12].try_into().unwrap());
+				if length > capacity(image, bits) {
+					return Err(invalid_data());
+				}
+				expected = HEADER_SIZE + length as usize;
+				bytes.reserve(length as usize);
+			}
+			if bytes.len() == expected {
+				break;
+			}
+		}
+	}
+	if bytes.len() != expected {
+		return Err(invalid_data());
+	}
+
+	let payload = bytes.split_off(HEADER_SIZE);
+	let mut hasher = Hasher::new();
+	hasher.update(&payload);
+	if hasher.finalize().to_be_bytes()[..] != bytes[12..16] {
+		return Err(invalid_data());
+	}
+	Ok(payload)
+}
+
+// Splits bytes into groups of bits (most significant first)
+struct BitStream<I: Iterator<Item = u8>> {
+	source: I,
+	accumulator: u32,
+	filled: u8
+}
+
+impl<I: Iterator<Item = u8>> BitStream<I> {
+	fn new(source: I) -> Self {
+		Self {
+			source: source,
+			accumulator: 0,
+			filled: 0
+		}
+	}
+
+	fn take(&mut self, bits: u8) -> Option<u8> {
+		if self.filled < bits {
+			match self.source.next() {
+				Some(byte) => {
+					self.accumulator = (self.accumulator << 8) | byte as u32;
+					self.filled += 8;
+				},
+				// Pad the last group with zeros
+				None if self.filled > 0 => {
+					self.accumulator <<= bits - self.filled;
+					self.filled = bits;
+				},
+				None => return None
+			};
+		}
+		self.filled -= bits;
+		let value = (self.accumulator >> self.filled) as u8 & ((1u8 << bits) - 1);
+		self.accumulator &= (1 << self.filled) - 1;
+		Some(value)
+	}
+}
+
+fn invalid_data() -> io::Error {
+	io::Error::from(io::ErrorKind::InvalidData)
+}
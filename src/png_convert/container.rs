@@ -0,0 +1,198 @@
+extern crate image;
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, BufReader};
+
+use crate::png_convert::image::Image;
+use crate::util::{Limits, PNG_HEADER};
+
+// File extensions of every supported container
+static EXTENSIONS: &[&str] = &[".png", ".jpg", ".jpeg", ".webp", ".bmp", ".tif", ".tiff", ".qoi"];
+
+// Image containers an encoded file may be found in (re-hosted or written with --format)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	Png,
+	Jpeg,
+	WebP,
+	Bmp,
+	Tiff,
+	Qoi
+}
+
+impl Format {
+	// Detects the container from the leading bytes of a file
+	pub fn detect(magic: &[u8]) -> Option<Self> {
+		if magic.starts_with(&PNG_HEADER) {
+			Some(Format::Png)
+		} else if magic.starts_with(&[0xff, 0xd8, 0xff]) {
+			Some(Format::Jpeg)
+		} else if magic.len() >= 12 && &magic[..4] == b"RIFF" && &magic[8..12] == b"WEBP" {
+			Some(Format::WebP)
+		} else if magic.starts_with(b"BM") {
+			Some(Format::Bmp)
+		} else if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+			Some(Format::Tiff)
+		} else if magic.starts_with(b"qoif") {
+			Some(Format::Qoi)
+		} else {
+			None
+		}
+	}
+
+	// Whether the container (usually) discards pixel data
+	pub fn is_lossy(&self, magic: &[u8]) -> bool {
+		match self {
+			Format::Jpeg => true,
+			// Simple lossy WebPs start with a "VP8 " chunk, lossless ones with "VP8L"
+			Format::WebP => magic.len() >= 16 && &magic[12..16] == b"VP8 ",
+			_ => false
+		}
+	}
+
+	fn image_format(&self) -> image::ImageFormat {
+		match self {
+			Format::Png => image::ImageFormat::Png,
+			Format::Jpeg => image::ImageFormat::Jpeg,
+			Format::WebP => image::ImageFormat::WebP,
+			Format::Bmp => image::ImageFormat::Bmp,
+			Format::Tiff => image::ImageFormat::Tiff,
+			Format::Qoi => image::ImageFormat::Qoi
+		}
+	}
+}
+
+impl fmt::Display for Format {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", match self {
+			Format::Png => "PNG",
+			Format::Jpeg => "JPEG",
+			Format::WebP => "WebP",
+			Format::Bmp => "BMP",
+			Format::Tiff => "TIFF",
+			Format::Qoi => "QOI"
+		})
+	}
+}
+
+// Removes the image extension from a file name (ie: file.txt.webp -> file.txt)
+pub fn strip_extension(name: &str) -> Option<&str> {
+	let lower = name.to_lowercase();
+	EXTENSIONS.iter()
+		.find(|extension| lower.len() > extension.len() && lower.ends_with(*extension))
+		.map(|extension| &name[..name.len() - extension.len()])
+}
+
+// Reads the leading bytes used to detect a container and rewinds the stream
+pub fn read_magic<R: Read + Seek>(source: &mut R) -> io::Result<Vec<u8>> {
+	let mut magic = Vec::with_capacity(16);
+	source.take(16).read_to_end(&mut magic)?;
+	source.seek(SeekFrom::Start(0))?;
+	Ok(magic)
+}
+
+// Decodes any supported container into its pixel rows exactly as stored, failing as soon as it runs into any of limits
+pub fn read_image<R: Read + Seek>(source: R, limits: &Limits) -> io::Result<Image> {
+	read(source, limits, false)
+}
+
+// Decodes a carrier like read_image, undoing the channels hosts add to pictures re-hosted in other formats
+// (payloads in the pixels are read with read_image, since their bytes can look like either)
+pub fn read_carrier<R: Read + Seek>(source: R, limits: &Limits) -> io::Result<Image> {
+	read(source, limits, true)
+}
+
+fn read<R: Read + Seek>(mut source: R, limits: &Limits, normalized: bool) -> io::Result<Image> {
+	let magic = read_magic(&mut source)?;
+	let format = match Format::detect(&magic) {
+		Some(format) => format,
+		None => return Err(invalid_data())
+	};
+	if format == Format::Png {
+		return Image::read_limited(source, limits);
+	}
+
+	// Dimensions are read from the header before anything is decoded
+	let (width, height) = image::ImageReader::with_format(BufReader::new(&mut source), format.image_format())
+		.into_dimensions()
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	limits.check_dimensions(width as u64, height as u64)?;
+	source.seek(SeekFrom::Start(0))?;
+
+	let decoded = image::ImageReader::with_format(BufReader::new(source), format.image_format())
+		.decode()
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	let (width, height) = (decoded.width(), decoded.height());
+	let (color_type, bit_depth, pixels) = match decoded {
+		image::DynamicImage::ImageLuma8(buffer) => (0, 8, buffer.into_raw()),
+		image::DynamicImage::ImageLumaA8(buffer) => (4, 8, buffer.into_raw()),
+		image::DynamicImage::ImageRgb8(buffer) => (2, 8, buffer.into_raw()),
+		image::DynamicImage::ImageRgba8(buffer) => (6, 8, buffer.into_raw()),
+		image::DynamicImage::ImageLuma16(buffer) => (0, 16, big_endian(buffer.into_raw())),
+		image::DynamicImage::ImageLumaA16(buffer) => (4, 16, big_endian(buffer.into_raw())),
+		image::DynamicImage::ImageRgb16(buffer) => (2, 16, big_endian(buffer.into_raw())),
+		image::DynamicImage::ImageRgba16(buffer) => (6, 16, big_endian(buffer.into_raw())),
+		// Floating point samples can't hold the original bytes
+		_ => return Err(invalid_data())
+	};
+	let mut image = Image {
+		width: width,
+		height: height,
+		bit_depth: bit_depth,
+		color_type: color_type,
+		pixels: pixels,
+		ancillary: Vec::new()
+	};
+	if normalized {
+		normalize(&mut image);
+	}
+	Ok(image)
+}
+
+// Samples of PNGs are stored most significant byte first
+fn big_endian(samples: Vec<u16>) -> Vec<u8> {
+	samples.iter().flat_map(|sample| sample.to_be_bytes().to_vec()).collect()
+}
+
+// Undoes channels added by hosts: opaque alpha channels and greyscale expanded to RGB
+fn normalize(image: &mut Image) {
+	let step = (image.bit_depth / 8) as usize;
+	let channels = image.bytes_per_pixel() / step;
+	let has_alpha = image.color_type == 4 || image.color_type == 6;
+	let is_color = image.color_type == 2 || image.color_type == 6;
+
+	let opaque = has_alpha && image.pixels.chunks(channels * step)
+		.all(|pixel| sample(pixel, channels - 1, step).iter().all(|&b| b == 0xff));
+	let grey = is_color && image.pixels.chunks(channels * step)
+		.all(|pixel| sample(pixel, 0, step) == sample(pixel, 1, step) && sample(pixel, 0, step) == sample(pixel, 2, step));
+	if !opaque && !grey {
+		return;
+	}
+
+	let alpha = has_alpha && !opaque;
+	let mut keep: Vec<usize> = match grey {
+		true => vec![0],
+		false => (0..channels - has_alpha as usize).collect()
+	};
+	if alpha {
+		keep.push(channels - 1);
+	}
+	image.pixels = image.pixels.chunks(channels * step)
+		.flat_map(|pixel| keep.iter().flat_map(|&channel| sample(pixel, channel, step).to_vec()).collect::<Vec<u8>>())
+		.collect();
+	image.color_type = match (grey || !is_color, alpha) {
+		(true, false) => 0,
+		(true, true) => 4,
+		(false, false) => 2,
+		(false, true) => 6
+	};
+}
+
+// Bytes of one channel of a pixel
+fn sample(pixel: &[u8], channel: usize, step: usize) -> &[u8] {
+	&pixel[channel * step..(channel + 1) * step]
+}
+
+fn invalid_data() -> io::Error {
+	io::Error::from(io::ErrorKind::InvalidData)
+}
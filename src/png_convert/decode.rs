@@ -1,117 +1,998 @@
 extern crate flate2;
-use flate2::read::ZlibDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
 
-use std::io::{self, Write};
+use std::io::{self, SeekFrom, Write};
 use std::io::prelude::*;
 use std::convert::TryInto;
 
 use crate::util;
-use util::color_type::*;
-use util::{ReadFile, WriteFile};
+use crate::png_convert::{banner, payload};
+use crate::png_convert::resume::{self, Checkpoint, FlushPoint, Resume};
+use util::png::Ihdr;
+use util::{chunk, metadata, ChunkData, HoleMap, Limits, Metadata, WriteFile};
+use util::sparse::{self, Dense, Skip, Unpacker};
 
-#[derive(Debug)]
-struct Transformer<R: Read> {
-	source: R,
-	buffer_size: usize,
-	total_read: usize
+// Where the decoder is within the PNG
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+	Signature,
+	// Collecting the length & type of the next chunk
+	Header,
+	// Collecting the start of a chunk whose data matters (IHDR, fcTL, fdAT sequence numbers & banners)
+	Collect(usize),
+	// Image data left in the current IDAT or fdAT chunk (followed by its crc)
+	Data(usize),
+	// Bytes left of a chunk that is skipped (or of the crc of any chunk)
+	Skip(usize),
+	End
+}
+
+// What a decoder finds in the pieces fed into it, in order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+	// Any chunk, once its length & type are known
+	Chunk { chunk_type: [u8; 4], length: u32 },
+	// The IHDR chunk
+	Header { width: u32, height: u32, bit_depth: u8, color_type: u8 },
+	// Image data starting a zlib stream of its own (the still image or the next frame of an APNG)
+	Frame { width: u32 },
+	// Bytes of the original file
+	Data(Vec<u8>),
+	// The IEND chunk (anything fed after it is ignored)
+	End
+}
+
+// Events of a single feed, merging the bytes of the original file written in between other events
+#[derive(Default)]
+struct Events(Vec<Event>);
+
+impl Write for Events {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self.0.last_mut() {
+			Some(Event::Data(data)) => data.extend_from_slice(buf),
+			_ => self.0.push(Event::Data(buf.to_vec()))
+		};
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+// Decodes a PNG (or the frames of an APNG) fed into it a piece at a time, leaving reading the input to whoever
+// drives it (pieces may split chunks anywhere)
+pub struct Decoder {
+	state: State,
+	events: Events,
+	// Bytes of the header or start of the current chunk collected so far
+	pending: Vec<u8>,
+	chunk_type: [u8; 4],
+	chunk_length: usize,
+	inflater: Decompress,
+	buffer: Vec<u8>,
+	// Scanlines of the current frame (every frame is a zlib stream of its own)
+	scanlines: Option<Scanlines>,
+	stream_ended: bool,
+	frames: u64,
+	width: usize,
+	height: usize,
+	multiplier: usize,
+	// Next APNG sequence number (fcTL & fdAT chunks share one sequence)
+	sequence: u32,
+	// Width of the next APNG frame
+	frame_width: Option<usize>,
+	// Frames listed by the acTL chunk (0 for still images)
+	frame_count: u32,
+	// Rows of the first frame taken up by a --banner
+	banner_rows: usize,
+	// Places the data of a sparse file around its holes
+	holes: Option<Unpacker>,
+	// pgSP chunk read once the image data starts (when the data the image holds is known)
+	hole_data: Option<Vec<u8>>,
+	// Whether the file comes from payload chunks (--container chunk) instead of the placeholder image data
+	payload: bool,
+	// Uncompressed bytes preceding the stream of the inflater (when continuing from a flush point)
+	base: u64,
+	total_in: u64,
+	limits: Limits,
+	// Bytes of the original file written so far
+	output: u64,
+	// Image data taken & inflated across every frame (for --max-ratio)
+	compressed: u64,
+	inflated: u64
 }
 
-impl<R: Read> Read for Transformer<R> {
-	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-		let mut read_size = self.buffer_size;
-		let mut filler = [0u8; 4];
-		if read_size > buf.len() {
-			read_size = buf.len();
+impl Decoder {
+	// Decodes from the signature on, inflating at most buffer_size bytes at a time
+	pub fn new(buffer_size: usize) -> Self {
+		Self::with_limits(buffer_size, Limits::default())
+	}
+
+	// Decodes from the signature on, failing as soon as the PNG runs into any of limits
+	pub fn with_limits(buffer_size: usize, limits: Limits) -> Self {
+		Self {
+			state: State::Signature,
+			events: Events::default(),
+			pending: Vec::with_capacity(util::PNG_HEADER.len()),
+			chunk_type: [0u8; 4],
+			chunk_length: 0,
+			inflater: Decompress::new(true),
+			buffer: vec![0u8; std::cmp::max(1, limits.buffer_size(buffer_size))],
+			scanlines: None,
+			stream_ended: false,
+			frames: 0,
+			width: 0,
+			height: 0,
+			multiplier: 0,
+			sequence: 0,
+			frame_width: None,
+			frame_count: 0,
+			banner_rows: 0,
+			holes: None,
+			hole_data: None,
+			payload: false,
+			base: 0,
+			total_in: 0,
+			limits: limits,
+			output: 0,
+			compressed: 0,
+			inflated: 0
+		}
+	}
+
+	// Continues the image data of a PNG without frames from the chunk at offset, where uncompressed bytes of
+	// scanlines have already been inflated (flush points start raw deflate blocks, only the start has a zlib header)
+	fn resume(index: &Index, offset: u64, uncompressed: u64, buffer_size: usize, limits: &Limits) -> io::Result<Self> {
+		let mut decoder = Self::with_limits(buffer_size, *limits);
+		decoder.state = State::Header;
+		decoder.width = index.width;
+		decoder.height = index.height;
+		decoder.multiplier = index.multiplier;
+		decoder.banner_rows = index.banner_rows;
+		decoder.total_in = offset;
+		decoder.start_frame()?;
+		if uncompressed != 0 {
+			let chunk_size = decoder.chunk_size();
+			decoder.inflater = Decompress::new(false);
+			decoder.base = uncompressed;
+			decoder.output = output_size(chunk_size, uncompressed, index.banner_rows);
+			decoder.scanlines = Some(Scanlines {
+				chunk_size: chunk_size,
+				remainder: (uncompressed % chunk_size as u64) as usize,
+				banner_left: decoder.banner_bytes()?.saturating_sub(uncompressed as usize),
+				raw: false
+			});
 		}
-		if read_size > 0 {
-			self.total_read += self.source.read(&mut buf[..read_size])?;
-			self.buffer_size -= read_size;
-			if self.buffer_size == 0 {
-				self.total_read += self.source.read(&mut filler)?;
+		decoder.holes = index.holes.clone().map(|map| Unpacker::at(map, decoder.output, *limits));
+		Ok(decoder)
+	}
+
+	// Bytes of the PNG taken so far
+	pub fn total_in(&self) -> u64 {
+		self.total_in
+	}
+
+	// Whether the end of the PNG has been reached (anything after it is left untouched)
+	pub fn ended(&self) -> bool {
+		self.state == State::End
+	}
+
+	// Holes of the original file (None unless it was sparse)
+	pub fn holes(&self) -> Option<&HoleMap> {
+		self.holes.as_ref().map(|holes| holes.map())
+	}
+
+	// Bytes of scanlines inflated so far (only meaningful for PNGs without frames)
+	fn uncompressed(&self) -> u64 {
+		self.base + self.inflater.total_out()
+	}
+
+	// Takes the next piece of the PNG, returning what was found in it
+	pub fn feed(&mut self, input: &[u8]) -> io::Result<Vec<Event>> {
+		self.run(input, None::<&mut Dense<Events>>)?;
+		Ok(std::mem::take(&mut self.events.0))
+	}
+
+	// Takes the next piece of the PNG, writing only the bytes of the original file to output (without the copies
+	// of Data events)
+	pub fn push<W: Write>(&mut self, input: &[u8], output: &mut W) -> io::Result<()> {
+		self.push_sparse(input, &mut Dense(output))
+	}
+
+	// Like push, but leaves the holes of a sparse file to output (ie: seeking past them) instead of writing zeros
+	pub fn push_sparse<O: Skip>(&mut self, input: &[u8], output: &mut O) -> io::Result<()> {
+		let result = self.run(input, Some(output));
+		self.events.0.clear();
+		result
+	}
+
+	// Data goes to output, or among the events when there is none
+	fn run<O: Skip>(&mut self, input: &[u8], mut output: Option<&mut O>) -> io::Result<()> {
+		let mut rest = input;
+		while !rest.is_empty() && self.state != State::End {
+			let taken = match self.state {
+				State::Signature | State::Header | State::Collect(_) => {
+					let wanted = match self.state {
+						State::Signature => util::PNG_HEADER.len(),
+						State::Header => 8,
+						State::Collect(size) => size,
+						_ => unreachable!()
+					};
+					let size = std::cmp::min(wanted - self.pending.len(), rest.len());
+					self.pending.extend_from_slice(&rest[..size]);
+					if self.pending.len() == wanted {
+						let pending = std::mem::take(&mut self.pending);
+						self.state = self.collected(&pending)?;
+						self.pending = pending;
+						self.pending.clear();
+						if self.state == State::End {
+							self.end(output.as_deref_mut())?;
+						}
+					}
+					size
+				},
+				State::Data(left) => {
+					let size = std::cmp::min(left, rest.len());
+					self.inflate(&rest[..size], output.as_deref_mut())?;
+					self.state = match left - size {
+						0 => State::Skip(4),
+						left => State::Data(left)
+					};
+					size
+				},
+				State::Skip(left) => {
+					let size = std::cmp::min(left, rest.len());
+					self.state = match left - size {
+						0 => State::Header,
+						left => State::Skip(left)
+					};
+					size
+				},
+				State::End => 0
+			};
+			rest = &rest[taken..];
+			self.total_in += taken as u64;
+		}
+		Ok(())
+	}
+
+	// Checks that the whole PNG was pushed
+	pub fn finish(&self) -> io::Result<()> {
+		match self.state {
+			State::End => Ok(()),
+			_ => Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+		}
+	}
+
+	// Acts on a complete signature, chunk header or start of a chunk, returning what follows it
+	fn collected(&mut self, data: &[u8]) -> io::Result<State> {
+		match self.state {
+			State::Signature => {
+				if data != &util::PNG_HEADER[..] {
+					return Err(io::Error::from(io::ErrorKind::InvalidData));
+				}
+				return Ok(State::Header);
+			},
+			State::Header => {
+				self.chunk_length = read_be_u32(&mut &data[..4]) as usize;
+				self.chunk_type.copy_from_slice(&data[4..8]);
+				let length = self.chunk_length;
+				let chunk_type = self.chunk_type;
+				self.limits.check_chunk(&chunk_type, length as u64)?;
+				self.events.0.push(Event::Chunk { chunk_type: chunk_type, length: length as u32 });
+				return Ok(match &chunk_type {
+					b"IEND" => State::End,
+					b"IDAT" if length == 0 || self.payload => State::Skip(length + 4),
+					b"IDAT" => {
+						self.next_data()?;
+						State::Data(length)
+					},
+					b"IHDR" if length >= 13 => State::Collect(13),
+					b"acTL" if length >= 8 => State::Collect(8),
+					b"fdAT" if length >= 4 => State::Collect(4),
+					b"fcTL" if length >= 8 => State::Collect(std::cmp::min(length, 12)),
+					chunk_type if chunk_type == banner::CHUNK_TYPE && length == 4 => State::Collect(4),
+					chunk_type if chunk_type == sparse::CHUNK_TYPE && length >= 8 => {
+						self.limits.check_private_chunk(chunk_type, length as u64)?;
+						State::Collect(length)
+					},
+					chunk_type if chunk_type == payload::CHUNK_TYPE && length > 0 => {
+						self.start_payload()?;
+						State::Data(length)
+					},
+					_ => State::Skip(length + 4)
+				});
+			},
+			_ => ()
+		};
+
+		let chunk_type = self.chunk_type;
+		match &chunk_type {
+			b"IHDR" => {
+				let ihdr = Ihdr::parse(data)?;
+				self.width = ihdr.width as usize;
+				self.height = ihdr.height as usize;
+				self.multiplier = multiplier(&ihdr)?;
+				self.limits.check_dimensions(ihdr.width as u64, ihdr.height as u64)?;
+				self.events.0.push(Event::Header {
+					width: ihdr.width,
+					height: ihdr.height,
+					bit_depth: ihdr.bit_depth,
+					color_type: ihdr.color_type
+				});
+			},
+			b"acTL" => {
+				self.frame_count = read_be_u32(&mut &data[..4]);
+			},
+			b"fdAT" => {
+				self.check_sequence(read_be_u32(&mut &data[..4]))?;
+				if self.chunk_length == 4 {
+					return Ok(State::Skip(4));
+				}
+				self.next_data()?;
+				return Ok(State::Data(self.chunk_length - 4));
+			},
+			b"fcTL" => {
+				self.check_sequence(read_be_u32(&mut &data[..4]))?;
+				let frame_width = read_be_u32(&mut &data[4..8]);
+				if data.len() >= 12 {
+					self.limits.check_dimensions(frame_width as u64, read_be_u32(&mut &data[8..12]) as u64)?;
+				}
+				self.frame_width = Some(frame_width as usize);
+				// The next image data starts a new frame
+				self.scanlines = None;
+			},
+			chunk_type if chunk_type == sparse::CHUNK_TYPE => {
+				self.hole_data = Some(data.to_vec());
+			},
+			_ => {
+				self.banner_rows = banner::rows(data).unwrap() as usize;
 			}
-			return Ok(read_size);
+		};
+		// Rest of the chunk & crc
+		Ok(State::Skip(self.chunk_length - data.len() + 4))
+	}
+
+	// Leaves the holes at the end of a sparse file once IEND is reached
+	fn end<O: Skip>(&mut self, output: Option<&mut O>) -> io::Result<()> {
+		self.read_holes()?;
+		if let Some(holes) = self.holes.as_mut() {
+			match output {
+				Some(output) => holes.skip_holes(output)?,
+				None => holes.skip_holes(&mut Dense(&mut self.events))?
+			};
+		}
+		self.events.0.push(Event::End);
+		Ok(())
+	}
+
+	fn check_sequence(&mut self, sequence: u32) -> io::Result<()> {
+		if sequence != self.sequence {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "APNG chunks are out of sequence order"));
 		}
+		self.sequence += 1;
+		Ok(())
+	}
 
-		self.total_read += self.source.read(&mut buf[..8])?;
-		self.buffer_size = read_be_u32(&mut &buf[..4]) as usize;
+	fn chunk_size(&self) -> usize {
+		(self.frame_width.unwrap_or(self.width) * self.multiplier) + 1
+	}
 
-		// Found end
-		if &buf[4..8] == b"IEND" {
-			return Ok(0usize);
+	// Bytes of scanlines taken up by the rows of a --banner, checked against the IHDR like the dimensions are (the
+	// pgBN chunk can claim anything)
+	fn banner_bytes(&self) -> io::Result<usize> {
+		match self.banner_rows <= self.height {
+			true => self.banner_rows.checked_mul(self.chunk_size()),
+			false => None
+		}.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "banner taller than the image"))
+	}
+
+	// Starts a frame unless the image data continues the current one
+	fn next_data(&mut self) -> io::Result<()> {
+		if self.scanlines.is_none() {
+			if self.width == 0 || self.multiplier == 0 {
+				return Err(io::Error::new(io::ErrorKind::InvalidData, "image data before the IHDR chunk"));
+			}
+			self.read_holes()?;
+			self.start_frame()?;
 		}
+		Ok(())
+	}
 
-		read_size = self.buffer_size;
-		if read_size > buf.len() {
-			read_size = buf.len();
+	// The payload of a chunk container is a zlib stream of the file as it is (the image is only a placeholder)
+	fn start_payload(&mut self) -> io::Result<()> {
+		if self.payload {
+			return Ok(());
 		}
-		self.total_read += self.source.read(&mut buf[..read_size])?;
-		self.buffer_size -= read_size;
-		if self.buffer_size == 0 {
-			// Flush crc
-			self.total_read += self.source.read(&mut filler)?;
+		self.payload = true;
+		self.read_holes()?;
+		self.scanlines = Some(Scanlines {
+			chunk_size: 1,
+			remainder: 0,
+			banner_left: 0,
+			raw: true
+		});
+		self.inflater.reset(true);
+		self.stream_ended = false;
+		Ok(())
+	}
+
+	// Reads the hole map against the data the image holds (the payload of a chunk container isn't bound by its
+	// placeholder image)
+	fn read_holes(&mut self) -> io::Result<()> {
+		if let Some(data) = self.hole_data.take() {
+			let capacity = match self.payload {
+				true => u64::MAX,
+				false => capacity(self.width, self.height, self.multiplier, self.frame_count)
+			};
+			self.holes = Some(Unpacker::new(read_hole_map(&data, capacity)?, self.limits));
 		}
-		return Ok(read_size);
+		Ok(())
 	}
-}
 
-// Decodes a PNG created from PNGIFIER back into a file
-pub fn decode(
-	read_file: &mut ReadFile,
-	write_file: &mut WriteFile,
-	buffer_size: usize
-	) -> io::Result<()> {
-	let mut progress_bar = util::ProgressBar::new(read_file.size, "Converting from PNG");
-	read_file.read_header()?;
-
-	let hdat = (read_file.read_chunk()?).unwrap();
-	let width = read_be_u32(&mut &hdat.get_data()[..4]) as usize;
-	let bit_depth = hdat.get_data()[8];
-	let color_type = hdat.get_data()[9];
-	let multiplier = total_bytes(color_type, bit_depth) as usize;
-	let transformer = Transformer {
-		source: read_file,
-		buffer_size: 0,
-		total_read: 0
-	};
+	fn start_frame(&mut self) -> io::Result<()> {
+		let chunk_size = self.chunk_size();
+		// Whole scanlines of the banner are dropped before any data
+		self.scanlines = Some(Scanlines {
+			chunk_size: chunk_size,
+			remainder: 0,
+			banner_left: match self.frames {
+				0 => self.banner_bytes()?,
+				_ => 0
+			},
+			raw: false
+		});
+		self.inflater.reset(true);
+		self.stream_ended = false;
+		self.frames += 1;
+		self.events.0.push(Event::Frame { width: self.frame_width.unwrap_or(self.width) as u32 });
+		Ok(())
+	}
+
+	fn inflate<O: Skip>(&mut self, mut input: &[u8], mut output: Option<&mut O>) -> io::Result<()> {
+		let scanlines = match self.scanlines.as_mut() {
+			Some(scanlines) => scanlines,
+			None => return Ok(())
+		};
+		while !self.stream_ended {
+			let total_in = self.inflater.total_in();
+			let total_out = self.inflater.total_out();
+			let status = self.inflater.decompress(input, &mut self.buffer, FlushDecompress::None)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			let consumed = (self.inflater.total_in() - total_in) as usize;
+			let produced = (self.inflater.total_out() - total_out) as usize;
+			input = &input[consumed..];
+			self.compressed += consumed as u64;
+			self.inflated += produced as u64;
+			self.limits.check_ratio(self.inflated, self.compressed)?;
+			// Nothing past the limit is written
+			let output_len = scanlines.output_len(produced) as u64;
+			self.limits.check_output(self.output + output_len)?;
+			self.output += output_len;
+			let data = &self.buffer[..produced];
+			match (output.as_mut(), self.holes.as_mut()) {
+				(Some(output), Some(holes)) => scanlines.write(&mut holes.writer(*output), data)?,
+				(Some(output), None) => scanlines.write(*output, data)?,
+				(None, Some(holes)) => scanlines.write(&mut holes.writer(&mut Dense(&mut self.events)), data)?,
+				(None, None) => scanlines.write(&mut self.events, data)?
+			};
+			self.stream_ended = status == Status::StreamEnd;
+			// Everything taken & nothing left inside the inflater
+			if (consumed == 0 && produced == 0) || (input.is_empty() && produced < self.buffer.len()) {
+				break;
+			}
+		}
+		Ok(())
+	}
+}
 
-	let mut inflater = ZlibDecoder::new(transformer);
+// Writes the data of scanlines to a file, dropping the filter bytes (and the rows of a banner)
+struct Scanlines {
+	chunk_size: usize,
+	// Bytes of the current scanline written so far
+	remainder: usize,
+	banner_left: usize,
+	// Writes everything without dropping filter bytes (the payload of a chunk container)
+	raw: bool
+}
 
-	let mut remainder: usize = 0;
-	let chunk_size: usize = (width * multiplier) + 1;
-	let mut buffer = vec![0u8; buffer_size];
+impl Scanlines {
+	// Bytes written for the next len bytes of scanlines
+	fn output_len(&self, len: usize) -> usize {
+		if self.raw {
+			return len;
+		}
+		let chunk_size = self.chunk_size;
+		let rest = len - std::cmp::min(self.banner_left, len);
+		// Filter bytes start every scanline
+		let filters = (self.remainder + rest).div_ceil(chunk_size) - self.remainder.div_ceil(chunk_size);
+		rest - filters
+	}
 
-	let mut read_size = inflater.read(&mut buffer)?;
-	while read_size != 0 {
-		let slice = &buffer[..read_size];
+	fn write<W: Write>(&mut self, output: &mut W, data: &[u8]) -> io::Result<()> {
+		if self.raw {
+			return output.write_all(data);
+		}
+		let chunk_size = self.chunk_size;
+		let remainder = self.remainder;
+		let skipped = std::cmp::min(self.banner_left, data.len());
+		self.banner_left -= skipped;
+		let slice = &data[skipped..];
 		let remaining: usize = (remainder + slice.len()) % chunk_size;
 		let complete: usize = (remainder + slice.len()) / chunk_size;
 		for i in 0..complete {
 			let start = safe_usize((i * chunk_size) + 1, remainder);
 			let end = safe_usize((i + 1) * chunk_size, remainder);
-			write_file.write(&slice[start..end])?;
+			output.write_all(&slice[start..end])?;
 		}
 		if remaining != 0 {
 			let shift: usize = safe_usize((complete * chunk_size) + 1, remainder);
-			write_file.write(&slice[shift..])?;
+			output.write_all(&slice[shift..])?;
 		}
-		remainder = remaining;
-		progress!({progress_bar.set_tick(inflater.get_ref().total_read as u64);});
-		read_size = inflater.read(&mut buffer)?;
+		self.remainder = remaining;
+		Ok(())
 	}
+}
+
+// Bytes written once uncompressed bytes of scanlines are inflated
+fn output_size(chunk_size: usize, uncompressed: u64, banner_rows: usize) -> u64 {
+	let chunk_size = chunk_size as u64;
+	let data = (uncompressed / chunk_size) * (chunk_size - 1) + (uncompressed % chunk_size).saturating_sub(1);
+	data.saturating_sub((banner_rows as u64).saturating_mul(chunk_size - 1))
+}
+
+// Decodes a PNG (or the frames of an APNG) created from PNGIFIER back into a file, returning the bytes of holes it
+// was left with (0 unless the original file was sparse)
+pub fn decode<R: Read>(
+	mut source: R,
+	size: u64,
+	write_file: &mut WriteFile,
+	buffer_size: usize,
+	limits: &Limits
+	) -> io::Result<u64> {
+	let mut progress_bar = util::ProgressBar::new(size, "Converting from PNG");
+	let mut decoder = Decoder::with_limits(buffer_size, *limits);
+	let mut buffer = input_buffer(buffer_size);
+	while !decoder.ended() {
+		let read_size = source.read(&mut buffer)?;
+		if read_size == 0 {
+			break;
+		}
+		decoder.push_sparse(&buffer[..read_size], write_file)?;
+		progress!({progress_bar.set_tick(decoder.total_in());});
+	}
+	decoder.finish()?;
 	progress!({progress_bar.complete();});
-	Ok(())
+	Ok(decoder.holes().map_or(0, |holes| holes.hole_bytes()))
 }
 
-fn safe_usize(base: usize, sub: usize) -> usize {
-	if sub > base {
-		0usize
-	} else {
-		base - sub
+// Chunks of a PNG that decoding can resume within, found by seeking past the image data
+struct Index {
+	width: usize,
+	height: usize,
+	multiplier: usize,
+	banner_rows: usize,
+	// Offset of the first IDAT chunk
+	data_offset: Option<u64>,
+	flush_points: Vec<FlushPoint>,
+	holes: Option<HoleMap>,
+	animated: bool
+}
+
+fn index<R: Read + Seek>(source: &mut R, limits: &Limits) -> io::Result<Index> {
+	let mut index = Index {
+		width: 0,
+		height: 0,
+		multiplier: 0,
+		banner_rows: 0,
+		data_offset: None,
+		flush_points: Vec::new(),
+		holes: None,
+		animated: false
+	};
+	// The hole map is checked once the frames & any payload chunks are known
+	let mut frame_count = 0;
+	let mut payload = false;
+	let mut hole_data: Option<Vec<u8>> = None;
+	loop {
+		let offset = source.stream_position()?;
+		let mut header = [0u8; 8];
+		source.read_exact(&mut header)?;
+		let length = read_be_u32(&mut &header[..4]) as usize;
+		limits.check_chunk(&header[4..8], length as u64)?;
+		let mut data = vec![0u8; 0];
+		match &header[4..8] {
+			b"IEND" => {
+				if let Some(data) = hole_data {
+					let capacity = match payload {
+						true => u64::MAX,
+						false => capacity(index.width, index.height, index.multiplier, frame_count)
+					};
+					index.holes = Some(read_hole_map(&data, capacity)?);
+				}
+				return Ok(index);
+			},
+			b"IDAT" => {
+				index.data_offset = index.data_offset.or(Some(offset));
+			},
+			b"acTL" => {
+				index.animated = true;
+				if length >= 8 {
+					data.resize(8, 0);
+					source.read_exact(&mut data)?;
+					frame_count = read_be_u32(&mut &data[..4]);
+				}
+			},
+			header if header == payload::CHUNK_TYPE => payload = true,
+			b"IHDR" if length >= 13 => {
+				data.resize(13, 0);
+				source.read_exact(&mut data)?;
+				let ihdr = Ihdr::parse(&data)?;
+				index.width = ihdr.width as usize;
+				index.height = ihdr.height as usize;
+				index.multiplier = multiplier(&ihdr)?;
+			},
+			header if header == banner::CHUNK_TYPE && length == 4 => {
+				data.resize(length, 0);
+				source.read_exact(&mut data)?;
+				index.banner_rows = banner::rows(&data).unwrap() as usize;
+			},
+			header if header == resume::INDEX_CHUNK_TYPE => {
+				data = read_private(source, limits, header, length)?;
+				index.flush_points = resume::read_index(&data);
+			},
+			header if header == sparse::CHUNK_TYPE => {
+				data = read_private(source, limits, header, length)?;
+				hole_data = Some(data.clone());
+			},
+			_ => ()
+		};
+		// Rest of the chunk & crc
+		source.seek(SeekFrom::Current((length - data.len() + 4) as i64))?;
 	}
 }
 
+// Decodes a PNG while checkpointing at each flush point listed by an encode with --resume, continuing from the last
+// checkpoint of resume (images without flush points are decoded from the start as usual)
+pub fn decode_resumable<R: Read + Seek>(
+	mut source: R,
+	size: u64,
+	write_file: &mut WriteFile,
+	buffer_size: usize,
+	limits: &Limits,
+	resume: &mut Resume
+	) -> io::Result<u64> {
+	util::read_header(&mut source)?;
+	let index = index(&mut source, limits)?;
+	let data_offset = match index.data_offset {
+		Some(offset) if !index.animated && !index.flush_points.is_empty() => offset,
+		_ => {
+			source.seek(SeekFrom::Start(0))?;
+			let holes = decode(source, size, write_file, buffer_size, limits)?;
+			resume.finish()?;
+			return Ok(holes);
+		}
+	};
+
+	let checkpoint = resume.checkpoint.clone().unwrap_or(Checkpoint {
+		input_offset: data_offset,
+		..Checkpoint::default()
+	});
+	let mut progress_bar = util::ProgressBar::new(size, "Converting from PNG");
+	source.seek(SeekFrom::Start(checkpoint.input_offset))?;
+	let mut decoder = Decoder::resume(&index, checkpoint.input_offset, checkpoint.uncompressed, buffer_size, limits)?;
+	let chunk_size = decoder.chunk_size();
+	let mut flush_points = index.flush_points.iter().filter(|point| point.uncompressed > checkpoint.uncompressed).peekable();
+	let mut buffer = input_buffer(buffer_size);
+	while !decoder.ended() {
+		let read_size = source.read(&mut buffer)?;
+		if read_size == 0 {
+			break;
+		}
+		decoder.push_sparse(&buffer[..read_size], write_file)?;
+
+		// Continues from the last flush point passed after a crash
+		let uncompressed = decoder.uncompressed();
+		let mut passed = None;
+		while let Some(point) = flush_points.peek() {
+			if point.uncompressed > uncompressed {
+				break;
+			}
+			passed = flush_points.next();
+		}
+		if let Some(point) = passed {
+			let output = output_size(chunk_size, point.uncompressed, index.banner_rows);
+			write_file.sync()?;
+			resume.save(Checkpoint {
+				input_offset: point.offset,
+				output_offset: index.holes.as_ref().map_or(output, |holes| holes.unpacked(output)),
+				uncompressed: point.uncompressed,
+				..Checkpoint::default()
+			})?;
+		}
+		progress!({progress_bar.set_tick(decoder.total_in());});
+	}
+	decoder.finish()?;
+	write_file.flush()?;
+	resume.finish()?;
+	progress!({progress_bar.complete();});
+	Ok(index.holes.map_or(0, |holes| holes.hole_bytes()))
+}
+
+// Finds the holes listed by a PNG of a sparse file ahead of decoding it (the chunk comes before the image data),
+// leaving the source at its start (decoding checks them against the image)
+pub fn read_holes<R: Read + Seek>(source: &mut R, limits: &Limits) -> io::Result<Option<HoleMap>> {
+	match read_ahead(source, limits, sparse::CHUNK_TYPE)? {
+		Some(data) => Ok(Some(read_hole_map(&data, u64::MAX)?)),
+		None => Ok(None)
+	}
+}
+
+// Most bytes of data an image holds across its frames (each frame fits within the image)
+fn capacity(width: usize, height: usize, multiplier: usize, frame_count: u32) -> u64 {
+	(width as u64)
+		.saturating_mul(height as u64)
+		.saturating_mul(multiplier as u64)
+		.saturating_mul(std::cmp::max(1, frame_count) as u64)
+}
+
+fn read_hole_map(data: &[u8], capacity: u64) -> io::Result<HoleMap> {
+	HoleMap::read(data, capacity).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid hole map"))
+}
+
+// Finds the attributes captured by --preserve ahead of decoding (the chunk comes before the image data), leaving the
+// source at its start
+pub fn read_metadata<R: Read + Seek>(source: &mut R, limits: &Limits) -> io::Result<Option<Metadata>> {
+	match read_ahead(source, limits, metadata::CHUNK_TYPE)? {
+		Some(data) => Ok(Some(Metadata::read(&data).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?)),
+		None => Ok(None)
+	}
+}
+
+// Data of the first chunk of chunk_type before the image data, seeking past every other chunk
+fn read_ahead<R: Read + Seek>(source: &mut R, limits: &Limits, chunk_type: &[u8]) -> io::Result<Option<Vec<u8>>> {
+	util::read_header(source)?;
+	let mut found = None;
+	loop {
+		let mut header = [0u8; 8];
+		source.read_exact(&mut header)?;
+		let length = read_be_u32(&mut &header[..4]) as usize;
+		limits.check_chunk(&header[4..8], length as u64)?;
+		match &header[4..8] {
+			b"IDAT" | b"IEND" => break,
+			header if header == chunk_type => {
+				found = Some(read_private(source, limits, header, length)?);
+				break;
+			},
+			_ => {
+				source.seek(SeekFrom::Current((length + 4) as i64))?;
+			}
+		};
+	}
+	source.seek(SeekFrom::Start(0))?;
+	Ok(found)
+}
+
+// Data of a private chunk read whole (checked against the limit of those, and growing with the data actually read)
+fn read_private<R: Read>(source: &mut R, limits: &Limits, chunk_type: &[u8], length: usize) -> io::Result<Vec<u8>> {
+	limits.check_private_chunk(chunk_type, length as u64)?;
+	chunk::read_data(source, length as u64)
+}
+
+// Pieces the PNG is read in (buffer_size limits the inflated data, the input only needs about a chunk at a time)
+fn input_buffer(buffer_size: usize) -> Vec<u8> {
+	vec![0u8; std::cmp::max(1, std::cmp::min(buffer_size, util::IDAT_SIZE))]
+}
+
+fn safe_usize(base: usize, sub: usize) -> usize {
+	base.saturating_sub(sub)
+}
+
+// Bytes of each pixel, which only images of whole bytes (not palettes or depths under 8 bits) have
+fn multiplier(ihdr: &Ihdr) -> io::Result<usize> {
+	ihdr.bytes_per_pixel().ok_or_else(|| io::Error::new(
+		io::ErrorKind::InvalidData,
+		format!("unsupported color type {} at {}-bit", ihdr.color_type, ihdr.bit_depth)
+	))
+}
+
 fn read_be_u32(input: &mut &[u8]) -> u32 {
 	let (int_bytes, rest) = input.split_at(std::mem::size_of::<u32>());
 	*input = rest;
 	u32::from_be_bytes(int_bytes.try_into().unwrap())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::{Arc, Mutex};
+
+	use crate::png_convert::{encode, sink, EncodeOptions};
+	use crate::util::{Exceeded, Hole};
+	use crate::util::testing::bytes;
+
+	// Image of data with small IDAT chunks and chunks before & after them (frames splits it into an APNG)
+	fn png(data: &[u8], frames: Option<u64>) -> Vec<u8> {
+		sparse_png(data, frames, None)
+	}
+
+	// Image of the data of a file with holes (which the data is without)
+	fn sparse_png(data: &[u8], frames: Option<u64>, holes: Option<HoleMap>) -> Vec<u8> {
+		let buffer = Arc::new(Mutex::new(Vec::new()));
+		let mut options = EncodeOptions::for_size(data.len() as u64);
+		options.holes = holes;
+		options.idat_size = 500;
+		options.mtime = Some(1593820800);
+		options.tags = vec![(String::from("title"), String::from("split"))];
+		if let Some(frames) = frames {
+			options.frames = Some(frames);
+			options.height = options.height.div_ceil(frames);
+		}
+		encode(data, sink::new(options.format, WriteFile::memory(buffer.clone())), &options).unwrap();
+		let png = buffer.lock().unwrap().clone();
+		png
+	}
+
+	// Events of feeding the PNG in pieces of sizes (cycling through them), with the data of every event merged
+	fn feed_in(png: &[u8], sizes: &[usize]) -> Vec<Event> {
+		let mut decoder = Decoder::new(4096);
+		let mut events: Vec<Event> = Vec::new();
+		let mut rest = png;
+		for &size in sizes.iter().cycle() {
+			if rest.is_empty() {
+				break;
+			}
+			let size = std::cmp::min(size, rest.len());
+			for event in decoder.feed(&rest[..size]).unwrap() {
+				match (events.last_mut(), event) {
+					(Some(Event::Data(data)), Event::Data(more)) => data.extend_from_slice(&more),
+					(_, event) => events.push(event)
+				};
+			}
+			rest = &rest[size..];
+		}
+		decoder.finish().unwrap();
+		events
+	}
+
+	#[test]
+	fn pieces_of_any_size_decode_the_same() {
+		for frames in [None, Some(2)] {
+			let data = bytes(40, 5000);
+			let png = png(&data, frames);
+			let expected = feed_in(&png, &[png.len()]);
+			let decoded: Vec<u8> = expected.iter().filter_map(|event| match event {
+				Event::Data(data) => Some(data.clone()),
+				_ => None
+			}).flatten().collect();
+			assert!(decoded[..data.len()] == *data, "{:?} frames: data differs", frames);
+
+			for size in (1..=64).chain([97, 500, 4096]) {
+				assert!(feed_in(&png, &[size]) == expected, "{:?} frames: pieces of {} bytes", frames, size);
+			}
+			assert!(feed_in(&png, &[1, 3, 2, 5, 7, 4]) == expected, "{:?} frames: uneven pieces", frames);
+		}
+	}
+
+	#[test]
+	fn splitting_anywhere_decodes_the_same() {
+		let png = png(&bytes(40, 2000), None);
+		let expected = feed_in(&png, &[png.len()]);
+		// Every split of a length, type, chunk start or crc
+		for split in 1..png.len() {
+			assert!(feed_in(&png, &[split, png.len()]) == expected, "split at byte {}", split);
+		}
+	}
+
+	#[test]
+	fn pushing_single_bytes_writes_the_same() {
+		let data = bytes(40, 3000);
+		let png = png(&data, None);
+		let mut whole = Vec::new();
+		Decoder::new(4096).push(&png, &mut whole).unwrap();
+		let mut decoder = Decoder::new(4096);
+		let mut bytes = Vec::new();
+		for byte in png.chunks(1) {
+			decoder.push(byte, &mut bytes).unwrap();
+		}
+		decoder.finish().unwrap();
+		assert!(bytes == whole);
+		assert!(whole[..data.len()] == *data);
+	}
+
+	// 16-bit RGBA PNG of the widest rows below a pgBN chunk claiming 0xffffffff rows, listing a flush point so
+	// decode_resumable continues from its image data
+	fn crafted_banner() -> Vec<u8> {
+		let buffer = Arc::new(Mutex::new(Vec::new()));
+		let mut write_file = WriteFile::memory(buffer.clone());
+		let mut ihdr = Vec::new();
+		ihdr.extend_from_slice(&0x7fff_ffffu32.to_be_bytes());
+		ihdr.extend_from_slice(&1u32.to_be_bytes());
+		ihdr.extend_from_slice(&[16, 6, 0, 0, 0]);
+		write_file.write_header().unwrap();
+		write_file.write_chunk(b"IHDR", &ihdr).unwrap();
+		write_file.write_chunk(banner::CHUNK_TYPE, &0xffff_ffffu32.to_be_bytes()).unwrap();
+		let offset = write_file.position();
+		write_file.write_chunk(b"IDAT", &[0x78, 0x01, 0x01, 0x00, 0x00, 0xff, 0xff]).unwrap();
+		write_file.write_chunk(resume::INDEX_CHUNK_TYPE, &resume::index_data(&[FlushPoint { offset: offset, uncompressed: 0 }])).unwrap();
+		write_file.write_chunk(b"IEND", b"").unwrap();
+		write_file.flush().unwrap();
+		let png = buffer.lock().unwrap().clone();
+		png
+	}
+
+	#[test]
+	fn crafted_banners_fail_instead_of_overflowing() {
+		let png = crafted_banner();
+		assert!(Decoder::new(4096).push(&png, &mut Vec::new()).is_err());
+
+		let mut write_file = WriteFile::memory(Arc::new(Mutex::new(Vec::new())));
+		assert!(decode(&png[..], png.len() as u64, &mut write_file, 4096, &Limits::default()).is_err());
+
+		let sidecar = std::env::temp_dir().join(format!("pngifier-crafted-{}.pngifier-resume", std::process::id()));
+		let mut resume = Resume::load(sidecar.clone(), String::from("crafted")).unwrap();
+		let result = decode_resumable(io::Cursor::new(&png), png.len() as u64, &mut write_file, 4096, &Limits::default(), &mut resume);
+		assert!(result.is_err());
+		assert!(!sidecar.exists());
+	}
+
+	#[test]
+	fn limits_bound_the_buffer() {
+		let limits = Limits {
+			max_buffer_bytes: Some(64 * 1024),
+			..Limits::default()
+		};
+		let mut decoder = Decoder::with_limits(usize::MAX / 2, limits);
+		assert_eq!(decoder.buffer.len(), 64 * 1024);
+
+		let data = bytes(40, 100_000);
+		let mut output = Vec::new();
+		decoder.push(&png(&data, None), &mut output).unwrap();
+		decoder.finish().unwrap();
+		assert!(output[..data.len()] == *data);
+	}
+
+	#[test]
+	fn holes_are_left_around_the_data() {
+		let data = bytes(44, 5000);
+		let holes = vec![Hole { offset: 0, length: 1000 }, Hole { offset: 3000, length: 3000 }, Hole { offset: 9000, length: 500 }];
+		let mut file = vec![0u8; 1000];
+		file.extend_from_slice(&data[..2000]);
+		file.extend_from_slice(&[0u8; 3000]);
+		file.extend_from_slice(&data[2000..]);
+		file.extend_from_slice(&[0u8; 500]);
+		// Two frames only hold the data together
+		for frames in [None, Some(2)] {
+			let png = sparse_png(&data, frames, HoleMap::new(9500, holes.clone()));
+			let mut output = Vec::new();
+			let mut decoder = Decoder::new(4096);
+			decoder.push(&png, &mut output).unwrap();
+			decoder.finish().unwrap();
+			assert!(output == file, "{:?} frames", frames);
+		}
+	}
+
+	#[test]
+	fn holes_count_towards_the_output_limit() {
+		let data = bytes(44, 5000);
+		let png = sparse_png(&data, None, HoleMap::new(1 << 40, vec![Hole { offset: 0, length: (1 << 40) - 5000 }]));
+		let limits = Limits {
+			max_output_bytes: Some(1024 * 1024),
+			..Limits::default()
+		};
+		let error = Decoder::with_limits(4096, limits).push(&png, &mut io::sink()).unwrap_err();
+		assert_eq!(Exceeded::find(&error), Some(&Exceeded::OutputBytes { limit: 1024 * 1024 }));
+		let error = Decoder::with_limits(4096, limits).feed(&png).unwrap_err();
+		assert_eq!(Exceeded::find(&error), Some(&Exceeded::OutputBytes { limit: 1024 * 1024 }));
+	}
+
+	#[test]
+	fn hole_maps_must_fit_the_image() {
+		let data = bytes(44, 5000);
+		// The rest of the file is far more than the image holds
+		let png = sparse_png(&data, None, HoleMap::new(1 << 40, vec![Hole { offset: 0, length: 1 }]));
+		let error = Decoder::new(4096).push(&png, &mut io::sink()).unwrap_err();
+		assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+		assert!(HoleMap::read(&HoleMap::new(1 << 40, vec![Hole { offset: 0, length: 1 }]).unwrap().to_data(), u64::MAX).is_some());
+	}
+}
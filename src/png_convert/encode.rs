@@ -1,147 +1,232 @@
-extern crate flate2;
-use flate2::read::ZlibEncoder;
-use flate2::Compression;
-
-use std::io::{self, Read, Write};
+use std::io::{self, Read};
 
 use crate::util;
-use util::{WriteFile, ReadFile};
+use util::{color_type, dimensions, HoleMap, Metadata};
+use crate::png_convert::banner::Banner;
+use crate::png_convert::payload::Container;
+use crate::png_convert::resume::{Checkpoint, Resume};
+use crate::png_convert::sink::{ImageSink, OutputFormat};
+
+// Layout of the image a file is encoded into
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+	pub width: u64,
+	pub height: u64,
+	pub chunk_size: u64,
+	pub max_bytes: u64,
+	pub buffer_size: usize,
+	// Size of the data of each IDAT (or fdAT) chunk, independent of buffer_size
+	pub idat_size: usize,
+	pub bit_depth: u8,
+	pub color_type: u8,
+	pub trim: bool,
+	pub format: OutputFormat,
+	// Where the bytes of the file go (the layout of chunk containers is a 1x1 placeholder)
+	pub container: Container,
+	// Frames of an APNG, each of width x height pixels
+	pub frames: Option<u64>,
+	// Rows rendered above the data (height only counts the rows of data)
+	pub banner: Option<Banner>,
+	// Seconds since the unix epoch stored in a tIME chunk (none keeps the output reproducible)
+	pub mtime: Option<u64>,
+	// Holes of a sparse input, which is read without them (max_bytes only counts the rest)
+	pub holes: Option<HoleMap>,
+	// Attributes of the input captured by --preserve
+	pub metadata: Option<Metadata>,
+	// Keys & values written as iTXt chunks
+	pub tags: Vec<(String, String)>,
+	// Name of the file in the ZIP archive of --polyglot zip
+	pub zip: Option<String>
+}
 
-#[derive(Debug)]
-struct Transformer<R: Read> {
-	source: R,
+impl EncodeOptions {
+	// Default layout of the command line for max_bytes of input: a square 8-bit RGB PNG
+	pub fn for_size(max_bytes: u64) -> Self {
+		let color_type = 2u8;
+		let bit_depth = 8u8;
+		let bytes_per_px = color_type::total_bytes(color_type, bit_depth) as u64;
+		let width = std::cmp::max(1, ((max_bytes / bytes_per_px) as f64).sqrt() as u64);
+		Self {
+			width: width,
+			// A square root of the input can't overflow a row
+			height: dimensions::rows_for(max_bytes, width, bytes_per_px).unwrap(),
+			chunk_size: width * bytes_per_px,
+			max_bytes: max_bytes,
+			buffer_size: (width * bytes_per_px) as usize,
+			idat_size: util::IDAT_SIZE,
+			bit_depth: bit_depth,
+			color_type: color_type,
+			trim: false,
+			format: OutputFormat::Png,
+			container: Container::Pixels,
+			frames: None,
+			banner: None,
+			mtime: None,
+			holes: None,
+			metadata: None,
+			tags: Vec::new(),
+			zip: None
+		}
+	}
+}
+
+// Encodes data pushed into it into the pixel rows of an image, leaving reading the input to whoever drives it
+pub struct Encoder<'a> {
+	sink: Box<dyn ImageSink + 'a>,
 	total_in: u64,
-	chunk_size: u64,
 	capacity: u64,
 	max_bytes: u64,
+	padding_size: usize,
 	trim: bool
 }
 
-impl<R: Read> Read for Transformer<R> {
-	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-
-		let mut read_size = buf.len();
-		// When the capacity is completely filled up
-		if self.total_in == self.capacity {
-			return Ok(0usize);
+impl<'a> Encoder<'a> {
+	// Begins a new image (with its banner)
+	pub fn new(mut sink: Box<dyn ImageSink + 'a>, options: &EncodeOptions) -> io::Result<Self> {
+		sink.begin(options)?;
+		if let Some(banner) = &options.banner {
+			sink.write_pixels(&banner.pixels)?;
 		}
-		// Current data is less than the data of the actual file
-		if self.total_in < self.max_bytes {
-
-			// Too much data (overflowing the chunk)
-			if read_size as u64 > self.chunk_size {
-				read_size = self.chunk_size as usize;
-			}
+		Ok(Self::at(sink, options, 0))
+	}
 
-			// Currently at the position to add a filter
-			if self.total_in % self.chunk_size == 0 {
-				buf[0] = 0u8;
-				read_size = self.source.read(&mut buf[1..read_size])?;
-				self.total_in += read_size as u64; // DO NOT INCLUDE FILTER
-				if read_size != 0 {
-					read_size += 1;
-				}
-				//println!("Apply Filter");
-				return Ok(read_size);
-			}
-			// Normally add data
+	// Continues the image of a checkpoint (the output must already be truncated to its output offset)
+	pub fn restore(mut sink: Box<dyn ImageSink + 'a>, options: &EncodeOptions, checkpoint: &Checkpoint) -> io::Result<Self> {
+		sink.restore(options, checkpoint)?;
+		Ok(Self::at(sink, options, checkpoint.input_offset))
+	}
 
-			// Remaining data to approach chunk size
-			let remaining = self.chunk_size - (self.total_in % self.chunk_size);
-			read_size = remaining as usize;
+	fn at(sink: Box<dyn ImageSink + 'a>, options: &EncodeOptions, total_in: u64) -> Self {
+		Self {
+			sink: sink,
+			total_in: total_in,
+			// Chunk containers hold exactly the input (there are no rows to pad)
+			capacity: match options.container {
+				Container::Pixels => options.chunk_size * options.height * options.frames.unwrap_or(1),
+				Container::Chunk => options.max_bytes
+			},
+			max_bytes: options.max_bytes,
+			padding_size: std::cmp::max(1, options.buffer_size),
+			trim: options.trim
+		}
+	}
 
-			if read_size > buf.len() {
-				read_size = buf.len();
-			}
+	// Bytes of input taken so far
+	pub fn total_in(&self) -> u64 {
+		self.total_in
+	}
 
-			if read_size as u64 + self.total_in > self.max_bytes {
-				read_size = (self.max_bytes - self.total_in) as usize;
-			}
+	// Bytes of pixel data the image holds
+	pub fn capacity(&self) -> u64 {
+		self.capacity
+	}
 
-			read_size = self.source.read(&mut buf[..read_size])?;
-			self.total_in += read_size as u64;
-			return Ok(read_size);
+	// Bytes of input still taken before the image is full
+	pub fn remaining(&self) -> u64 {
+		self.max_bytes.saturating_sub(self.total_in)
+	}
 
+	// Takes as much of data as still fits, returning how much was taken
+	pub fn push(&mut self, data: &[u8]) -> io::Result<usize> {
+		let size = std::cmp::min(data.len() as u64, self.remaining()) as usize;
+		if size != 0 {
+			self.sink.write_pixels(&data[..size])?;
+			self.total_in += size as u64;
 		}
+		Ok(size)
+	}
 
-		if self.trim {
-			return Ok(0usize);
+	// Ends the data written so far at a point encoding can continue from after a crash (None if the sink can't)
+	pub fn checkpoint(&mut self) -> io::Result<Option<Checkpoint>> {
+		if self.total_in >= self.capacity {
+			return Ok(None);
 		}
+		Ok(self.sink.checkpoint()?.map(|mut checkpoint| {
+			checkpoint.input_offset = self.total_in;
+			checkpoint
+		}))
+	}
 
-		// Fill to capacity
-		if read_size as u64 + self.total_in > self.capacity {
-			read_size = (self.capacity - self.total_in) as usize;
-		}
-		for i in buf.iter_mut() {
-			*i = 0;
+	// Fills the rest of the image with zeros (unless trimming) & writes everything following the pixel data
+	pub fn finish(mut self) -> io::Result<()> {
+		if !self.trim {
+			let zeros = vec![0u8; self.padding_size];
+			while self.total_in < self.capacity {
+				let size = std::cmp::min(zeros.len() as u64, self.capacity - self.total_in) as usize;
+				self.sink.write_pixels(&zeros[..size])?;
+				self.total_in += size as u64;
+			}
 		}
-		self.total_in += read_size as u64;
-		return Ok(read_size);
-
+		self.sink.finish()
 	}
 }
 
-// Encodes a file into a PNG
-pub fn encode(
-	read_file: &mut ReadFile,
-	write_file: &mut WriteFile,
-	width: u64, 
-	height: u64, 
-	chunk_size: u64, 
-	max_bytes: u64, 
-	buffer_size: usize,
-	bit_depth: u8, 
-	color_type: u8,
-	trim: bool
+// Encodes a file into the pixel rows of an image
+pub fn encode<'a, R: Read>(
+	source: R,
+	sink: Box<dyn ImageSink + 'a>,
+	options: &EncodeOptions
 	) -> io::Result<()>{
+	encode_from(source, sink, options, None)
+}
 
-	let transformer = Transformer {
-			source: read_file,
-			total_in: 0,
-			chunk_size: chunk_size,
-			capacity: chunk_size * height,
-			max_bytes: max_bytes,
-			trim: trim
-	};
-	let mut deflater = ZlibEncoder::new(transformer, Compression::fast());
-
-	let mut buffer = vec![0u8; buffer_size];
-
-	let mut progress_bar = util::ProgressBar::new(chunk_size * height + 3, "Encoding as PNG");
+// Encodes a file while checkpointing every interval bytes of input, continuing from the last checkpoint of resume
+// (the source must already be at the input offset of that checkpoint & the output truncated to its output offset)
+pub fn encode_resumable<'a, R: Read>(
+	source: R,
+	sink: Box<dyn ImageSink + 'a>,
+	options: &EncodeOptions,
+	resume: &mut Resume,
+	interval: u64
+	) -> io::Result<()>{
+	encode_from(source, sink, options, Some((resume, interval)))
+}
 
-	write_file.write_header()?;
+fn encode_from<'a, R: Read>(
+	mut source: R,
+	sink: Box<dyn ImageSink + 'a>,
+	options: &EncodeOptions,
+	mut resume: Option<(&mut Resume, u64)>
+	) -> io::Result<()>{
 
-	progress!({progress_bar.tick(1);});
-	
-	write_file.write_chunk(
-		b"IHDR",
-		&[
-			&(width as u32).to_be_bytes()[..4],
-			&(height as u32).to_be_bytes()[..4],
-			&[bit_depth, color_type, 0u8, 0u8, 0u8][..]
-		].concat()
-	)?;
-	progress!({progress_bar.tick(1);});
+	let mut encoder = match resume.as_ref().and_then(|(resume, _)| resume.checkpoint.as_ref()) {
+		Some(checkpoint) => Encoder::restore(sink, options, checkpoint)?,
+		None => Encoder::new(sink, options)?
+	};
 
+	let mut buffer = vec![0u8; std::cmp::max(1, options.buffer_size)];
 
-	let mut size = deflater.read(&mut buffer)?;	
-	while size != 0 {
-		progress!({progress_bar.set_tick(deflater.get_ref().total_in + 2);});
-		write_file.write_chunk(
-			b"IDAT",
-			&buffer[..size]
-		)?;
+	let mut progress_bar = util::ProgressBar::new(encoder.capacity() + 2, &format!("Encoding as {}", options.format));
+	progress!({progress_bar.set_tick(encoder.total_in() + 1);});
 
-		size = deflater.read(&mut buffer)?;
+	let mut next_checkpoint = resume.as_ref().map_or(u64::MAX, |(_, interval)| encoder.total_in() + interval);
+	while encoder.remaining() != 0 {
+		let read_size = std::cmp::min(buffer.len() as u64, encoder.remaining()) as usize;
+		let size = source.read(&mut buffer[..read_size])?;
+		if size == 0 {
+			break;
+		}
+		encoder.push(&buffer[..size])?;
+		progress!({progress_bar.set_tick(encoder.total_in() + 1);});
+
+		if encoder.total_in() >= next_checkpoint {
+			if let Some((resume, interval)) = resume.as_mut() {
+				next_checkpoint = match encoder.checkpoint()? {
+					Some(checkpoint) => {
+						resume.save(checkpoint)?;
+						encoder.total_in() + *interval
+					},
+					None => u64::MAX
+				};
+			}
+		}
 	}
 
-	write_file.write_chunk(
-		b"IEND",
-		b""
-	)?;
+	encoder.finish()?;
+	if let Some((resume, _)) = resume.as_mut() {
+		resume.finish()?;
+	}
 	progress!({progress_bar.complete();});
 
-	write_file.flush()?;
-
 	Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,77 @@
+use std::io;
+
+// Reverses the filter of a scanline in place (prev is the unfiltered previous scanline)
+pub fn unfilter(filter_type: u8, row: &mut [u8], prev: &[u8], bpp: usize) -> io::Result<()> {
+	match filter_type {
+		0 => (),
+		1 => {
+			for i in bpp..row.len() {
+				row[i] = row[i].wrapping_add(row[i - bpp]);
+			}
+		},
+		2 => {
+			for i in 0..row.len() {
+				row[i] = row[i].wrapping_add(prev[i]);
+			}
+		},
+		3 => {
+			for i in 0..row.len() {
+				let left = if i >= bpp { row[i - bpp] as u16 } else { 0 };
+				row[i] = row[i].wrapping_add(((left + prev[i] as u16) / 2) as u8);
+			}
+		},
+		4 => {
+			for i in 0..row.len() {
+				let left = if i >= bpp { row[i - bpp] } else { 0 };
+				let upper_left = if i >= bpp { prev[i - bpp] } else { 0 };
+				row[i] = row[i].wrapping_add(paeth(left, prev[i], upper_left));
+			}
+		},
+		_ => return Err(io::Error::from(io::ErrorKind::InvalidData))
+	};
+	Ok(())
+}
+
+// Filters a scanline with the given filter type into out
+pub fn filter(filter_type: u8, row: &[u8], prev: &[u8], bpp: usize, out: &mut [u8]) {
+	for i in 0..row.len() {
+		let left = if i >= bpp { row[i - bpp] } else { 0 };
+		let upper_left = if i >= bpp { prev[i - bpp] } else { 0 };
+		out[i] = match filter_type {
+			1 => row[i].wrapping_sub(left),
+			2 => row[i].wrapping_sub(prev[i]),
+			3 => row[i].wrapping_sub(((left as u16 + prev[i] as u16) / 2) as u8),
+			4 => row[i].wrapping_sub(paeth(left, prev[i], upper_left)),
+			_ => row[i]
+		};
+	}
+}
+
+// Picks the filter with the smallest sum of absolute differences and writes the filter byte and scanline into out
+pub fn filter_adaptive(row: &[u8], prev: &[u8], bpp: usize, out: &mut [u8]) {
+	let mut candidate = vec![0u8; row.len()];
+	let mut best = u64::MAX;
+	for filter_type in 0..5u8 {
+		filter(filter_type, row, prev, bpp, &mut candidate);
+		let score: u64 = candidate.iter().map(|&b| (b as i8 as i16).unsigned_abs() as u64).sum();
+		if score < best {
+			best = score;
+			out[0] = filter_type;
+			out[1..].copy_from_slice(&candidate);
+		}
+	}
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+	let p = a as i16 + b as i16 - c as i16;
+	let pa = (p - a as i16).abs();
+	let pb = (p - b as i16).abs();
+	let pc = (p - c as i16).abs();
+	if pa <= pb && pa <= pc {
+		a
+	} else if pb <= pc {
+		b
+	} else {
+		c
+	}
+}
@@ -0,0 +1,180 @@
+extern crate flate2;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use std::io::{self, Read, Write};
+
+use crate::util;
+use crate::png_convert::{filter, sink, EncodeOptions, OutputFormat};
+use crate::png_convert::payload::Container;
+use util::color_type::*;
+use util::png::{Ihdr, Time};
+use util::{Chunk, ChunkData, Limits, WriteFile};
+
+// Fully decoded, non-interlaced PNG of 8-bit or 16-bit samples
+pub struct Image {
+	pub width: u32,
+	pub height: u32,
+	pub bit_depth: u8,
+	pub color_type: u8,
+	// Unfiltered scanlines without filter bytes
+	pub pixels: Vec<u8>,
+	// Ancillary chunks found before the image data (ie: gAMA, sRGB, iCCP) as (type, data)
+	pub ancillary: Vec<(Vec<u8>, Vec<u8>)>
+}
+
+impl Image {
+	pub fn bytes_per_pixel(&self) -> usize {
+		total_bytes(self.color_type, self.bit_depth)
+	}
+
+	pub fn row_bytes(&self) -> usize {
+		self.width as usize * self.bytes_per_pixel()
+	}
+
+	pub fn read<R: Read>(source: R) -> io::Result<Self> {
+		Self::read_limited(source, &Limits::default())
+	}
+
+	// Reads an untrusted PNG, failing as soon as it runs into any of limits
+	pub fn read_limited<R: Read>(mut source: R, limits: &Limits) -> io::Result<Self> {
+		util::read_header(&mut source)?;
+		let mut image = Image {
+			width: 0,
+			height: 0,
+			bit_depth: 0,
+			color_type: 0,
+			pixels: Vec::new(),
+			ancillary: Vec::new()
+		};
+
+		let mut compressed: Vec<u8> = Vec::new();
+		let mut found_header = false;
+		while let Some(chunk) = Chunk::limited(&mut source, limits)? {
+			if !chunk.verify_crc() {
+				return Err(invalid_data());
+			}
+			let data = chunk.get_data();
+			match chunk.get_header() {
+				b"IHDR" => {
+					let ihdr = Ihdr::parse(data)?;
+					if ihdr.bytes_per_pixel().is_none() {
+						return Err(invalid_data());
+					}
+					image.width = ihdr.width;
+					image.height = ihdr.height;
+					image.bit_depth = ihdr.bit_depth;
+					image.color_type = ihdr.color_type;
+					limits.check_dimensions(image.width as u64, image.height as u64)?;
+					found_header = true;
+				},
+				b"IDAT" => compressed.extend_from_slice(data),
+				b"IEND" => break,
+				header => {
+					// Lowercase first letter marks an ancillary chunk
+					if compressed.is_empty() && header[0] & 0x20 != 0 {
+						image.ancillary.push((header.to_vec(), data.to_vec()));
+					}
+				}
+			};
+		}
+		if !found_header {
+			return Err(invalid_data());
+		}
+
+		let bpp = image.bytes_per_pixel();
+		let row_bytes = image.row_bytes();
+		// Inflates no further than the scanlines of the header, growing with what is actually inflated
+		let expected = (row_bytes + 1) * image.height as usize;
+		let mut inflater = ZlibDecoder::new(&compressed[..]).take(expected as u64);
+		let mut raw = Vec::new();
+		while inflater.by_ref().take(util::IDAT_SIZE as u64).read_to_end(&mut raw)? != 0 {
+			limits.check_ratio(raw.len() as u64, inflater.get_ref().total_in())?;
+		}
+		if raw.len() < expected {
+			return Err(invalid_data());
+		}
+
+		image.pixels = vec![0u8; row_bytes * image.height as usize];
+		let empty = vec![0u8; row_bytes];
+		for y in 0..image.height as usize {
+			let line = &raw[y * (row_bytes + 1)..(y + 1) * (row_bytes + 1)];
+			let (done, rest) = image.pixels.split_at_mut(y * row_bytes);
+			let prev = if y == 0 { &empty[..] } else { &done[(y - 1) * row_bytes..] };
+			let row = &mut rest[..row_bytes];
+			row.copy_from_slice(&line[1..]);
+			filter::unfilter(line[0], row, prev, bpp)?;
+		}
+		Ok(image)
+	}
+
+	// Replaces the modification time of the image (only kept by PNGs)
+	pub fn set_time(&mut self, seconds: u64) {
+		self.ancillary.retain(|(header, _)| header != b"tIME");
+		self.ancillary.push((Time::CHUNK_TYPE.to_vec(), Time::from_seconds(seconds).to_data()));
+	}
+
+	pub fn write(&self, write_file: &mut WriteFile) -> io::Result<()> {
+		write_file.write_header()?;
+		write_file.write_typed(&Ihdr::new(self.width, self.height, self.bit_depth, self.color_type))?;
+		for (header, data) in self.ancillary.iter() {
+			write_file.write_chunk(header, data)?;
+		}
+
+		let bpp = self.bytes_per_pixel();
+		let row_bytes = self.row_bytes();
+		let mut deflater = ZlibEncoder::new(Vec::new(), Compression::default());
+		let mut line = vec![0u8; row_bytes + 1];
+		let empty = vec![0u8; row_bytes];
+		for y in 0..self.height as usize {
+			let row = &self.pixels[y * row_bytes..(y + 1) * row_bytes];
+			let prev = if y == 0 { &empty[..] } else { &self.pixels[(y - 1) * row_bytes..y * row_bytes] };
+			filter::filter_adaptive(row, prev, bpp, &mut line);
+			deflater.write_all(&line)?;
+		}
+		let compressed = deflater.finish()?;
+		for data in compressed.chunks(util::IDAT_SIZE) {
+			write_file.write_chunk(b"IDAT", data)?;
+		}
+
+		write_file.write_chunk(b"IEND", b"")?;
+		write_file.flush()
+	}
+
+	// Writes the image in another container (ancillary chunks are only kept by PNGs)
+	pub fn write_as(&self, format: OutputFormat, write_file: &mut WriteFile) -> io::Result<()> {
+		if format == OutputFormat::Png {
+			return self.write(write_file);
+		}
+		let size = self.pixels.len() as u64;
+		let options = EncodeOptions {
+			width: self.width as u64,
+			height: self.height as u64,
+			chunk_size: self.row_bytes() as u64,
+			max_bytes: size,
+			buffer_size: util::IDAT_SIZE,
+			idat_size: util::IDAT_SIZE,
+			bit_depth: self.bit_depth,
+			color_type: self.color_type,
+			trim: false,
+			format: format,
+			container: Container::Pixels,
+			frames: None,
+			banner: None,
+			mtime: None,
+			holes: None,
+			metadata: None,
+			tags: Vec::new(),
+			zip: None
+		};
+		let mut sink = sink::new(format, write_file);
+		sink.begin(&options)?;
+		sink.write_pixels(&self.pixels)?;
+		sink.finish()
+	}
+}
+
+fn invalid_data() -> io::Error {
+	io::Error::from(io::ErrorKind::InvalidData)
+}
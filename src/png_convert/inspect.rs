@@ -0,0 +1,229 @@
+use std::io::{self, Read};
+use std::fmt;
+use std::convert::TryInto;
+
+use crate::util;
+use crate::png_convert::{banner, payload, polyglot};
+use util::color_type::*;
+use util::png::Ihdr;
+use util::{metadata, sparse, Chunk, ChunkData, Chunks, HoleMap, Metadata};
+
+// Consecutive chunks of the same type
+#[derive(Debug)]
+pub struct ChunkRun {
+	pub chunk_type: String,
+	pub offset: u64,
+	pub count: u64,
+	pub length: u64,
+	pub crc_valid: bool
+}
+
+#[derive(Debug)]
+pub struct Inspection {
+	pub width: u32,
+	pub height: u32,
+	pub bit_depth: u8,
+	pub color_type: u8,
+	// Frames announced by the acTL chunk of an APNG
+	pub frames: Option<u32>,
+	// Rows taken up by a --banner
+	pub banner_rows: Option<u32>,
+	// Holes of a sparse input left out of the image data
+	pub holes: Option<HoleMap>,
+	// Attributes of the input captured by --preserve
+	pub metadata: Option<Metadata>,
+	// Compressed bytes of a chunk container (0 when the file is in the pixels)
+	pub payload_bytes: u64,
+	// Name, deflated & original size of the ZIP entry of a polyglot
+	pub zip: Option<(String, u64, u64)>,
+	// Bytes following IEND (the central directory of a ZIP polyglot)
+	pub trailing_bytes: u64,
+	pub size: u64,
+	pub chunks: Vec<ChunkRun>
+}
+
+impl Inspection {
+	// Bytes of payload the pixels of the image can hold
+	pub fn capacity(&self) -> u64 {
+		if !type_exists(self.color_type) {
+			return 0;
+		}
+		(self.width as u64) * (self.height.saturating_sub(self.banner_rows.unwrap_or(0)) as u64) * (total_bytes(self.color_type, self.bit_depth) as u64) * (self.frames.unwrap_or(1) as u64)
+	}
+
+	pub fn to_json(&self) -> String {
+		let chunks: Vec<String> = self.chunks.iter().map(|run| format!(
+			"{{\"type\":\"{}\",\"offset\":{},\"count\":{},\"length\":{},\"crc_valid\":{}}}",
+			json_escape(&run.chunk_type), run.offset, run.count, run.length, run.crc_valid
+		)).collect();
+		format!(
+			"{{\"width\":{},\"height\":{},\"bit_depth\":{},\"color_type\":{},\"frames\":{},\"banner_rows\":{},\"hole_bytes\":{},\"metadata\":{},\"payload_bytes\":{},\"zip\":{},\"trailing_bytes\":{},\"size\":{},\"capacity\":{},\"chunks\":[{}]}}",
+			self.width, self.height, self.bit_depth, self.color_type, self.frames.unwrap_or(1), self.banner_rows.unwrap_or(0),
+			self.holes.as_ref().map_or(0, |holes| holes.hole_bytes()),
+			self.metadata.as_ref().map_or(String::from("null"), metadata_json), self.payload_bytes,
+			self.zip.as_ref().map_or(String::from("null"), |(name, compressed, size)| format!(
+				"{{\"name\":\"{}\",\"compressed\":{},\"size\":{}}}", json_escape(name), compressed, size
+			)),
+			self.trailing_bytes, self.size, self.capacity(), chunks.join(",")
+		)
+	}
+}
+
+impl fmt::Display for Inspection {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "\x1b[1;33mDimensions: \x1b[1;36m{}x{}px\x1b[0m", self.width, self.height)?;
+		writeln!(f, "\x1b[1;33mColor Type: \x1b[1;36m{}\x1b[0m", self.color_type)?;
+		writeln!(f, "\x1b[1;33mBit Depth: \x1b[1;36m{}\x1b[0m", self.bit_depth)?;
+		if let Some(frames) = self.frames {
+			writeln!(f, "\x1b[1;33mFrames: \x1b[1;36m{}\x1b[0m", frames)?;
+		}
+		if let Some(rows) = self.banner_rows {
+			writeln!(f, "\x1b[1;33mBanner: \x1b[1;36m{} rows\x1b[0m", rows)?;
+		}
+		if let Some(holes) = &self.holes {
+			writeln!(f, "\x1b[1;33mHoles: \x1b[1;36m{} bytes in {} holes (of a {} byte file)\x1b[0m", holes.hole_bytes(), holes.holes.len(), holes.size)?;
+		}
+		if let Some(metadata) = &self.metadata {
+			writeln!(f, "\x1b[1;33mMetadata: \x1b[1;36m{}\x1b[0m", metadata)?;
+		}
+		if self.payload_bytes != 0 {
+			writeln!(f, "\x1b[1;33mPayload: \x1b[1;36m{} bytes in {} chunks\x1b[0m", self.payload_bytes, String::from_utf8_lossy(payload::CHUNK_TYPE))?;
+			writeln!(f, "\x1b[1;33mWarning: {}.\x1b[0m", payload::STRIP_WARNING)?;
+		}
+		if let Some((name, compressed, size)) = &self.zip {
+			writeln!(
+				f, "\x1b[1;33mPolyglot: \x1b[1;36mZIP of '{}' ({} bytes deflated to {}) in a {} chunk, central directory after IEND\x1b[0m",
+				name, size, compressed, String::from_utf8_lossy(polyglot::CHUNK_TYPE)
+			)?;
+		}
+		if self.trailing_bytes != 0 {
+			writeln!(f, "\x1b[1;33mTrailing: \x1b[1;36m{} bytes after IEND\x1b[0m", self.trailing_bytes)?;
+		}
+		writeln!(f, "\x1b[1;33mSize: \x1b[1;36m{} bytes\x1b[0m", self.size)?;
+		writeln!(f, "\x1b[1;33mCapacity: \x1b[1;36m{} bytes\x1b[0m", self.capacity())?;
+		writeln!(f, "\x1b[1;33mChunks:\x1b[0m")?;
+		for run in self.chunks.iter() {
+			writeln!(
+				f,
+				"  \x1b[1;36m{}\x1b[0m x{} at {} ({} bytes){}",
+				run.chunk_type, run.count, run.offset, run.length,
+				if run.crc_valid { "" } else { " \x1b[1;31minvalid crc\x1b[0m" }
+			)?;
+		}
+		Ok(())
+	}
+}
+
+impl Inspection {
+	fn new() -> Self {
+		Self {
+			width: 0,
+			height: 0,
+			bit_depth: 0,
+			color_type: 0,
+			frames: None,
+			banner_rows: None,
+			holes: None,
+			metadata: None,
+			payload_bytes: 0,
+			zip: None,
+			trailing_bytes: 0,
+			size: 8,
+			chunks: Vec::new()
+		}
+	}
+
+	// Adds the next chunk to the layout, returning false once the end is reached
+	fn add(&mut self, header: &[u8], data: &[u8], crc_valid: bool, total_data: usize) -> bool {
+		let chunk_type = String::from_utf8_lossy(header).to_string();
+		// An invalid header leaves the dimensions at 0
+		if let (b"IHDR", Ok(ihdr)) = (header, Ihdr::parse(data)) {
+			self.width = ihdr.width;
+			self.height = ihdr.height;
+			self.bit_depth = ihdr.bit_depth;
+			self.color_type = ihdr.color_type;
+		}
+		if header == banner::CHUNK_TYPE {
+			self.banner_rows = banner::rows(data);
+		}
+		// Whether the map fits the image is left to decoding
+		if header == sparse::CHUNK_TYPE {
+			self.holes = HoleMap::read(data, u64::MAX);
+		}
+		if header == payload::CHUNK_TYPE {
+			self.payload_bytes += data.len() as u64;
+		}
+		if header == polyglot::CHUNK_TYPE {
+			self.zip = polyglot::read(data);
+		}
+		if header == metadata::CHUNK_TYPE {
+			self.metadata = Metadata::read(data);
+		}
+		if chunk_type == "acTL" && data.len() >= 4 {
+			self.frames = Some(u32::from_be_bytes(data[..4].try_into().unwrap()));
+		}
+
+		let length = data.len() as u64;
+		match self.chunks.last_mut() {
+			Some(run) if run.chunk_type == chunk_type => {
+				run.count += 1;
+				run.length += length;
+				run.crc_valid &= crc_valid;
+			},
+			_ => self.chunks.push(ChunkRun {
+				chunk_type: chunk_type.clone(),
+				offset: self.size,
+				count: 1,
+				length: length,
+				crc_valid: crc_valid
+			})
+		};
+		self.size += total_data as u64;
+		chunk_type != "IEND"
+	}
+}
+
+// Summarizes the header and chunk layout of a PNG
+pub fn inspect<R: Read>(mut source: R) -> io::Result<Inspection> {
+	util::read_header(&mut source)?;
+	let mut inspection = Inspection::new();
+	while let Some(chunk) = Chunk::new(&mut source)? {
+		if !inspection.add(chunk.get_header(), chunk.get_data(), chunk.verify_crc(), chunk.total_data) {
+			inspection.trailing_bytes = io::copy(&mut source, &mut io::sink())?;
+			break;
+		}
+	}
+	Ok(inspection)
+}
+
+// Summarizes a PNG held in memory (ie: a memory map) without copying its chunks
+pub fn inspect_bytes(bytes: &[u8]) -> io::Result<Inspection> {
+	util::read_header(&mut &bytes[..])?;
+	let mut inspection = Inspection::new();
+	for chunk in Chunks::new(bytes, 8) {
+		let chunk = chunk?;
+		if !inspection.add(chunk.get_header(), chunk.get_data(), chunk.verify_crc(), chunk.total_data) {
+			inspection.trailing_bytes = (bytes.len() as u64).saturating_sub(inspection.size);
+			break;
+		}
+	}
+	Ok(inspection)
+}
+
+fn metadata_json(metadata: &Metadata) -> String {
+	let number = |value: Option<u32>| value.map_or(String::from("null"), |value| value.to_string());
+	format!(
+		"{{\"mode\":{},\"uid\":{},\"gid\":{},\"xattrs\":{},\"acls\":{}}}",
+		number(metadata.mode), number(metadata.owner.map(|owner| owner.0)), number(metadata.owner.map(|owner| owner.1)),
+		metadata.xattrs.len() - metadata.acls(), metadata.acls()
+	)
+}
+
+pub(crate) fn json_escape(s: &str) -> String {
+	s.chars().map(|c| match c {
+		'"' => String::from("\\\""),
+		'\\' => String::from("\\\\"),
+		c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+		c => c.to_string()
+	}).collect()
+}
@@ -0,0 +1,620 @@
+extern crate flate2;
+use flate2::{Decompress, FlushDecompress, Status};
+use crc32fast::Hasher;
+
+use std::io::{self, Read};
+use std::fmt;
+
+use crate::png_convert::inspect::json_escape;
+use crate::png_convert::sink::adler32;
+use crate::util::dimensions::MAX_DIMENSION;
+use crate::util::limits::MAX_CHUNK_LENGTH;
+use crate::util::png::Ihdr;
+use crate::util::{ChunkData, PngChunk, PNG_HEADER};
+
+// Ancillary chunks that must come before PLTE & IDAT
+static BEFORE_PLTE: [&[u8; 4]; 5] = [b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB"];
+// Ancillary chunks that must come before IDAT (& after PLTE when there is one)
+static AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+static BEFORE_IDAT: [&[u8; 4]; 3] = [b"pHYs", b"sPLT", b"acTL"];
+// Ancillary chunks that can only appear once
+static UNIQUE: [&[u8; 4]; 12] = [
+	b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"tIME", b"acTL", b"eXIf"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+	// Breaks a rule of the PNG specification that decoders rely on
+	Error,
+	// Breaks a rule decoders can get by without (ie: of ancillary chunks), or is suspicious
+	Warning
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", match self {
+			Severity::Error => "error",
+			Severity::Warning => "warning"
+		})
+	}
+}
+
+#[derive(Debug)]
+pub struct Issue {
+	// Byte offset of the chunk (or data) at fault
+	pub offset: u64,
+	pub severity: Severity,
+	pub message: String
+}
+
+#[derive(Debug)]
+pub struct Lint {
+	pub issues: Vec<Issue>,
+	pub size: u64
+}
+
+impl Lint {
+	pub fn errors(&self) -> usize {
+		self.issues.iter().filter(|issue| issue.severity == Severity::Error).count()
+	}
+
+	pub fn warnings(&self) -> usize {
+		self.issues.len() - self.errors()
+	}
+
+	pub fn to_json(&self) -> String {
+		let issues: Vec<String> = self.issues.iter().map(|issue| format!(
+			"{{\"offset\":{},\"severity\":\"{}\",\"message\":\"{}\"}}",
+			issue.offset, issue.severity, json_escape(&issue.message)
+		)).collect();
+		format!(
+			"{{\"valid\":{},\"errors\":{},\"warnings\":{},\"size\":{},\"issues\":[{}]}}",
+			self.errors() == 0, self.errors(), self.warnings(), self.size, issues.join(",")
+		)
+	}
+}
+
+impl fmt::Display for Lint {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for issue in self.issues.iter() {
+			let color = match issue.severity {
+				Severity::Error => "1;31",
+				Severity::Warning => "1;33"
+			};
+			writeln!(f, "\x1b[{}m{}\x1b[0m at \x1b[1;36m{}\x1b[0m: {}", color, issue.severity, issue.offset, issue.message)?;
+		}
+		writeln!(f, "\x1b[1;33mErrors: \x1b[1;36m{}\x1b[0m", self.errors())?;
+		writeln!(f, "\x1b[1;33mWarnings: \x1b[1;36m{}\x1b[0m", self.warnings())
+	}
+}
+
+// Checks the zlib stream spread across the IDAT chunks as it arrives
+struct ZlibCheck {
+	header: Vec<u8>,
+	inflater: Decompress,
+	buffer: Vec<u8>,
+	adler: u32,
+	inflated: u64,
+	// Adler-32 following the deflate data
+	trailer: Vec<u8>,
+	// Bytes past the trailer
+	extra: u64,
+	ended: bool,
+	// Set once the stream can't be followed any further
+	failed: bool
+}
+
+impl ZlibCheck {
+	fn new() -> Self {
+		Self {
+			header: Vec::with_capacity(2),
+			inflater: Decompress::new(false),
+			buffer: vec![0u8; 64 * 1024],
+			adler: 1,
+			inflated: 0,
+			trailer: Vec::with_capacity(4),
+			extra: 0,
+			ended: false,
+			failed: false
+		}
+	}
+
+	fn feed(&mut self, mut input: &[u8], offset: u64, issues: &mut Vec<Issue>) {
+		if self.failed {
+			return;
+		}
+		if self.header.len() < 2 {
+			let take = std::cmp::min(2 - self.header.len(), input.len());
+			self.header.extend_from_slice(&input[..take]);
+			input = &input[take..];
+			if self.header.len() == 2 && !self.check_header(offset, issues) {
+				self.failed = true;
+				return;
+			}
+		}
+		while !self.ended && !input.is_empty() {
+			let (total_in, total_out) = (self.inflater.total_in(), self.inflater.total_out());
+			let status = match self.inflater.decompress(input, &mut self.buffer, FlushDecompress::None) {
+				Ok(status) => status,
+				Err(e) => {
+					issues.push(error(offset, format!("invalid deflate data in the zlib stream ({})", e)));
+					self.failed = true;
+					return;
+				}
+			};
+			let consumed = (self.inflater.total_in() - total_in) as usize;
+			let produced = (self.inflater.total_out() - total_out) as usize;
+			self.adler = adler32(self.adler, &self.buffer[..produced]);
+			self.inflated += produced as u64;
+			input = &input[consumed..];
+			self.ended = status == Status::StreamEnd;
+			if consumed == 0 && produced == 0 {
+				break;
+			}
+		}
+		if self.ended {
+			let take = std::cmp::min(4 - self.trailer.len(), input.len());
+			self.trailer.extend_from_slice(&input[..take]);
+			self.extra += (input.len() - take) as u64;
+		}
+	}
+
+	// Compression method 8 with a window of up to 32kb, a valid check & no preset dictionary
+	fn check_header(&self, offset: u64, issues: &mut Vec<Issue>) -> bool {
+		let (cmf, flg) = (self.header[0], self.header[1]);
+		let mut valid = true;
+		if cmf & 0x0f != 8 {
+			issues.push(error(offset, format!("zlib compression method of {}, not 8 (deflate)", cmf & 0x0f)));
+			valid = false;
+		}
+		if cmf >> 4 > 7 {
+			issues.push(error(offset, format!("zlib window size of 2^{} bytes, past the 32kb of deflate", (cmf >> 4) + 8)));
+			valid = false;
+		}
+		if !u16::from_be_bytes([cmf, flg]).is_multiple_of(31) {
+			issues.push(error(offset, String::from("zlib header check bits don't match (CMF * 256 + FLG isn't a multiple of 31)")));
+			valid = false;
+		}
+		if flg & 0x20 != 0 {
+			issues.push(error(offset, String::from("zlib stream asks for a preset dictionary, which PNGs can't have")));
+			valid = false;
+		}
+		valid
+	}
+}
+
+struct Linter {
+	issues: Vec<Issue>,
+	offset: u64,
+	ihdr: Option<Ihdr>,
+	// Chunk types in the order first seen
+	seen: Vec<[u8; 4]>,
+	chunks: u64,
+	// IDAT chunks started & followed by another chunk
+	idat: bool,
+	idat_ended: bool,
+	// Offset of the last IDAT chunk (where problems found at the end of the stream are reported)
+	idat_offset: u64,
+	zlib: ZlibCheck,
+	iend: bool
+}
+
+impl Linter {
+	fn new() -> Self {
+		Self {
+			issues: Vec::new(),
+			offset: 8,
+			ihdr: None,
+			seen: Vec::new(),
+			chunks: 0,
+			idat: false,
+			idat_ended: false,
+			idat_offset: 0,
+			zlib: ZlibCheck::new(),
+			iend: false
+		}
+	}
+
+	fn seen(&self, chunk_type: &[u8; 4]) -> bool {
+		self.seen.contains(chunk_type)
+	}
+
+	// Rules about where a chunk of a type can appear, checked before its data
+	fn check_placement(&mut self, chunk_type: &[u8; 4]) {
+		let offset = self.offset;
+		let name = String::from_utf8_lossy(chunk_type).to_string();
+		let critical = chunk_type[0].is_ascii_uppercase();
+		let plte = self.seen(b"PLTE");
+
+		if self.chunks == 0 && chunk_type != b"IHDR" {
+			self.issues.push(error(offset, format!("first chunk is {}, not IHDR", name)));
+		}
+		if chunk_type[2].is_ascii_lowercase() {
+			self.issues.push(error(offset, format!("{} has the reserved bit (a lowercase third letter) set", name)));
+		}
+		if critical && ![b"IHDR", b"PLTE", b"IDAT", b"IEND"].contains(&chunk_type) {
+			self.issues.push(error(offset, format!("unknown critical chunk {}", name)));
+		}
+		if self.idat && chunk_type != b"IDAT" {
+			self.idat_ended = true;
+		}
+
+		match chunk_type {
+			b"IHDR" if self.chunks != 0 => self.issues.push(error(offset, String::from("IHDR after the first chunk"))),
+			b"PLTE" => {
+				if plte {
+					self.issues.push(error(offset, String::from("more than one PLTE")));
+				}
+				if self.idat {
+					self.issues.push(error(offset, String::from("PLTE after IDAT")));
+				}
+				if let Some(ihdr) = self.ihdr.filter(|ihdr| ihdr.color_type == 0 || ihdr.color_type == 4) {
+					self.issues.push(error(offset, format!("PLTE in an image of color type {}", ihdr.color_type)));
+				}
+			},
+			b"IDAT" if self.idat_ended => self.issues.push(error(offset, String::from("IDAT chunks aren't consecutive"))),
+			_ if BEFORE_PLTE.contains(&chunk_type) && (plte || self.idat) => {
+				self.issues.push(warning(offset, format!("{} after {}", name, if plte { "PLTE" } else { "IDAT" })));
+			},
+			_ if AFTER_PLTE.contains(&chunk_type) || BEFORE_IDAT.contains(&chunk_type) => {
+				if self.idat {
+					self.issues.push(warning(offset, format!("{} after IDAT", name)));
+				} else if AFTER_PLTE.contains(&chunk_type) && !plte && self.ihdr.is_some_and(|ihdr| ihdr.color_type == 3) {
+					self.issues.push(warning(offset, format!("{} before PLTE", name)));
+				}
+			},
+			_ => ()
+		};
+
+		if UNIQUE.contains(&chunk_type) && self.seen(chunk_type) {
+			self.issues.push(warning(offset, format!("more than one {}", name)));
+		}
+		if (chunk_type == b"iCCP" && self.seen(b"sRGB")) || (chunk_type == b"sRGB" && self.seen(b"iCCP")) {
+			self.issues.push(warning(offset, String::from("both iCCP and sRGB")));
+		}
+		if chunk_type == b"IDAT" {
+			self.idat = true;
+			self.idat_offset = offset;
+		}
+		if !self.seen(chunk_type) {
+			self.seen.push(*chunk_type);
+		}
+		self.chunks += 1;
+	}
+
+	// Rules about the data of a chunk, checked once it has been read
+	fn check_data(&mut self, chunk_type: &[u8; 4], data: &[u8]) {
+		let offset = self.offset;
+		match chunk_type {
+			b"IHDR" => self.check_ihdr(data),
+			b"PLTE" => {
+				if data.is_empty() || !data.len().is_multiple_of(3) || data.len() > 256 * 3 {
+					self.issues.push(error(offset, format!("PLTE of {} bytes, not 1 to 256 RGB entries", data.len())));
+				} else if let Some(ihdr) = self.ihdr.filter(|ihdr| ihdr.color_type == 3 && data.len() / 3 > 1 << ihdr.bit_depth) {
+					self.issues.push(error(offset, format!("PLTE of {} entries in a {}-bit image", data.len() / 3, ihdr.bit_depth)));
+				}
+			},
+			b"IEND" if !data.is_empty() => self.issues.push(error(offset, format!("IEND holds {} bytes of data", data.len()))),
+			b"tRNS" if self.ihdr.is_some_and(|ihdr| ihdr.color_type == 4 || ihdr.color_type == 6) => {
+				self.issues.push(warning(offset, String::from("tRNS in an image that already has an alpha channel")));
+			},
+			b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => {
+				if let Err(e) = PngChunk::parse(chunk_type, data) {
+					self.issues.push(warning(offset, format!("invalid {} ({})", String::from_utf8_lossy(chunk_type), e)));
+				}
+			},
+			_ => ()
+		};
+	}
+
+	// Every field out of range is its own issue
+	fn check_ihdr(&mut self, data: &[u8]) {
+		let offset = self.offset;
+		if data.len() != 13 {
+			self.issues.push(error(offset, format!("IHDR of {} bytes, not 13", data.len())));
+			return;
+		}
+		let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+		let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+		let (bit_depth, color_type) = (data[8], data[9]);
+		if width == 0 || width as u64 > MAX_DIMENSION {
+			self.issues.push(error(offset, format!("width of {}px, not 1 to 2^31 - 1", width)));
+		}
+		if height == 0 || height as u64 > MAX_DIMENSION {
+			self.issues.push(error(offset, format!("height of {}px, not 1 to 2^31 - 1", height)));
+		}
+		match color_type {
+			0 | 2 | 3 | 4 | 6 => if Ihdr::new(1, 1, bit_depth, color_type).validate().is_err() {
+				self.issues.push(error(offset, format!("bit depth of {} isn't allowed with color type {}", bit_depth, color_type)));
+			},
+			_ => self.issues.push(error(offset, format!("unknown color type {}", color_type)))
+		};
+		if data[10] != 0 {
+			self.issues.push(error(offset, format!("unknown compression method {}", data[10])));
+		}
+		if data[11] != 0 {
+			self.issues.push(error(offset, format!("unknown filter method {}", data[11])));
+		}
+		if data[12] > 1 {
+			self.issues.push(error(offset, format!("unknown interlace method {}", data[12])));
+		}
+		self.ihdr = Ihdr::parse(data).ok();
+	}
+
+	// Rules about the file as a whole, checked at its end
+	fn finish(&mut self) {
+		let offset = self.offset;
+		if !self.seen(b"IHDR") {
+			self.issues.push(error(8, String::from("missing IHDR")));
+		}
+		if self.ihdr.is_some_and(|ihdr| ihdr.color_type == 3) && !self.seen(b"PLTE") {
+			self.issues.push(error(offset, String::from("missing PLTE in an image of color type 3")));
+		}
+		if !self.iend {
+			self.issues.push(error(offset, String::from("missing IEND")));
+		}
+		if !self.idat {
+			self.issues.push(error(offset, String::from("missing IDAT")));
+			return;
+		}
+
+		let (zlib, idat_offset) = (&self.zlib, self.idat_offset);
+		if zlib.failed {
+			return;
+		}
+		if !zlib.ended {
+			self.issues.push(error(idat_offset, format!("zlib stream ends early, after {} inflated bytes", zlib.inflated)));
+			return;
+		}
+		match zlib.trailer.len() {
+			4 => {
+				let expected = u32::from_be_bytes([zlib.trailer[0], zlib.trailer[1], zlib.trailer[2], zlib.trailer[3]]);
+				if expected != zlib.adler {
+					self.issues.push(error(idat_offset, format!("Adler-32 of {:08x} doesn't match the {:08x} of the inflated data", expected, zlib.adler)));
+				}
+			},
+			length => self.issues.push(error(idat_offset, format!("Adler-32 cut short at {} of 4 bytes", length)))
+		};
+		if zlib.extra != 0 {
+			self.issues.push(warning(idat_offset, format!("{} bytes after the end of the zlib stream", zlib.extra)));
+		}
+		if let Some(ihdr) = self.ihdr {
+			let expected = image_bytes(&ihdr);
+			if zlib.inflated != expected {
+				self.issues.push(error(idat_offset, format!(
+					"image data inflates to {} bytes, not the {} of {}x{}px (height * (1 + rowbytes))",
+					zlib.inflated, expected, ihdr.width, ihdr.height
+				)));
+			}
+		}
+	}
+}
+
+// Checks a PNG against the rules of the specification, reporting every issue found instead of stopping at the first
+pub fn lint<R: Read>(mut source: R) -> io::Result<Lint> {
+	let mut linter = Linter::new();
+	let mut signature = [0u8; 8];
+	let read = fill(&mut source, &mut signature)?;
+	if read < 8 || signature != PNG_HEADER {
+		linter.issues.push(error(0, String::from("missing PNG signature")));
+		return Ok(Lint {
+			issues: linter.issues,
+			size: read as u64 + io::copy(&mut source, &mut io::sink())?
+		});
+	}
+
+	let mut buffer = vec![0u8; 64 * 1024];
+	loop {
+		let offset = linter.offset;
+		let mut header = [0u8; 8];
+		let read = fill(&mut source, &mut header)?;
+		if read == 0 {
+			break;
+		}
+		if read < 8 {
+			linter.issues.push(error(offset, format!("chunk cut short at {} of its 8 byte length & type", read)));
+			linter.offset += read as u64;
+			break;
+		}
+		let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+		let chunk_type = [header[4], header[5], header[6], header[7]];
+		let name = String::from_utf8_lossy(&chunk_type).to_string();
+		// Neither can be trusted enough to find the next chunk
+		if !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+			linter.issues.push(error(offset, format!("chunk type of {:02x?} isn't 4 ASCII letters", chunk_type)));
+			linter.offset += 8 + io::copy(&mut source, &mut io::sink())?;
+			break;
+		}
+		if length > MAX_CHUNK_LENGTH {
+			linter.issues.push(error(offset, format!("{} length of {} bytes, past 2^31 - 1", name, length)));
+			linter.offset += 8 + io::copy(&mut source, &mut io::sink())?;
+			break;
+		}
+		linter.check_placement(&chunk_type);
+
+		// Only the data check_data looks at is kept (IDAT streams through the zlib check)
+		let keep = matches!(&chunk_type, b"IHDR" | b"PLTE" | b"IEND" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME");
+		let mut hasher = Hasher::new();
+		hasher.update(&chunk_type);
+		let mut data = Vec::new();
+		let mut left = length;
+		while left > 0 {
+			let want = std::cmp::min(left, buffer.len() as u64) as usize;
+			let read = fill(&mut source, &mut buffer[..want])?;
+			hasher.update(&buffer[..read]);
+			if &chunk_type == b"IDAT" {
+				linter.zlib.feed(&buffer[..read], offset, &mut linter.issues);
+			} else if keep {
+				data.extend_from_slice(&buffer[..read]);
+			}
+			left -= read as u64;
+			if read < want {
+				break;
+			}
+		}
+		if left > 0 {
+			linter.issues.push(error(offset, format!("{} cut short at {} of its {} bytes of data", name, length - left, length)));
+			linter.offset += 8 + length - left;
+			break;
+		}
+		let mut crc = [0u8; 4];
+		let read = fill(&mut source, &mut crc)?;
+		if read < 4 {
+			linter.issues.push(error(offset, format!("{} cut short at {} of its 4 byte CRC", name, read)));
+			linter.offset += 8 + length + read as u64;
+			break;
+		}
+		if hasher.finalize() != u32::from_be_bytes(crc) {
+			linter.issues.push(error(offset, format!("{} CRC doesn't match its type & data", name)));
+		}
+		linter.check_data(&chunk_type, &data);
+		linter.offset += 12 + length;
+
+		if &chunk_type == b"IEND" {
+			linter.iend = true;
+			let trailing = io::copy(&mut source, &mut io::sink())?;
+			if trailing != 0 {
+				linter.issues.push(warning(linter.offset, format!("{} bytes after IEND", trailing)));
+			}
+			linter.offset += trailing;
+			break;
+		}
+	}
+	linter.finish();
+	Ok(Lint {
+		issues: linter.issues,
+		size: linter.offset
+	})
+}
+
+// Bytes of filtered image data (a filter type byte & the packed pixels of each row) of the whole image, or of every
+// Adam7 pass
+fn image_bytes(ihdr: &Ihdr) -> u64 {
+	let channels = match ihdr.color_type {
+		2 => 3,
+		4 => 2,
+		6 => 4,
+		_ => 1
+	};
+	let bits = channels * ihdr.bit_depth as u64;
+	let rows = |width: u64, height: u64| match width == 0 {
+		true => 0,
+		false => height * (1 + (width * bits).div_ceil(8))
+	};
+	let (width, height) = (ihdr.width as u64, ihdr.height as u64);
+	if !ihdr.interlaced {
+		return rows(width, height);
+	}
+	// Starting column & row, then the steps between them, of each pass
+	let passes = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+	passes.iter().map(|&(x, y, dx, dy)| rows(
+		(width + dx - 1).saturating_sub(x) / dx,
+		(height + dy - 1).saturating_sub(y) / dy
+	)).sum()
+}
+
+// Reads until the buffer is full or the source ends, returning the bytes read
+fn fill<R: Read>(source: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+	let mut read = 0;
+	while read < buffer.len() {
+		match source.read(&mut buffer[read..]) {
+			Ok(0) => break,
+			Ok(n) => read += n,
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e)
+		};
+	}
+	Ok(read)
+}
+
+fn error(offset: u64, message: String) -> Issue {
+	Issue {
+		offset: offset,
+		severity: Severity::Error,
+		message: message
+	}
+}
+
+fn warning(offset: u64, message: String) -> Issue {
+	Issue {
+		offset: offset,
+		severity: Severity::Warning,
+		message: message
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use flate2::write::ZlibEncoder;
+	use flate2::Compression;
+	use std::io::Write;
+
+	use crate::util::ChunkWriter;
+
+	// 8-bit RGB image of 4x2px whose zlib stream holds rows of filtered data (two rows is the right size), ending in
+	// IEND if it is set
+	fn png(rows: usize, zlib: impl Fn(Vec<u8>) -> Vec<u8>, iend: bool) -> (Vec<u8>, u64) {
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(&vec![0u8; rows * 13]).unwrap();
+		let mut writer = ChunkWriter::new(Vec::new());
+		writer.write_signature().unwrap();
+		writer.write_typed(&Ihdr::new(4, 2, 8, 2)).unwrap();
+		let idat = writer.get_mut().len() as u64;
+		writer.write_chunk(b"IDAT", &zlib(encoder.finish().unwrap())).unwrap();
+		if iend {
+			writer.write_chunk(b"IEND", &[]).unwrap();
+		}
+		(writer.into_inner(), idat)
+	}
+
+	// Offset & message of every error
+	fn errors(png: &[u8]) -> Vec<(u64, String)> {
+		let lint = lint(png).unwrap();
+		assert_eq!(lint.size, png.len() as u64);
+		lint.issues.into_iter().filter(|issue| issue.severity == Severity::Error).map(|issue| (issue.offset, issue.message)).collect()
+	}
+
+	#[test]
+	fn conformant_images_pass() {
+		let (png, _) = png(2, |zlib| zlib, true);
+		assert!(lint(&png[..]).unwrap().issues.is_empty());
+	}
+
+	#[test]
+	fn missing_iend_is_reported() {
+		let (png, _) = png(2, |zlib| zlib, false);
+		assert_eq!(errors(&png), vec![(png.len() as u64, String::from("missing IEND"))]);
+	}
+
+	#[test]
+	fn bad_adler32_is_reported() {
+		let (png, idat) = png(2, |mut zlib| {
+			*zlib.last_mut().unwrap() ^= 1;
+			zlib
+		}, true);
+		let errors = errors(&png);
+		assert_eq!(errors.len(), 1, "{:?}", errors);
+		assert_eq!(errors[0].0, idat);
+		assert!(errors[0].1.starts_with("Adler-32 of "), "{}", errors[0].1);
+	}
+
+	#[test]
+	fn wrong_inflated_size_is_reported() {
+		for rows in [1, 3] {
+			let (png, idat) = png(rows, |zlib| zlib, true);
+			assert_eq!(errors(&png), vec![(idat, format!(
+				"image data inflates to {} bytes, not the 26 of 4x2px (height * (1 + rowbytes))", rows * 13
+			))]);
+		}
+	}
+
+	#[test]
+	fn interlaced_sizes_add_up_every_pass() {
+		let mut ihdr = Ihdr::new(4, 2, 8, 2);
+		assert_eq!(image_bytes(&ihdr), 26);
+		// Passes 1, 4, 6 & 7 hold 1x1, 1x1, 2x1 & 4x1px (passes 2, 3 & 5 are empty)
+		ihdr.interlaced = true;
+		assert_eq!(image_bytes(&ihdr), 4 + 4 + 7 + 13);
+	}
+}
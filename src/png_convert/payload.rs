@@ -0,0 +1,35 @@
+use std::fmt;
+
+// Private ancillary chunks holding the zlib stream of the file with --container chunk (written before the image data,
+// which is a 1x1 placeholder)
+pub static CHUNK_TYPE: &[u8; 4] = b"pgDT";
+
+pub static STRIP_WARNING: &str = "Many hosts strip ancillary chunks, which loses the payload of a chunk container";
+
+// Where the bytes of the file are stored in a PNG
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+	// Packed into the scanlines of the image
+	Pixels,
+	// Compressed into private ancillary chunks next to a placeholder image (no scanlines or filter bytes)
+	Chunk
+}
+
+impl Container {
+	pub fn parse(name: &str) -> Option<Self> {
+		match &name.to_lowercase()[..] {
+			"pixels" => Some(Container::Pixels),
+			"chunk" => Some(Container::Chunk),
+			_ => None
+		}
+	}
+}
+
+impl fmt::Display for Container {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", match self {
+			Container::Pixels => "pixels",
+			Container::Chunk => "chunk"
+		})
+	}
+}
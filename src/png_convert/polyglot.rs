@@ -0,0 +1,167 @@
+extern crate flate2;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use crc32fast::Hasher;
+
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::util::timestamp;
+
+// Private ancillary chunk holding the local header & deflated data of the ZIP entry of --polyglot zip (the central
+// directory follows IEND, where ZIP readers look for it)
+pub static CHUNK_TYPE: &[u8; 4] = b"pgZP";
+
+// Largest input stored in a ZIP polyglot, which leaves room in the chunk for the local header & the overhead deflate
+// adds to incompressible data (there is no ZIP64)
+pub static MAX_BYTES: u64 = 0x7f00_0000;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+// Version 2.0 (deflate) is needed to extract
+const VERSION: u16 = 20;
+// The name is UTF-8
+const FLAGS: u16 = 1 << 11;
+const DEFLATE: u16 = 8;
+const LOCAL_HEADER_SIZE: usize = 30;
+
+// Archives a PNG can double as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Polyglot {
+	Zip
+}
+
+impl Polyglot {
+	pub fn parse(name: &str) -> Option<Self> {
+		match &name.to_lowercase()[..] {
+			"zip" => Some(Polyglot::Zip),
+			_ => None
+		}
+	}
+}
+
+impl fmt::Display for Polyglot {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", match self {
+			Polyglot::Zip => "zip"
+		})
+	}
+}
+
+// Deflates the original file into the single entry of a ZIP archive as the pixel data goes by, leaving out what
+// precedes it (the banner) and follows it (padding)
+pub struct ZipEntry {
+	name: String,
+	// MS-DOS time & date
+	modified: (u16, u16),
+	skip: u64,
+	left: u64,
+	hasher: Hasher,
+	size: u64,
+	deflater: DeflateEncoder<Vec<u8>>,
+	crc: u32,
+	compressed: u64
+}
+
+impl ZipEntry {
+	pub fn new(name: &str, mtime: Option<u64>, skip: u64, size: u64) -> Self {
+		Self {
+			name: name.to_string(),
+			// The earliest MS-DOS date without a modification time
+			modified: timestamp::dos_time(mtime.unwrap_or(0)),
+			skip: skip,
+			left: size,
+			hasher: Hasher::new(),
+			size: 0,
+			deflater: DeflateEncoder::new(Vec::new(), Compression::default()),
+			crc: 0,
+			compressed: 0
+		}
+	}
+
+	pub fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+		let skipped = std::cmp::min(self.skip, data.len() as u64) as usize;
+		self.skip -= skipped as u64;
+		data = &data[skipped..];
+		let size = std::cmp::min(self.left, data.len() as u64) as usize;
+		if size == 0 {
+			return Ok(());
+		}
+		self.left -= size as u64;
+		self.size += size as u64;
+		self.hasher.update(&data[..size]);
+		self.deflater.write_all(&data[..size])
+	}
+
+	// Data of the chunk: the local header, then the deflated file
+	pub fn finish(&mut self) -> io::Result<Vec<u8>> {
+		let compressed = std::mem::replace(&mut self.deflater, DeflateEncoder::new(Vec::new(), Compression::default())).finish()?;
+		self.crc = std::mem::replace(&mut self.hasher, Hasher::new()).finalize();
+		self.compressed = compressed.len() as u64;
+		if self.size > MAX_BYTES || self.compressed > MAX_BYTES {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "file too large for a ZIP polyglot"));
+		}
+		let mut data = Vec::with_capacity(LOCAL_HEADER_SIZE + self.name.len() + compressed.len());
+		data.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+		data.extend_from_slice(&VERSION.to_le_bytes());
+		self.describe(&mut data);
+		data.extend_from_slice(&0u16.to_le_bytes());
+		data.extend_from_slice(self.name.as_bytes());
+		data.extend_from_slice(&compressed);
+		Ok(data)
+	}
+
+	// Central directory & its end record, given the offsets of the local header & of the central directory itself
+	// from the start of the PNG
+	pub fn central_directory(&self, local_offset: u64, offset: u64) -> Vec<u8> {
+		let mut central = Vec::new();
+		central.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+		// Made by & needed to extract
+		central.extend_from_slice(&VERSION.to_le_bytes());
+		central.extend_from_slice(&VERSION.to_le_bytes());
+		self.describe(&mut central);
+		// Extra field, comment, disk, internal & external attributes
+		central.extend_from_slice(&[0u8; 12]);
+		central.extend_from_slice(&(local_offset as u32).to_le_bytes());
+		central.extend_from_slice(self.name.as_bytes());
+
+		let mut end = Vec::new();
+		end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+		// This disk & the disk of the central directory
+		end.extend_from_slice(&[0u8; 4]);
+		// Entries on this disk & in total
+		end.extend_from_slice(&1u16.to_le_bytes());
+		end.extend_from_slice(&1u16.to_le_bytes());
+		end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+		end.extend_from_slice(&(offset as u32).to_le_bytes());
+		// Comment
+		end.extend_from_slice(&0u16.to_le_bytes());
+		[central, end].concat()
+	}
+
+	// Fields shared by the local & central headers, from the flags to the length of the name
+	fn describe(&self, data: &mut Vec<u8>) {
+		data.extend_from_slice(&FLAGS.to_le_bytes());
+		data.extend_from_slice(&DEFLATE.to_le_bytes());
+		data.extend_from_slice(&self.modified.0.to_le_bytes());
+		data.extend_from_slice(&self.modified.1.to_le_bytes());
+		data.extend_from_slice(&self.crc.to_le_bytes());
+		data.extend_from_slice(&(self.compressed as u32).to_le_bytes());
+		data.extend_from_slice(&(self.size as u32).to_le_bytes());
+		data.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+	}
+}
+
+// Name, deflated & original size of the entry in the data of a chunk, None if it isn't a local header
+pub fn read(data: &[u8]) -> Option<(String, u64, u64)> {
+	if data.len() < LOCAL_HEADER_SIZE || data[..4] != LOCAL_HEADER.to_le_bytes() {
+		return None;
+	}
+	let compressed = u32::from_le_bytes(data[18..22].try_into().unwrap()) as u64;
+	let size = u32::from_le_bytes(data[22..26].try_into().unwrap()) as u64;
+	let name_length = u16::from_le_bytes(data[26..28].try_into().unwrap()) as usize;
+	let name = data.get(LOCAL_HEADER_SIZE..LOCAL_HEADER_SIZE + name_length)?;
+	Some((String::from_utf8_lossy(name).to_string(), compressed, size))
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::convert::TryInto;
+
+use crate::util::limits;
+
+// Private ancillary chunk listing the full flush points of the image data (written after the last IDAT)
+pub static INDEX_CHUNK_TYPE: &[u8; 4] = b"pgFI";
+// Input encoded between checkpoints
+pub static CHECKPOINT_INTERVAL: u64 = 64 * 1024 * 1024;
+
+// Place in the image data where the zlib stream was fully flushed, so inflating can restart there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlushPoint {
+	// Offset in the PNG of the IDAT chunk starting after the flush
+	pub offset: u64,
+	// Bytes of scanlines (filter bytes included) compressed before the flush
+	pub uncompressed: u64
+}
+
+// State written to the sidecar file, enough to truncate the output and carry on
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+	pub input_offset: u64,
+	pub output_offset: u64,
+	pub uncompressed: u64,
+	// Running Adler-32 of the scanlines (zlib trailer)
+	pub adler: u32,
+	pub flush_points: Vec<FlushPoint>
+}
+
+// Sidecar file of an encode or decode started with --resume
+#[derive(Debug)]
+pub struct Resume {
+	pub path: PathBuf,
+	// Identifies the input & options the checkpoints belong to
+	pub fingerprint: String,
+	pub checkpoint: Option<Checkpoint>
+}
+
+impl Resume {
+	// Sidecar of an output file (ie: file.txt.png.pngifier-resume)
+	pub fn sidecar(output: &Path) -> PathBuf {
+		let mut path = output.as_os_str().to_owned();
+		path.push(".pngifier-resume");
+		PathBuf::from(path)
+	}
+
+	// Loads the last checkpoint, failing if it was made for another input or options
+	pub fn load(path: PathBuf, fingerprint: String) -> io::Result<Self> {
+		let contents = match fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self {
+				path: path,
+				fingerprint: fingerprint,
+				checkpoint: None
+			}),
+			Err(e) => return Err(e)
+		};
+
+		let mut checkpoint = Checkpoint::default();
+		let mut matches = false;
+		for line in contents.lines() {
+			let fields: Vec<&str> = line.split('\t').collect();
+			let number = |i: usize| fields.get(i).and_then(|field| field.parse::<u64>().ok()).ok_or_else(invalid_data);
+			match fields[0] {
+				"fingerprint" => matches = fields.get(1) == Some(&&fingerprint[..]),
+				"input" => checkpoint.input_offset = number(1)?,
+				"output" => checkpoint.output_offset = number(1)?,
+				"uncompressed" => checkpoint.uncompressed = number(1)?,
+				"adler" => checkpoint.adler = number(1)? as u32,
+				"flush" => checkpoint.flush_points.push(FlushPoint {
+					offset: number(1)?,
+					uncompressed: number(2)?
+				}),
+				_ => return Err(invalid_data())
+			};
+		}
+		if !matches {
+			return Err(invalid_data());
+		}
+		Ok(Self {
+			path: path,
+			fingerprint: fingerprint,
+			checkpoint: Some(checkpoint)
+		})
+	}
+
+	// Replaces the sidecar with a new checkpoint (written to a temporary file & renamed so a crash keeps the old one)
+	pub fn save(&mut self, checkpoint: Checkpoint) -> io::Result<()> {
+		let mut contents = format!(
+			"fingerprint\t{}\ninput\t{}\noutput\t{}\nuncompressed\t{}\nadler\t{}\n",
+			self.fingerprint, checkpoint.input_offset, checkpoint.output_offset, checkpoint.uncompressed, checkpoint.adler
+		);
+		for point in checkpoint.flush_points.iter() {
+			contents.push_str(&format!("flush\t{}\t{}\n", point.offset, point.uncompressed));
+		}
+
+		let mut temporary = self.path.as_os_str().to_owned();
+		temporary.push(".tmp");
+		let mut file = fs::File::create(&temporary)?;
+		file.write_all(contents.as_bytes())?;
+		file.sync_all()?;
+		fs::rename(&temporary, &self.path)?;
+		self.checkpoint = Some(checkpoint);
+		Ok(())
+	}
+
+	// Removes the sidecar once the output is complete
+	pub fn finish(&mut self) -> io::Result<()> {
+		self.checkpoint = None;
+		match fs::remove_file(&self.path) {
+			Err(ref e) if e.kind() != io::ErrorKind::NotFound => Err(io::Error::new(e.kind(), e.to_string())),
+			_ => Ok(())
+		}
+	}
+}
+
+// Data of the index chunk (offset & uncompressed size of each flush point), keeping only every few points of an index
+// too large for decoders to read (decoding can continue from any of them)
+pub fn index_data(flush_points: &[FlushPoint]) -> Vec<u8> {
+	let step = std::cmp::max(1, (flush_points.len() as u64 * 16).div_ceil(limits::MAX_PRIVATE_CHUNK_LENGTH));
+	flush_points.iter()
+		.step_by(step as usize)
+		.flat_map(|point| [point.offset.to_be_bytes(), point.uncompressed.to_be_bytes()].concat())
+		.collect()
+}
+
+pub fn read_index(data: &[u8]) -> Vec<FlushPoint> {
+	data.chunks_exact(16).map(|point| FlushPoint {
+		offset: u64::from_be_bytes(point[..8].try_into().unwrap()),
+		uncompressed: u64::from_be_bytes(point[8..].try_into().unwrap())
+	}).collect()
+}
+
+fn invalid_data() -> io::Error {
+	io::Error::from(io::ErrorKind::InvalidData)
+}
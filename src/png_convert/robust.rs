@@ -0,0 +1,246 @@
+extern crate crc32fast;
+use crc32fast::Hasher;
+
+use std::io;
+use std::convert::TryInto;
+
+use crate::util;
+use crate::png_convert::image::Image;
+use util::reed_solomon;
+
+// Size of the flat blocks, aligned to the 8x8 DCT grid of JPEG
+pub static BLOCK_SIZE: usize = 8;
+static MAGIC: &[u8; 4] = b"pngR";
+// Magic + payload length + crc32 of the payload
+static HEADER_SIZE: usize = 16;
+// Blocks holding the number of levels (1 bit each)
+static MODE_BLOCKS: usize = 8;
+
+fn levels_for(count: u8) -> &'static [u8] {
+	match count {
+		4 => &[24, 92, 164, 232],
+		_ => &[40, 215]
+	}
+}
+
+fn bits_for(count: u8) -> usize {
+	match count {
+		4 => 2,
+		_ => 1
+	}
+}
+
+// Reed-Solomon codewords that fit in a grid of blocks
+fn codewords_for(blocks: usize, levels: u8) -> usize {
+	blocks.saturating_sub(MODE_BLOCKS) * bits_for(levels) / 8 / reed_solomon::CODEWORD_SIZE
+}
+
+// Size of the grid of blocks (width, height) needed to hold max_bytes
+pub fn grid_for(max_bytes: u64, levels: u8) -> (usize, usize) {
+	let data = HEADER_SIZE + max_bytes as usize;
+	let codewords = std::cmp::max(1, data.div_ceil(reed_solomon::DATA_SIZE));
+	let bits = bits_for(levels);
+	let blocks = MODE_BLOCKS + (codewords * reed_solomon::CODEWORD_SIZE * 8).div_ceil(bits);
+	let width = std::cmp::max(1, (blocks as f64).sqrt().ceil() as usize);
+	(width, blocks.div_ceil(width))
+}
+
+// Renders the payload as flat blocks of a few luminance levels protected by Reed-Solomon codes
+pub fn encode(payload: &[u8], levels: u8) -> Image {
+	let (grid_width, grid_height) = grid_for(payload.len() as u64, levels);
+	let codewords = codewords_for(grid_width * grid_height, levels);
+
+	let mut hasher = Hasher::new();
+	hasher.update(payload);
+	let mut data = [
+		&MAGIC[..],
+		&(payload.len() as u64).to_be_bytes()[..],
+		&hasher.finalize().to_be_bytes()[..],
+		payload
+	].concat();
+	data.resize(codewords * reed_solomon::DATA_SIZE, 0);
+
+	// Interleave the codewords so damage to neighbouring blocks is spread between them
+	let encoded: Vec<Vec<u8>> = data.chunks(reed_solomon::DATA_SIZE).map(reed_solomon::encode).collect();
+	let mut symbols: Vec<u8> = Vec::with_capacity(codewords * reed_solomon::CODEWORD_SIZE);
+	for i in 0..reed_solomon::CODEWORD_SIZE {
+		for codeword in encoded.iter() {
+			symbols.push(codeword[i]);
+		}
+	}
+
+	let mut values: Vec<u8> = Vec::with_capacity(grid_width * grid_height);
+	let binary = levels_for(2);
+	for i in 0..MODE_BLOCKS {
+		values.push(binary[((levels >> (MODE_BLOCKS - 1 - i)) & 1) as usize]);
+	}
+	let bits = bits_for(levels);
+	let palette = levels_for(levels);
+	for byte in symbols.iter() {
+		for shift in (0..8 / bits).rev() {
+			values.push(palette[((byte >> (shift * bits)) as usize) & ((1 << bits) - 1)]);
+		}
+	}
+	values.resize(grid_width * grid_height, palette[0]);
+
+	let width = grid_width * BLOCK_SIZE;
+	let height = grid_height * BLOCK_SIZE;
+	let mut pixels = vec![0u8; width * height];
+	for (i, &value) in values.iter().enumerate() {
+		let (block_x, block_y) = (i % grid_width, i / grid_width);
+		for y in 0..BLOCK_SIZE {
+			let start = (block_y * BLOCK_SIZE + y) * width + block_x * BLOCK_SIZE;
+			for pixel in pixels[start..start + BLOCK_SIZE].iter_mut() {
+				*pixel = value;
+			}
+		}
+	}
+
+	Image {
+		width: width as u32,
+		height: height as u32,
+		bit_depth: 8,
+		color_type: 0,
+		pixels: pixels,
+		ancillary: Vec::new()
+	}
+}
+
+// Recovers a payload from an image encoded with encode, even after lossy recompression
+pub fn decode(image: &Image) -> io::Result<Vec<u8>> {
+	let grid_width = image.width as usize / BLOCK_SIZE;
+	let grid_height = image.height as usize / BLOCK_SIZE;
+	let blocks = grid_width * grid_height;
+	if blocks < MODE_BLOCKS {
+		return Err(invalid_data());
+	}
+	let luminance = |block: usize| -> u8 {
+		let (block_x, block_y) = (block % grid_width, block / grid_width);
+		// Edges of a block suffer the most from ringing, so only the center is sampled
+		let mut total: u32 = 0;
+		for y in 2..BLOCK_SIZE - 2 {
+			for x in 2..BLOCK_SIZE - 2 {
+				total += luma(image, block_x * BLOCK_SIZE + x, block_y * BLOCK_SIZE + y) as u32;
+			}
+		}
+		(total / ((BLOCK_SIZE as u32 - 4) * (BLOCK_SIZE as u32 - 4))) as u8
+	};
+
+	let mut levels = 0u8;
+	for block in 0..MODE_BLOCKS {
+		levels = (levels << 1) | nearest(levels_for(2), luminance(block));
+	}
+	if levels != 2 && levels != 4 {
+		return Err(invalid_data());
+	}
+
+	let codewords = codewords_for(blocks, levels);
+	let bits = bits_for(levels);
+	let palette = levels_for(levels);
+	let mut symbols: Vec<u8> = Vec::with_capacity(codewords * reed_solomon::CODEWORD_SIZE);
+	let mut block = MODE_BLOCKS;
+	for _ in 0..codewords * reed_solomon::CODEWORD_SIZE {
+		let mut byte = 0u8;
+		for _ in 0..8 / bits {
+			byte = (byte << bits) | nearest(palette, luminance(block));
+			block += 1;
+		}
+		symbols.push(byte);
+	}
+
+	let mut data: Vec<u8> = Vec::with_capacity(codewords * reed_solomon::DATA_SIZE);
+	for j in 0..codewords {
+		let mut codeword: Vec<u8> = (0..reed_solomon::CODEWORD_SIZE).map(|i| symbols[i * codewords + j]).collect();
+		if reed_solomon::decode(&mut codeword).is_none() {
+			return Err(invalid_data());
+		}
+		data.extend_from_slice(&codeword[..reed_solomon::DATA_SIZE]);
+	}
+
+	if data.len() < HEADER_SIZE || data[..4] != MAGIC[..] {
+		return Err(invalid_data());
+	}
+	let length = u64::from_be_bytes(data[4..12].try_into().unwrap()) as usize;
+	if length > data.len() - HEADER_SIZE {
+		return Err(invalid_data());
+	}
+	let payload = data[HEADER_SIZE..HEADER_SIZE + length].to_vec();
+	let mut hasher = Hasher::new();
+	hasher.update(&payload);
+	if hasher.finalize().to_be_bytes()[..] != data[12..16] {
+		return Err(invalid_data());
+	}
+	Ok(payload)
+}
+
+// Luminance of a pixel (ITU-R BT.601) using the most significant byte of each sample
+fn luma(image: &Image, x: usize, y: usize) -> u8 {
+	let step = (image.bit_depth / 8) as usize;
+	let start = y * image.row_bytes() + x * image.bytes_per_pixel();
+	let sample = |channel: usize| image.pixels[start + channel * step] as u32;
+	match image.color_type {
+		2 | 6 => ((299 * sample(0) + 587 * sample(1) + 114 * sample(2)) / 1000) as u8,
+		_ => sample(0) as u8
+	}
+}
+
+fn nearest(palette: &[u8], value: u8) -> u8 {
+	let mut best = 0;
+	for (i, &level) in palette.iter().enumerate() {
+		if (level as i16 - value as i16).abs() < (palette[best] as i16 - value as i16).abs() {
+			best = i;
+		}
+	}
+	best as u8
+}
+
+fn invalid_data() -> io::Error {
+	io::Error::from(io::ErrorKind::InvalidData)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	use crate::png_convert::container;
+	use crate::util::Limits;
+	use crate::util::testing::bytes;
+
+	// Re-encodes an image as a JPEG and reads it back the way decode does
+	fn through_jpeg(image: &Image, quality: u8) -> Image {
+		let mut jpeg = Vec::new();
+		image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+			.encode(&image.pixels, image.width, image.height, image::ExtendedColorType::L8)
+			.unwrap();
+		container::read_image(Cursor::new(jpeg), &Limits::default()).unwrap()
+	}
+
+	#[test]
+	fn round_trips_without_recompression() {
+		for levels in [2, 4] {
+			let data = bytes(31, 5000);
+			assert_eq!(decode(&encode(&data, levels)).unwrap(), data);
+		}
+	}
+
+	#[test]
+	fn survives_jpeg_recompression() {
+		for levels in [2, 4] {
+			for quality in [75, 85, 95] {
+				let data = bytes(31, 4000);
+				let recompressed = through_jpeg(&encode(&data, levels), quality);
+				assert_eq!(decode(&recompressed).unwrap(), data, "{} levels at quality {}", levels, quality);
+			}
+		}
+	}
+
+	#[test]
+	fn fails_instead_of_returning_damaged_data() {
+		let mut image = encode(&bytes(31, 1000), 4);
+		for pixel in image.pixels.iter_mut().skip(MODE_BLOCKS * BLOCK_SIZE).step_by(3) {
+			*pixel = !*pixel;
+		}
+		assert!(decode(&image).is_err());
+	}
+}
@@ -0,0 +1,954 @@
+extern crate flate2;
+extern crate image;
+use flate2::write::ZlibEncoder;
+use flate2::{Compress, Compression, FlushCompress, Status};
+use image::ImageEncoder;
+
+use std::borrow::BorrowMut;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::util;
+use crate::png_convert::{banner, payload, EncodeOptions};
+use crate::png_convert::payload::Container;
+use crate::png_convert::polyglot::{self, ZipEntry};
+use crate::png_convert::resume::{self, Checkpoint, FlushPoint};
+use util::color_type::*;
+use util::png::{IText, Ihdr, Time};
+use util::{metadata, sparse, WriteFile};
+
+// Destination of the pixel rows of an encoded file
+pub trait ImageSink: Send {
+	// Writes everything preceding the pixel data
+	fn begin(&mut self, options: &EncodeOptions) -> io::Result<()>;
+	// Receives unfiltered pixel rows in order, split at arbitrary points
+	fn write_pixels(&mut self, data: &[u8]) -> io::Result<()>;
+	// Writes everything following the pixel data (missing rows are filled with zeros except for PNGs)
+	fn finish(&mut self) -> io::Result<()>;
+	// Ends the data written so far at a point writing can continue from after a crash (None if the format can't)
+	fn checkpoint(&mut self) -> io::Result<Option<Checkpoint>> {
+		Ok(None)
+	}
+	// Continues the image of a checkpoint instead of beginning a new one
+	fn restore(&mut self, _options: &EncodeOptions, _checkpoint: &Checkpoint) -> io::Result<()> {
+		Err(io::Error::other("unable to resume this format"))
+	}
+}
+
+// Lossless containers a file can be encoded into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+	Png,
+	Qoi,
+	Bmp,
+	Tiff,
+	TiffDeflate,
+	WebP
+}
+
+impl OutputFormat {
+	pub fn parse(name: &str) -> Option<Self> {
+		match &name.to_lowercase()[..] {
+			"png" => Some(OutputFormat::Png),
+			"qoi" => Some(OutputFormat::Qoi),
+			"bmp" => Some(OutputFormat::Bmp),
+			"tif" | "tiff" => Some(OutputFormat::Tiff),
+			"tiff-deflate" => Some(OutputFormat::TiffDeflate),
+			"webp" => Some(OutputFormat::WebP),
+			_ => None
+		}
+	}
+
+	// Picks the format from the extension of an output file (ie: file.txt.qoi)
+	pub fn from_path(path: &str) -> Option<Self> {
+		match path.rfind('.') {
+			Some(index) if index > 0 => match Self::parse(&path[index + 1..]) {
+				// Deflate has no extension of its own
+				Some(OutputFormat::TiffDeflate) => None,
+				format => format
+			},
+			_ => None
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			OutputFormat::Png => "png",
+			OutputFormat::Qoi => "qoi",
+			OutputFormat::Bmp => "bmp",
+			OutputFormat::Tiff | OutputFormat::TiffDeflate => "tiff",
+			OutputFormat::WebP => "webp"
+		}
+	}
+
+	pub fn mime_type(&self) -> &'static str {
+		match self {
+			OutputFormat::Png => "image/png",
+			OutputFormat::Qoi => "image/qoi",
+			OutputFormat::Bmp => "image/bmp",
+			OutputFormat::Tiff | OutputFormat::TiffDeflate => "image/tiff",
+			OutputFormat::WebP => "image/webp"
+		}
+	}
+
+	// Whether the container can store the color type & bit depth without loss
+	pub fn supports(&self, color_type: u8, bit_depth: u8) -> bool {
+		match self {
+			OutputFormat::Png => true,
+			// Greyscale with alpha TIFFs aren't widely readable (including by decode)
+			OutputFormat::Tiff | OutputFormat::TiffDeflate => color_type != 4,
+			OutputFormat::Qoi => bit_depth == 8 && (color_type == 2 || color_type == 6),
+			OutputFormat::Bmp => bit_depth == 8 && color_type != 4,
+			OutputFormat::WebP => bit_depth == 8
+		}
+	}
+
+	// Largest width & height of the container
+	pub fn max_dimension(&self) -> u64 {
+		match self {
+			OutputFormat::WebP => 16383,
+			_ => util::dimensions::MAX_DIMENSION
+		}
+	}
+
+	// Largest file the container can address
+	pub fn max_size(&self) -> u64 {
+		match self {
+			OutputFormat::Bmp | OutputFormat::Tiff | OutputFormat::TiffDeflate => u32::MAX as u64 - 4096,
+			_ => u64::MAX
+		}
+	}
+}
+
+impl fmt::Display for OutputFormat {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", match self {
+			OutputFormat::Png => "PNG",
+			OutputFormat::Qoi => "QOI",
+			OutputFormat::Bmp => "BMP",
+			OutputFormat::Tiff => "TIFF",
+			OutputFormat::TiffDeflate => "TIFF (deflate)",
+			OutputFormat::WebP => "WebP (lossless)"
+		})
+	}
+}
+
+// Creates the writer of a format, writing into a borrowed or owned WriteFile
+pub fn new<'a, W: BorrowMut<WriteFile> + Send + 'a>(format: OutputFormat, write_file: W) -> Box<dyn ImageSink + 'a> {
+	match format {
+		OutputFormat::Png => Box::new(PngSink::new(write_file)),
+		OutputFormat::Qoi => Box::new(QoiSink::new(write_file)),
+		OutputFormat::Bmp => Box::new(BmpSink::new(write_file)),
+		OutputFormat::Tiff => Box::new(TiffSink::new(write_file, false)),
+		OutputFormat::TiffDeflate => Box::new(TiffSink::new(write_file, true)),
+		OutputFormat::WebP => Box::new(WebPSink::new(write_file))
+	}
+}
+
+// Layout shared by the writers
+#[derive(Default)]
+struct Layout {
+	width: u32,
+	height: u32,
+	bit_depth: u8,
+	color_type: u8,
+	row_bytes: usize,
+	frames: Option<u64>,
+	total: u64,
+	written: u64
+}
+
+impl Layout {
+	fn new(options: &EncodeOptions) -> Self {
+		let banner_rows = options.banner.as_ref().map_or(0, |banner| banner.rows as u64);
+		Self {
+			width: options.width as u32,
+			height: (options.height + banner_rows) as u32,
+			bit_depth: options.bit_depth,
+			color_type: options.color_type,
+			row_bytes: options.chunk_size as usize,
+			frames: options.frames,
+			total: options.chunk_size * (options.height * options.frames.unwrap_or(1) + banner_rows),
+			written: 0
+		}
+	}
+
+	fn channels(&self) -> usize {
+		total_bytes(self.color_type, self.bit_depth) / (self.bit_depth / 8) as usize
+	}
+
+	// Next piece of the zeros filling the rows left out by --trim (None once the image is full), so an image left
+	// mostly empty isn't padded from one allocation of its whole size
+	fn padding(&self) -> Option<&'static [u8]> {
+		match self.total - self.written {
+			0 => None,
+			left => Some(&PADDING[..std::cmp::min(left, PADDING.len() as u64) as usize])
+		}
+	}
+}
+
+// Zeros padding is written from, a piece at a time
+static PADDING: [u8; 64 * 1024] = [0u8; 64 * 1024];
+
+// Most a full flush writes: two blocks of the 85K output buffer of miniz_oxide
+static FLUSH_SIZE: usize = 256 * 1024;
+
+// Raw deflate with the zlib header & Adler-32 trailer written by hand, so the stream can continue after a full flush
+struct Deflater {
+	compress: Compress,
+	// Fixed size, as the compressor picks other blocks when it writes straight into larger buffers
+	buffer: Vec<u8>,
+	output: Vec<u8>,
+	adler: u32,
+	// Bytes compressed since the start of the stream
+	uncompressed: u64
+}
+
+impl Deflater {
+	fn new() -> Self {
+		let mut deflater = Self::resume(1, 0);
+		// Deflate with a 32K window at the fastest level
+		deflater.output.extend_from_slice(&[0x78, 0x01]);
+		deflater
+	}
+
+	fn resume(adler: u32, uncompressed: u64) -> Self {
+		Self {
+			compress: Compress::new(Compression::fast(), false),
+			buffer: Vec::with_capacity(32 * 1024),
+			output: Vec::new(),
+			adler: adler,
+			uncompressed: uncompressed
+		}
+	}
+
+	fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+		self.adler = adler32(self.adler, data);
+		self.uncompressed += data.len() as u64;
+		self.run(data, FlushCompress::None)
+	}
+
+	// Byte aligns the output & forgets the window so inflating can start from here
+	fn full_flush(&mut self) -> io::Result<()> {
+		// miniz_oxide can end a flush that pauses for a full buffer before its empty block, and flushing again adds
+		// another, so the flush gets room for everything it writes (the rest of a block & the last one)
+		let mut buffer = Vec::with_capacity(FLUSH_SIZE);
+		loop {
+			let total_out = self.compress.total_out();
+			self.compress.compress_vec(&[], &mut buffer, FlushCompress::Full)
+				.map_err(io::Error::other)?;
+			self.output.extend_from_slice(&buffer);
+			// Only continues while output is still pending
+			if self.compress.total_out() - total_out < buffer.capacity() as u64 {
+				break;
+			}
+			buffer.clear();
+		}
+		// Continuing after a crash starts a new compressor, so the output matches an uninterrupted run
+		self.compress = Compress::new(Compression::fast(), false);
+		Ok(())
+	}
+
+	fn finish(&mut self) -> io::Result<()> {
+		self.run(&[], FlushCompress::Finish)?;
+		self.output.extend_from_slice(&self.adler.to_be_bytes());
+		Ok(())
+	}
+
+	fn run(&mut self, mut input: &[u8], flush: FlushCompress) -> io::Result<()> {
+		loop {
+			self.buffer.clear();
+			let total_in = self.compress.total_in();
+			let status = self.compress.compress_vec(input, &mut self.buffer, flush)
+				.map_err(io::Error::other)?;
+			input = &input[(self.compress.total_in() - total_in) as usize..];
+			self.output.extend_from_slice(&self.buffer);
+			// Output left room in the buffer once everything is compressed (or flushed)
+			let flushed = self.buffer.len() < self.buffer.capacity();
+			if input.is_empty() && (status == Status::StreamEnd || (flush != FlushCompress::Finish && flushed)) {
+				return Ok(());
+			}
+		}
+	}
+}
+
+pub(crate) fn adler32(adler: u32, data: &[u8]) -> u32 {
+	let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+	// Largest run of bytes before the sums can overflow
+	for run in data.chunks(5552) {
+		for &byte in run {
+			a += byte as u32;
+			b += a;
+		}
+		a %= 65521;
+		b %= 65521;
+	}
+	(b << 16) | a
+}
+
+// PNG chunks written through WriteFile::write_chunk (APNGs give each frame its own zlib stream)
+pub struct PngSink<W: BorrowMut<WriteFile>> {
+	write_file: W,
+	deflater: Deflater,
+	layout: Layout,
+	container: Container,
+	// The original file as the ZIP entry of a polyglot
+	zip: Option<ZipEntry>,
+	column: usize,
+	row: u32,
+	frame: u64,
+	sequence: u32,
+	idat_size: usize,
+	flush_points: Vec<FlushPoint>
+}
+
+impl<W: BorrowMut<WriteFile>> PngSink<W> {
+	pub fn new(write_file: W) -> Self {
+		Self {
+			write_file: write_file,
+			deflater: Deflater::new(),
+			layout: Layout::default(),
+			container: Container::Pixels,
+			zip: None,
+			column: 0,
+			row: 0,
+			frame: 0,
+			sequence: 0,
+			idat_size: 0,
+			flush_points: Vec::new()
+		}
+	}
+
+	// Writes compressed data as IDAT (first frame), fdAT or payload chunks of at most idat_size bytes
+	fn write_idats(&mut self, all: bool) -> io::Result<()> {
+		while self.deflater.output.len() >= self.idat_size || (all && !self.deflater.output.is_empty()) {
+			let size = std::cmp::min(self.idat_size, self.deflater.output.len());
+			let data: Vec<u8> = self.deflater.output.drain(..size).collect();
+			match self.frame {
+				_ if self.container == Container::Chunk => self.write_file.borrow_mut().write_chunk(payload::CHUNK_TYPE, &data)?,
+				0 => self.write_file.borrow_mut().write_chunk(b"IDAT", &data)?,
+				_ => {
+					self.sequence += 1;
+					self.write_file.borrow_mut().write_fdat(self.sequence - 1, &data)?
+				}
+			};
+		}
+		Ok(())
+	}
+
+	fn write_fctl(&mut self) -> io::Result<()> {
+		self.write_file.borrow_mut().write_fctl(self.sequence, self.layout.width, self.layout.height)?;
+		self.sequence += 1;
+		Ok(())
+	}
+
+	// Ends the zlib stream of the current frame and starts the next one
+	fn next_frame(&mut self) -> io::Result<()> {
+		self.deflater.finish()?;
+		self.write_idats(true)?;
+		self.deflater = Deflater::new();
+		self.frame += 1;
+		self.row = 0;
+		self.write_fctl()
+	}
+}
+
+impl<W: BorrowMut<WriteFile> + Send> ImageSink for PngSink<W> {
+	fn begin(&mut self, options: &EncodeOptions) -> io::Result<()> {
+		self.layout = Layout::new(options);
+		self.container = options.container;
+		self.zip = options.zip.as_ref().map(|name| ZipEntry::new(
+			name,
+			options.mtime,
+			options.banner.as_ref().map_or(0, |banner| banner.pixels.len() as u64),
+			options.max_bytes
+		));
+		self.idat_size = std::cmp::max(1, options.idat_size);
+		self.write_file.borrow_mut().write_header()?;
+		self.write_file.borrow_mut().write_typed(&Ihdr::new(self.layout.width, self.layout.height, self.layout.bit_depth, self.layout.color_type))?;
+		if let Some(mtime) = options.mtime {
+			self.write_file.borrow_mut().write_typed(&Time::from_seconds(mtime))?;
+		}
+		if let Some(banner) = &options.banner {
+			self.write_file.borrow_mut().write_chunk(banner::CHUNK_TYPE, &banner.rows.to_be_bytes())?;
+		}
+		if let Some(holes) = &options.holes {
+			self.write_file.borrow_mut().write_chunk(sparse::CHUNK_TYPE, &holes.to_data())?;
+		}
+		if let Some(metadata) = &options.metadata {
+			self.write_file.borrow_mut().write_chunk(metadata::CHUNK_TYPE, &metadata.to_data())?;
+		}
+		for (key, value) in options.tags.iter() {
+			self.write_file.borrow_mut().write_typed(&IText::new(key, value))?;
+		}
+		if let Some(frames) = self.layout.frames {
+			self.write_file.borrow_mut().write_actl(frames as u32, 0)?;
+			self.write_fctl()?;
+		}
+		Ok(())
+	}
+
+	fn write_pixels(&mut self, mut data: &[u8]) -> io::Result<()> {
+		if let Some(zip) = self.zip.as_mut() {
+			zip.write(data)?;
+		}
+		// The file is compressed as it is, without scanlines
+		if self.container == Container::Chunk {
+			self.deflater.write_all(data)?;
+			return self.write_idats(false);
+		}
+		while !data.is_empty() {
+			if self.column == 0 {
+				if self.row == self.layout.height {
+					self.next_frame()?;
+				}
+				// Every scanline starts with filter type 0 (none)
+				self.deflater.write_all(&[0u8])?;
+			}
+			let size = std::cmp::min(self.layout.row_bytes - self.column, data.len());
+			self.deflater.write_all(&data[..size])?;
+			self.column = (self.column + size) % self.layout.row_bytes;
+			if self.column == 0 {
+				self.row += 1;
+			}
+			data = &data[size..];
+		}
+		self.write_idats(false)
+	}
+
+	fn finish(&mut self) -> io::Result<()> {
+		self.deflater.finish()?;
+		self.write_idats(true)?;
+		if self.container == Container::Chunk {
+			// A single black pixel (after its filter byte)
+			let mut placeholder = Deflater::new();
+			placeholder.write_all(&[0u8; 2])?;
+			placeholder.finish()?;
+			self.write_file.borrow_mut().write_chunk(b"IDAT", &placeholder.output)?;
+		}
+		if !self.flush_points.is_empty() {
+			self.write_file.borrow_mut().write_chunk(resume::INDEX_CHUNK_TYPE, &resume::index_data(&self.flush_points))?;
+		}
+		// ZIP readers find the entry through offsets from the start of the file, which is the start of the PNG
+		let mut local_offset = 0;
+		if let Some(zip) = self.zip.as_mut() {
+			local_offset = self.write_file.borrow_mut().position() + 8;
+			self.write_file.borrow_mut().write_chunk(polyglot::CHUNK_TYPE, &zip.finish()?)?;
+		}
+		self.write_file.borrow_mut().write_chunk(b"IEND", b"")?;
+		if let Some(zip) = self.zip.as_ref() {
+			let offset = self.write_file.borrow_mut().position();
+			self.write_file.borrow_mut().write_all(&zip.central_directory(local_offset, offset))?;
+		}
+		self.write_file.borrow_mut().flush()
+	}
+
+	fn checkpoint(&mut self) -> io::Result<Option<Checkpoint>> {
+		// Frames have zlib streams of their own (and payloads aren't indexed)
+		if self.layout.frames.is_some() || self.container == Container::Chunk {
+			return Ok(None);
+		}
+		self.deflater.full_flush()?;
+		self.write_idats(true)?;
+		let offset = self.write_file.borrow_mut().sync()?;
+		self.flush_points.push(FlushPoint {
+			offset: offset,
+			uncompressed: self.deflater.uncompressed
+		});
+		Ok(Some(Checkpoint {
+			input_offset: 0,
+			output_offset: offset,
+			uncompressed: self.deflater.uncompressed,
+			adler: self.deflater.adler,
+			flush_points: self.flush_points.clone()
+		}))
+	}
+
+	fn restore(&mut self, options: &EncodeOptions, checkpoint: &Checkpoint) -> io::Result<()> {
+		self.layout = Layout::new(options);
+		self.idat_size = std::cmp::max(1, options.idat_size);
+		// Checkpoints never fall between a filter byte & the rest of its scanline
+		let scanline = self.layout.row_bytes as u64 + 1;
+		self.row = (checkpoint.uncompressed / scanline) as u32;
+		self.column = (checkpoint.uncompressed % scanline).saturating_sub(1) as usize;
+		self.deflater = Deflater::resume(checkpoint.adler, checkpoint.uncompressed);
+		self.flush_points = checkpoint.flush_points.clone();
+		Ok(())
+	}
+}
+
+// Quite OK Image format (https://qoiformat.org/qoi-specification.pdf)
+pub struct QoiSink<W: BorrowMut<WriteFile>> {
+	write_file: W,
+	layout: Layout,
+	pixel: Vec<u8>,
+	previous: [u8; 4],
+	index: [[u8; 4]; 64],
+	run: u8
+}
+
+impl<W: BorrowMut<WriteFile>> QoiSink<W> {
+	pub fn new(write_file: W) -> Self {
+		Self {
+			write_file: write_file,
+			layout: Layout::default(),
+			pixel: Vec::with_capacity(4),
+			previous: [0, 0, 0, 255],
+			index: [[0u8; 4]; 64],
+			run: 0
+		}
+	}
+
+	fn push(&mut self, pixel: [u8; 4], out: &mut Vec<u8>) {
+		if pixel == self.previous {
+			self.run += 1;
+			if self.run == 62 {
+				out.push(0xc0 | (self.run - 1));
+				self.run = 0;
+			}
+			return;
+		}
+		if self.run > 0 {
+			out.push(0xc0 | (self.run - 1));
+			self.run = 0;
+		}
+
+		let [r, g, b, a] = pixel;
+		let hash = (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64;
+		if self.index[hash] == pixel {
+			out.push(hash as u8);
+		} else {
+			self.index[hash] = pixel;
+			if a == self.previous[3] {
+				let dr = r.wrapping_sub(self.previous[0]) as i8;
+				let dg = g.wrapping_sub(self.previous[1]) as i8;
+				let db = b.wrapping_sub(self.previous[2]) as i8;
+				let dr_dg = dr.wrapping_sub(dg);
+				let db_dg = db.wrapping_sub(dg);
+				if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+					out.push(0x40 | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+				} else if (-32..=31).contains(&dg) && (-8..=7).contains(&dr_dg) && (-8..=7).contains(&db_dg) {
+					out.push(0x80 | (dg + 32) as u8);
+					out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+				} else {
+					out.extend_from_slice(&[0xfe, r, g, b]);
+				}
+			} else {
+				out.extend_from_slice(&[0xff, r, g, b, a]);
+			}
+		}
+		self.previous = pixel;
+	}
+}
+
+impl<W: BorrowMut<WriteFile> + Send> ImageSink for QoiSink<W> {
+	fn begin(&mut self, options: &EncodeOptions) -> io::Result<()> {
+		self.layout = Layout::new(options);
+		self.write_file.borrow_mut().write_all(b"qoif")?;
+		self.write_file.borrow_mut().write_all(&self.layout.width.to_be_bytes())?;
+		self.write_file.borrow_mut().write_all(&self.layout.height.to_be_bytes())?;
+		// sRGB with linear alpha
+		self.write_file.borrow_mut().write_all(&[self.layout.channels() as u8, 0u8])
+	}
+
+	fn write_pixels(&mut self, data: &[u8]) -> io::Result<()> {
+		let channels = self.layout.channels();
+		let mut out: Vec<u8> = Vec::with_capacity(data.len() + data.len() / 4);
+		for &byte in data.iter() {
+			self.pixel.push(byte);
+			if self.pixel.len() == channels {
+				let alpha = if channels == 4 { self.pixel[3] } else { 255 };
+				let pixel = [self.pixel[0], self.pixel[1], self.pixel[2], alpha];
+				self.pixel.clear();
+				self.push(pixel, &mut out);
+			}
+		}
+		self.layout.written += data.len() as u64;
+		self.write_file.borrow_mut().write_all(&out)
+	}
+
+	fn finish(&mut self) -> io::Result<()> {
+		while let Some(zeros) = self.layout.padding() {
+			self.write_pixels(zeros)?;
+		}
+		if self.run > 0 {
+			self.write_file.borrow_mut().write_all(&[0xc0 | (self.run - 1)])?;
+			self.run = 0;
+		}
+		self.write_file.borrow_mut().write_all(&[0, 0, 0, 0, 0, 0, 0, 1])?;
+		self.write_file.borrow_mut().flush()
+	}
+}
+
+// Top-down BMP: 8-bit greyscale palette, 24-bit BGR or 32-bit BGRA (with a V4 header for the alpha mask)
+pub struct BmpSink<W: BorrowMut<WriteFile>> {
+	write_file: W,
+	layout: Layout,
+	row: Vec<u8>
+}
+
+impl<W: BorrowMut<WriteFile>> BmpSink<W> {
+	pub fn new(write_file: W) -> Self {
+		Self {
+			write_file: write_file,
+			layout: Layout::default(),
+			row: Vec::new()
+		}
+	}
+
+	fn stride(&self) -> usize {
+		(self.layout.row_bytes + 3) & !3
+	}
+
+	fn write_row(&mut self) -> io::Result<()> {
+		let channels = self.layout.channels();
+		let mut out = vec![0u8; self.stride()];
+		for (pixel, dest) in self.row.chunks(channels).zip(out.chunks_mut(channels)) {
+			dest.copy_from_slice(pixel);
+			// Red and blue are swapped
+			if channels >= 3 {
+				dest.swap(0, 2);
+			}
+		}
+		self.row.clear();
+		self.write_file.borrow_mut().write_all(&out)
+	}
+}
+
+impl<W: BorrowMut<WriteFile> + Send> ImageSink for BmpSink<W> {
+	fn begin(&mut self, options: &EncodeOptions) -> io::Result<()> {
+		self.layout = Layout::new(options);
+		self.row.reserve(self.layout.row_bytes);
+		let channels = self.layout.channels();
+		let (header_size, palette_size) = match channels {
+			1 => (40u32, 1024u32),
+			3 => (40, 0),
+			_ => (108, 0)
+		};
+		let offset = 14 + header_size + palette_size;
+		let image_size = (self.stride() * self.layout.height as usize) as u32;
+
+		let mut header: Vec<u8> = Vec::with_capacity(offset as usize);
+		header.extend_from_slice(b"BM");
+		header.extend_from_slice(&(offset + image_size).to_le_bytes());
+		header.extend_from_slice(&[0u8; 4]);
+		header.extend_from_slice(&offset.to_le_bytes());
+		header.extend_from_slice(&header_size.to_le_bytes());
+		header.extend_from_slice(&(self.layout.width as i32).to_le_bytes());
+		// Negative heights store the rows from the top down
+		header.extend_from_slice(&(-(self.layout.height as i32)).to_le_bytes());
+		header.extend_from_slice(&1u16.to_le_bytes());
+		header.extend_from_slice(&((channels * 8) as u16).to_le_bytes());
+		// BI_RGB or BI_BITFIELDS
+		header.extend_from_slice(&(if channels == 4 { 3u32 } else { 0u32 }).to_le_bytes());
+		header.extend_from_slice(&image_size.to_le_bytes());
+		// 2835 pixels per meter (72 DPI)
+		header.extend_from_slice(&2835u32.to_le_bytes());
+		header.extend_from_slice(&2835u32.to_le_bytes());
+		header.extend_from_slice(&(if channels == 1 { 256u32 } else { 0u32 }).to_le_bytes());
+		header.extend_from_slice(&0u32.to_le_bytes());
+		if channels == 4 {
+			for mask in [0x00ff_0000u32, 0x0000_ff00, 0x0000_00ff, 0xff00_0000].iter() {
+				header.extend_from_slice(&mask.to_le_bytes());
+			}
+			// LCS_sRGB followed by unused endpoints & gamma
+			header.extend_from_slice(b"BGRs");
+			header.extend_from_slice(&[0u8; 48]);
+		}
+		if channels == 1 {
+			for i in 0..=255u8 {
+				header.extend_from_slice(&[i, i, i, 0]);
+			}
+		}
+		self.write_file.borrow_mut().write_all(&header)
+	}
+
+	fn write_pixels(&mut self, mut data: &[u8]) -> io::Result<()> {
+		self.layout.written += data.len() as u64;
+		while !data.is_empty() {
+			let size = std::cmp::min(self.layout.row_bytes - self.row.len(), data.len());
+			self.row.extend_from_slice(&data[..size]);
+			data = &data[size..];
+			if self.row.len() == self.layout.row_bytes {
+				self.write_row()?;
+			}
+		}
+		Ok(())
+	}
+
+	fn finish(&mut self) -> io::Result<()> {
+		while let Some(zeros) = self.layout.padding() {
+			self.write_pixels(zeros)?;
+		}
+		self.write_file.borrow_mut().flush()
+	}
+}
+
+// Big-endian TIFF with a single strip, so 16-bit samples keep the byte order of PNGs
+pub struct TiffSink<W: BorrowMut<WriteFile>> {
+	write_file: W,
+	layout: Layout,
+	// Deflate needs the compressed size before the strip, so the strip is kept in memory
+	deflater: Option<ZlibEncoder<Vec<u8>>>
+}
+
+impl<W: BorrowMut<WriteFile>> TiffSink<W> {
+	pub fn new(write_file: W, deflate: bool) -> Self {
+		Self {
+			write_file: write_file,
+			layout: Layout::default(),
+			deflater: match deflate {
+				true => Some(ZlibEncoder::new(Vec::new(), Compression::fast())),
+				false => None
+			}
+		}
+	}
+
+	// Header & image file directory of a strip of strip_size bytes
+	fn write_directory(&mut self, strip_size: u32, compression: u32) -> io::Result<()> {
+		let channels = self.layout.channels() as u32;
+		let has_alpha = self.layout.color_type == 4 || self.layout.color_type == 6;
+		let entries: u32 = if has_alpha { 11 } else { 10 };
+		let directory_size = 2 + entries * 12 + 4;
+		// Bits per sample only fit in the entry for up to 2 samples
+		let bits_offset = 8 + directory_size;
+		let extra_size = if channels > 2 { channels * 2 } else { 0 };
+		let strip_offset = bits_offset + extra_size;
+
+		let mut out: Vec<u8> = Vec::with_capacity(strip_offset as usize);
+		out.extend_from_slice(b"MM\0*");
+		out.extend_from_slice(&8u32.to_be_bytes());
+		out.extend_from_slice(&(entries as u16).to_be_bytes());
+		let mut entry = |tag: u16, field_type: u16, count: u32, value: u32| {
+			out.extend_from_slice(&tag.to_be_bytes());
+			out.extend_from_slice(&field_type.to_be_bytes());
+			out.extend_from_slice(&count.to_be_bytes());
+			// SHORT values are left justified
+			match field_type == 3 && count == 1 {
+				true => out.extend_from_slice(&[&(value as u16).to_be_bytes()[..], &[0u8, 0u8][..]].concat()),
+				false => out.extend_from_slice(&value.to_be_bytes())
+			};
+		};
+		let bits = self.layout.bit_depth as u32;
+		entry(256, 4, 1, self.layout.width);
+		entry(257, 4, 1, self.layout.height);
+		match channels {
+			1 => entry(258, 3, 1, bits),
+			2 => entry(258, 3, 2, bits << 16 | bits),
+			_ => entry(258, 3, channels, bits_offset)
+		};
+		entry(259, 3, 1, compression);
+		// BlackIsZero or RGB
+		entry(262, 3, 1, if channels >= 3 { 2 } else { 1 });
+		entry(273, 4, 1, strip_offset);
+		entry(277, 3, 1, channels);
+		entry(278, 4, 1, self.layout.height);
+		entry(279, 4, 1, strip_size);
+		entry(284, 3, 1, 1);
+		if has_alpha {
+			// Unassociated alpha
+			entry(338, 3, 1, 2);
+		}
+		out.extend_from_slice(&0u32.to_be_bytes());
+		if channels > 2 {
+			for _ in 0..channels {
+				out.extend_from_slice(&(bits as u16).to_be_bytes());
+			}
+		}
+		self.write_file.borrow_mut().write_all(&out)
+	}
+}
+
+impl<W: BorrowMut<WriteFile> + Send> ImageSink for TiffSink<W> {
+	fn begin(&mut self, options: &EncodeOptions) -> io::Result<()> {
+		self.layout = Layout::new(options);
+		match self.deflater {
+			Some(_) => Ok(()),
+			None => self.write_directory(self.layout.total as u32, 1)
+		}
+	}
+
+	fn write_pixels(&mut self, data: &[u8]) -> io::Result<()> {
+		self.layout.written += data.len() as u64;
+		match &mut self.deflater {
+			Some(deflater) => deflater.write_all(data),
+			None => self.write_file.borrow_mut().write_all(data)
+		}
+	}
+
+	fn finish(&mut self) -> io::Result<()> {
+		while let Some(zeros) = self.layout.padding() {
+			self.write_pixels(zeros)?;
+		}
+		if let Some(deflater) = self.deflater.take() {
+			let strip = deflater.finish()?;
+			// Adobe deflate
+			self.write_directory(strip.len() as u32, 8)?;
+			self.write_file.borrow_mut().write_all(&strip)?;
+		}
+		self.write_file.borrow_mut().flush()
+	}
+}
+
+// Lossless WebP (VP8L), which is compressed as a whole so the pixels are kept in memory
+pub struct WebPSink<W: BorrowMut<WriteFile>> {
+	write_file: W,
+	layout: Layout,
+	pixels: Vec<u8>
+}
+
+impl<W: BorrowMut<WriteFile>> WebPSink<W> {
+	pub fn new(write_file: W) -> Self {
+		Self {
+			write_file: write_file,
+			layout: Layout::default(),
+			pixels: Vec::new()
+		}
+	}
+}
+
+impl<W: BorrowMut<WriteFile> + Send> ImageSink for WebPSink<W> {
+	fn begin(&mut self, options: &EncodeOptions) -> io::Result<()> {
+		self.layout = Layout::new(options);
+		self.pixels.reserve(self.layout.total as usize);
+		Ok(())
+	}
+
+	fn write_pixels(&mut self, data: &[u8]) -> io::Result<()> {
+		self.layout.written += data.len() as u64;
+		self.pixels.extend_from_slice(data);
+		Ok(())
+	}
+
+	fn finish(&mut self) -> io::Result<()> {
+		while let Some(zeros) = self.layout.padding() {
+			self.write_pixels(zeros)?;
+		}
+		let color_type = match self.layout.color_type {
+			0 => image::ExtendedColorType::L8,
+			4 => image::ExtendedColorType::La8,
+			2 => image::ExtendedColorType::Rgb8,
+			_ => image::ExtendedColorType::Rgba8
+		};
+		image::codecs::webp::WebPEncoder::new_lossless(self.write_file.borrow_mut())
+			.write_image(&self.pixels, self.layout.width, self.layout.height, color_type)
+			.map_err(io::Error::other)?;
+		self.write_file.borrow_mut().flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use std::io::{Cursor, Read};
+	use std::path::PathBuf;
+
+	use flate2::{Decompress, FlushDecompress};
+
+	use crate::png_convert::{decode, encode_resumable};
+	use crate::png_convert::resume::Resume;
+	use crate::util::Limits;
+	use crate::util::testing::bytes;
+
+	// Source failing once more than limit bytes were read, like a crash partway through (reads keep their size so
+	// checkpoints land where they would have)
+	struct Crashing {
+		data: Cursor<Vec<u8>>,
+		limit: u64
+	}
+
+	impl Read for Crashing {
+		fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+			if self.data.position() >= self.limit {
+				return Err(io::Error::other("crashed"));
+			}
+			self.data.read(buffer)
+		}
+	}
+
+	fn directory(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("pngifier-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&path);
+		fs::create_dir_all(&path).unwrap();
+		path
+	}
+
+	// Encodes data with --resume, crashing after crash bytes of input & continuing like running the command again
+	fn encode_crashing(path: &PathBuf, data: &[u8], options: &EncodeOptions, interval: u64, crash: u64) -> Vec<u8> {
+		let sidecar = Resume::sidecar(path);
+		let mut resume = Resume::load(sidecar.clone(), String::from("test")).unwrap();
+		let source = Crashing { data: Cursor::new(data.to_vec()), limit: crash };
+		let sink = new(OutputFormat::Png, WriteFile::from_pathbuf(path.clone()).unwrap());
+		assert!(encode_resumable(source, sink, options, &mut resume, interval).is_err());
+
+		let mut resume = Resume::load(sidecar, String::from("test")).unwrap();
+		let checkpoint = resume.checkpoint.clone().expect("crashed before a checkpoint");
+		let mut write_file = WriteFile::append(path.clone()).unwrap();
+		write_file.truncate(checkpoint.output_offset).unwrap();
+		let mut source = Cursor::new(data.to_vec());
+		source.set_position(checkpoint.input_offset);
+		encode_resumable(source, new(OutputFormat::Png, write_file), options, &mut resume, interval).unwrap();
+		fs::read(path).unwrap()
+	}
+
+	#[test]
+	fn resumed_encode_matches_an_uninterrupted_one() {
+		let directory = directory("sink-resume");
+		let data = bytes(37, 600_000);
+		for buffer_size in [1000, 64 * 1024] {
+			for interval in [50_000, 130_000] {
+				let mut options = EncodeOptions::for_size(data.len() as u64);
+				options.buffer_size = buffer_size;
+
+				let expected_path = directory.join("expected.png");
+				let mut resume = Resume::load(Resume::sidecar(&expected_path), String::from("test")).unwrap();
+				let sink = new(OutputFormat::Png, WriteFile::from_pathbuf(expected_path.clone()).unwrap());
+				encode_resumable(Cursor::new(data.clone()), sink, &options, &mut resume, interval).unwrap();
+				let expected = fs::read(&expected_path).unwrap();
+				// Matching alone would pass checkpoints that lose data the same way each time
+				let decoded_path = directory.join("decoded.bin");
+				let mut write_file = WriteFile::from_pathbuf(decoded_path.clone()).unwrap();
+				decode(Cursor::new(&expected), expected.len() as u64, &mut write_file, buffer_size, &Limits::default()).unwrap();
+				write_file.flush().unwrap();
+				assert!(fs::read(&decoded_path).unwrap()[..data.len()] == *data, "checkpoints every {} lost data", interval);
+
+				for crash in [interval + 1, 3 * interval + 4321, 520_000] {
+					let resumed = encode_crashing(&directory.join("resumed.png"), &data, &options, interval, crash);
+					assert!(resumed == expected, "buffer {}, checkpoints every {}, crashed at {}", buffer_size, interval, crash);
+				}
+			}
+		}
+		let _ = fs::remove_dir_all(&directory);
+	}
+
+	#[test]
+	fn full_flush_ends_with_a_single_empty_block() {
+		for size in (0..40).map(|i| 1000 + i * 7919) {
+			let data = bytes(37, size);
+			let mut deflater = Deflater::new();
+			deflater.write_all(&data).unwrap();
+			deflater.full_flush().unwrap();
+			let output = &deflater.output;
+			assert!(output.ends_with(&[0, 0, 0xff, 0xff]), "{} bytes: flush doesn't end with an empty block", size);
+			assert!(!output.ends_with(&[0, 0, 0xff, 0xff, 0, 0, 0, 0xff, 0xff]), "{} bytes: flush ends with two empty blocks", size);
+
+			let mut inflated = Vec::with_capacity(size + 1);
+			Decompress::new(true).decompress_vec(output, &mut inflated, FlushDecompress::Sync).unwrap();
+			assert!(inflated == data, "{} bytes: flush lost data", size);
+		}
+	}
+
+	#[test]
+	fn padding_comes_in_pieces() {
+		let mut layout = Layout::new(&EncodeOptions::for_size(10_000_000));
+		layout.written = 1234;
+		let mut padded = 0;
+		while let Some(zeros) = layout.padding() {
+			assert!(zeros.len() <= PADDING.len() && zeros.iter().all(|&byte| byte == 0));
+			padded += zeros.len() as u64;
+			layout.written += zeros.len() as u64;
+		}
+		assert_eq!(padded, layout.total - 1234);
+	}
+}
@@ -0,0 +1,121 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::convert::TryInto;
+
+use crate::util::{self, chunk, png, ChunkData, Limits};
+use crate::util::png::IText;
+use crate::png_convert::inspect::json_escape;
+
+// International text chunk holding a tag: the key as its keyword & the value as its text
+pub static CHUNK_TYPE: &[u8; 4] = &IText::CHUNK_TYPE;
+
+// Splits key=value, None unless the key can be the keyword of a text chunk
+pub fn parse(s: &str) -> Option<(String, String)> {
+	let (key, value) = s.split_once('=')?;
+	match valid_key(key) {
+		true => Some((key.to_string(), value.to_string())),
+		false => None
+	}
+}
+
+// Keywords of tags are ASCII (keywords are Latin-1, which isn't what is typed into a terminal past ASCII)
+pub fn valid_key(key: &str) -> bool {
+	key.is_ascii() && png::valid_keyword(key)
+}
+
+// Keyword & text of an iTXt chunk (inflating compressed text), None if it is malformed
+pub fn read(data: &[u8]) -> Option<(String, String)> {
+	IText::parse(data).ok().map(|text| (text.keyword, text.text))
+}
+
+// Tags of a PNG, reading the chunk headers & seeking past the data of everything else. Stops at the image data unless
+// all is set, as tags are written ahead of it (this keeps searching many PNGs fast)
+pub fn read_tags<R: Read + Seek>(source: &mut R, all: bool) -> io::Result<Vec<(String, String)>> {
+	util::read_header(source)?;
+	let limits = Limits::default();
+	let mut tags = Vec::new();
+	loop {
+		let mut header = [0u8; 8];
+		source.read_exact(&mut header)?;
+		let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+		limits.check_chunk(&header[4..8], length)?;
+		match &header[4..8] {
+			b"IEND" => break,
+			b"IDAT" if !all => break,
+			chunk_type if chunk_type == CHUNK_TYPE => {
+				let data = chunk::read_data(source, length)?;
+				source.seek(SeekFrom::Current(4))?;
+				if let Some(tag) = read(&data) {
+					tags.push(tag);
+				}
+			},
+			_ => {
+				source.seek(SeekFrom::Current(length as i64 + 4))?;
+			}
+		};
+	}
+	Ok(tags)
+}
+
+// Whether every wanted key is tagged (with the wanted value, unless it is None)
+pub fn matches(tags: &[(String, String)], wanted: &[(String, Option<String>)]) -> bool {
+	wanted.iter().all(|(key, value)| tags.iter().any(|(k, v)| k == key && match value {
+		Some(value) => v == value,
+		None => true
+	}))
+}
+
+pub fn to_json(tags: &[(String, String)]) -> String {
+	let tags: Vec<String> = tags.iter()
+		.map(|(key, value)| format!("{{\"key\":\"{}\",\"value\":\"{}\"}}", json_escape(key), json_escape(value)))
+		.collect();
+	format!("[{}]", tags.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	use crate::util::ChunkWriter;
+	use crate::util::png::Ihdr;
+
+	// PNG with a tag ahead of the image data & another one past it
+	fn png() -> (Vec<u8>, usize) {
+		let mut writer = ChunkWriter::new(Vec::new());
+		writer.write_signature().unwrap();
+		writer.write_typed(&Ihdr::new(1, 1, 8, 2)).unwrap();
+		writer.write_typed(&IText::new("project", "pngifier")).unwrap();
+		let idat = writer.get_mut().len();
+		writer.write_chunk(b"IDAT", &[0; 16]).unwrap();
+		writer.write_typed(&IText::new("owner", "late")).unwrap();
+		writer.write_chunk(b"IEND", &[]).unwrap();
+		(writer.into_inner(), idat)
+	}
+
+	#[test]
+	fn searching_stops_at_the_image_data() {
+		let (png, idat) = png();
+		let project = (String::from("project"), String::from("pngifier"));
+		let owner = (String::from("owner"), String::from("late"));
+		assert_eq!(read_tags(&mut Cursor::new(&png), false).unwrap(), vec![project.clone()]);
+		assert_eq!(read_tags(&mut Cursor::new(&png), true).unwrap(), vec![project.clone(), owner]);
+
+		// Nothing past the header of the IDAT chunk is read
+		let truncated = &png[..idat + 8];
+		assert_eq!(read_tags(&mut Cursor::new(truncated), false).unwrap(), vec![project]);
+		assert!(read_tags(&mut Cursor::new(truncated), true).is_err());
+	}
+
+	#[test]
+	fn tags_match_keys_and_values() {
+		let tags = vec![(String::from("project"), String::from("foo")), (String::from("retention"), String::from("1y"))];
+		let wanted = |pairs: &[(&str, Option<&str>)]| -> Vec<(String, Option<String>)> {
+			pairs.iter().map(|(key, value)| (key.to_string(), value.map(String::from))).collect()
+		};
+		assert!(matches(&tags, &wanted(&[("project", Some("foo")), ("retention", None)])));
+		assert!(!matches(&tags, &wanted(&[("project", Some("bar"))])));
+		assert!(!matches(&tags, &wanted(&[("project", None), ("owner", None)])));
+		assert!(parse("project=a=b") == Some((String::from("project"), String::from("a=b"))));
+		assert!(parse(" project=foo").is_none() && parse("projet").is_none());
+	}
+}
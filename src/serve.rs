@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::util;
+use crate::png_convert;
+use crate::options;
+use util::{Config, Error, Limits, Source, WriteFile};
+
+// Options a query can't set: they size the buffers of the server rather than the image (decodes keep their limits too)
+static SERVER_KEYS: [&str; 7] = ["buffer", "idat-size", "max-chunk-bytes", "max-output-bytes", "max-dimensions", "max-ratio", "max-input-bytes"];
+// Largest row an encode request can ask for (the encoder holds a few rows at a time)
+static MAX_ROW_BYTES: u64 = 64 * 1024 * 1024;
+// Largest buffer a decode request inflates into (whatever buffer the config of the server asks for)
+static MAX_BUFFER_BYTES: u64 = 64 * 1024 * 1024;
+// Largest body an encode request can send unless the server sets --max-input-bytes (its Content-Length sizes the image)
+static MAX_INPUT_BYTES: u64 = 1024 * 1024 * 1024;
+// Connections handled at once (any more are turned away until one finishes)
+static MAX_CONNECTIONS: usize = 64;
+// Time a connection may go without sending anything before it's dropped
+static IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Request {
+	method: String,
+	path: String,
+	query: HashMap<String, String>,
+	headers: HashMap<String, String>
+}
+
+impl Request {
+	fn parse<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+		let mut line = String::new();
+		reader.read_line(&mut line)?;
+		let mut parts = line.split_whitespace();
+		let method = parts.next().unwrap_or("").to_string();
+		let target = parts.next().unwrap_or("").to_string();
+		if method.is_empty() || target.is_empty() {
+			return Err(io::Error::from(io::ErrorKind::InvalidData));
+		}
+
+		let mut headers = HashMap::new();
+		loop {
+			let mut line = String::new();
+			if reader.read_line(&mut line)? == 0 {
+				return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+			}
+			let line = line.trim_end();
+			if line.is_empty() {
+				break;
+			}
+			if let Some(index) = line.find(':') {
+				headers.insert(line[..index].trim().to_lowercase(), line[index + 1..].trim().to_string());
+			}
+		}
+
+		let (path, query) = match target.find('?') {
+			Some(index) => (target[..index].to_string(), parse_query(&target[index + 1..])),
+			None => (target, HashMap::new())
+		};
+		Ok(Self {
+			method: method,
+			path: path,
+			query: query,
+			headers: headers
+		})
+	}
+
+	fn content_length(&self) -> Option<u64> {
+		self.headers.get("content-length").and_then(|s| s.parse::<u64>().ok())
+	}
+
+	// Options of the query, without the ones only the server sets
+	fn options(&self) -> HashMap<String, String> {
+		self.query.iter()
+			.filter(|(key, _)| !SERVER_KEYS.contains(&&key[..]))
+			.map(|(key, value)| (key.clone(), value.clone()))
+			.collect()
+	}
+
+	fn is_chunked(&self) -> bool {
+		match self.headers.get("transfer-encoding") {
+			Some(s) => s.to_lowercase().contains("chunked"),
+			None => false
+		}
+	}
+}
+
+// Reads a request body sent with "Transfer-Encoding: chunked"
+struct ChunkedReader<R: BufRead> {
+	source: R,
+	remaining: u64,
+	done: bool
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.done || buf.is_empty() {
+			return Ok(0);
+		}
+		if self.remaining == 0 {
+			let mut line = String::new();
+			self.source.read_line(&mut line)?;
+			let size = line.trim().split(';').next().unwrap_or("");
+			self.remaining = match u64::from_str_radix(size, 16) {
+				Ok(n) => n,
+				_ => return Err(io::Error::from(io::ErrorKind::InvalidData))
+			};
+			if self.remaining == 0 {
+				// Skip trailers
+				loop {
+					line.clear();
+					if self.source.read_line(&mut line)? == 0 || line.trim().is_empty() {
+						break;
+					}
+				}
+				self.done = true;
+				return Ok(0);
+			}
+		}
+		let limit = std::cmp::min(buf.len() as u64, self.remaining) as usize;
+		let size = self.source.read(&mut buf[..limit])?;
+		if size == 0 {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+		}
+		self.remaining -= size as u64;
+		if self.remaining == 0 {
+			let mut crlf = [0u8; 2];
+			self.source.read_exact(&mut crlf)?;
+		}
+		Ok(size)
+	}
+}
+
+// Reads a body of Content-Length bytes, failing if the connection ends before all of them arrived
+struct LengthReader<R: Read> {
+	source: R,
+	remaining: u64
+}
+
+impl<R: Read> Read for LengthReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.remaining == 0 || buf.is_empty() {
+			return Ok(0);
+		}
+		let limit = std::cmp::min(buf.len() as u64, self.remaining) as usize;
+		let size = self.source.read(&mut buf[..limit])?;
+		if size == 0 {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("the body ended {} bytes before its Content-Length", self.remaining)));
+		}
+		self.remaining -= size as u64;
+		Ok(size)
+	}
+}
+
+// Reads until the buffer is full or the stream ends, like the chunk readers expect from files
+struct FillReader<R: Read> {
+	source: R
+}
+
+impl<R: Read> Read for FillReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut total = 0;
+		while total < buf.len() {
+			match self.source.read(&mut buf[total..]) {
+				Ok(0) => break,
+				Ok(size) => total += size,
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			};
+		}
+		Ok(total)
+	}
+}
+
+// Streams a response body with "Transfer-Encoding: chunked", sending the head on the first write
+struct ChunkedWriter {
+	stream: TcpStream,
+	head: Option<Vec<u8>>,
+	started: Arc<AtomicBool>
+}
+
+impl Write for ChunkedWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		if let Some(head) = self.head.take() {
+			self.started.store(true, Ordering::SeqCst);
+			self.stream.write_all(&head)?;
+		}
+		write!(self.stream, "{:x}\r\n", buf.len())?;
+		self.stream.write_all(buf)?;
+		self.stream.write_all(b"\r\n")?;
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.stream.flush()
+	}
+}
+
+// Serves encode and decode over HTTP until the process is stopped
+// Decodes are held to limits whatever the query of a request asks for
+pub fn serve(encode: &Config, decode: &Config, limits: &Limits, bind: &str) -> Result<(), Error> {
+	let limits = &Limits {
+		max_buffer_bytes: Some(limits.max_buffer_bytes.map_or(MAX_BUFFER_BYTES, |limit| std::cmp::min(limit, MAX_BUFFER_BYTES))),
+		max_input_bytes: Some(limits.max_input_bytes.unwrap_or(MAX_INPUT_BYTES)),
+		..*limits
+	};
+	let listener = error_exp!(Bind, bind, TcpListener::bind(bind));
+	silent!({println!("Listening on \x1b[1;36mhttp://{}\x1b[0m.", bind)});
+	let connections = AtomicUsize::new(0);
+	thread::scope(|scope| {
+		for stream in listener.incoming() {
+			let stream = match stream {
+				Ok(stream) => stream,
+				_ => continue
+			};
+			if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+				connections.fetch_sub(1, Ordering::SeqCst);
+				let _ = respond(&stream, 503, "text/plain", b"Too many connections.\n");
+				continue;
+			}
+			let connections = &connections;
+			scope.spawn(move || {
+				let result = stream.set_read_timeout(Some(IDLE_TIMEOUT))
+					.and_then(|_| stream.set_write_timeout(Some(IDLE_TIMEOUT)))
+					.and_then(|_| handle(encode, decode, limits, stream));
+				if let Err(e) = result {
+					verbose!({println!("\x1b[1;33mWarning: {}\x1b[0m", e)});
+				}
+				connections.fetch_sub(1, Ordering::SeqCst);
+			});
+		}
+	});
+	Ok(())
+}
+
+fn handle(encode: &Config, decode: &Config, limits: &Limits, stream: TcpStream) -> io::Result<()> {
+	let start = Instant::now();
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let request = match Request::parse(&mut reader) {
+		Ok(request) => request,
+		Err(_) => return respond(&stream, 400, "text/plain", b"Malformed request.\n")
+	};
+
+	let expects_continue = match request.headers.get("expect") {
+		Some(s) => s.eq_ignore_ascii_case("100-continue"),
+		None => false
+	};
+	if expects_continue && request.method == "POST" {
+		(&stream).write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+	}
+
+	let body: Option<Box<dyn Read>> = match (request.content_length(), request.is_chunked()) {
+		(_, true) => Some(Box::new(FillReader { source: ChunkedReader { source: reader, remaining: 0, done: false } })),
+		(Some(length), false) => Some(Box::new(FillReader { source: LengthReader { source: reader, remaining: length } })),
+		(None, false) => None
+	};
+
+	let status = match (&request.method[..], &request.path[..], body) {
+		("GET", "/health", _) => {
+			let health = format!("{{\"status\":\"ok\",\"version\":\"{}\"}}\n", crate::VERSION);
+			respond(&stream, 200, "application/json", health.as_bytes())?;
+			200
+		},
+		("POST", "/encode", Some(body)) => serve_encode(encode, limits, &request, body, &stream)?,
+		("POST", "/decode", Some(body)) => serve_decode(decode, limits, &request, body, &stream)?,
+		("POST", "/inspect", Some(body)) => match png_convert::inspect(body) {
+			Ok(inspection) => {
+				respond(&stream, 200, "application/json", format!("{}\n", inspection.to_json()).as_bytes())?;
+				200
+			},
+			Err(_) => {
+				respond(&stream, 400, "text/plain", b"Request body is not a valid PNG.\n")?;
+				400
+			}
+		},
+		("POST", "/encode", None) | ("POST", "/decode", None) | ("POST", "/inspect", None) => {
+			respond(&stream, 411, "text/plain", b"A Content-Length or chunked request body is required.\n")?;
+			411
+		},
+		(_, "/health", _) | (_, "/encode", _) | (_, "/decode", _) | (_, "/inspect", _) => {
+			respond(&stream, 405, "text/plain", b"Method not allowed.\n")?;
+			405
+		},
+		_ => {
+			respond(&stream, 404, "text/plain", b"Not found.\n")?;
+			404
+		}
+	};
+	silent!({println!("{} {} \x1b[1;36m{}\x1b[0m in \x1b[1;36m{:?}\x1b[0m", request.method, request.path, status, start.elapsed())});
+	Ok(())
+}
+
+fn serve_encode(encode: &Config, limits: &Limits, request: &Request, body: Box<dyn Read>, stream: &TcpStream) -> io::Result<u16> {
+	let length = match request.content_length() {
+		Some(length) => length,
+		None => {
+			respond(stream, 411, "text/plain", b"Encoding requires a Content-Length.\n")?;
+			return Ok(411);
+		}
+	};
+	// The image is sized for the whole body before any of it is read
+	if let Err(e) = limits.check_input(length) {
+		let exceeded = util::Exceeded::find(&e).map_or(e.to_string(), |exceeded| exceeded.to_error(&"request body").to_string());
+		respond(stream, 413, "text/plain", format!("{}\n", exceeded).as_bytes())?;
+		return Ok(413);
+	}
+	let config = encode.with_overrides(Source::Query, request.options());
+	let options = match options::encode_options(&config, "request body", length) {
+		Ok(options) => options,
+		Err(e) => {
+			respond(stream, 400, "text/plain", format!("{}\n", e).as_bytes())?;
+			return Ok(400);
+		}
+	};
+	if options.chunk_size > MAX_ROW_BYTES {
+		respond(stream, 400, "text/plain", format!("Rows of {} bytes are over the {} bytes a request can ask for.\n", options.chunk_size, MAX_ROW_BYTES).as_bytes())?;
+		return Ok(400);
+	}
+
+	let (mut write_file, started) = chunked_response(stream, options.format.mime_type())?;
+	let result = png_convert::encode(body, png_convert::sink::new(options.format, &mut write_file), &options);
+	finish_response(stream, write_file, started, result, "Unable to encode request body as an image.")
+}
+
+fn serve_decode(decode: &Config, limits: &Limits, request: &Request, body: Box<dyn Read>, stream: &TcpStream) -> io::Result<u16> {
+	let config = decode.with_overrides(Source::Query, request.options());
+	let buffer_size = match options::decode_buffer_size(&config) {
+		Ok(buffer_size) => buffer_size,
+		Err(e) => {
+			respond(stream, 400, "text/plain", format!("{}\n", e).as_bytes())?;
+			return Ok(400);
+		}
+	};
+
+	let (mut write_file, started) = chunked_response(stream, "application/octet-stream")?;
+	// Holes of sparse files are streamed as zeros
+	let result = png_convert::decode(body, request.content_length().unwrap_or(0), &mut write_file, buffer_size, limits).map(|_| ());
+	let message = match result.as_ref().err().and_then(util::Exceeded::find) {
+		Some(exceeded) => exceeded.to_error(&"request body").to_string(),
+		None => String::from("Unable to decode request body from PNG.")
+	};
+	finish_response(stream, write_file, started, result, &message)
+}
+
+fn chunked_response(stream: &TcpStream, content_type: &str) -> io::Result<(WriteFile, Arc<AtomicBool>)> {
+	let started = Arc::new(AtomicBool::new(false));
+	let head = format!(
+		"HTTP/1.1 200 OK\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+		content_type
+	);
+	let writer = ChunkedWriter {
+		stream: stream.try_clone()?,
+		head: Some(head.into_bytes()),
+		started: started.clone()
+	};
+	Ok((WriteFile::from_writer("response", Box::new(writer)), started))
+}
+
+fn finish_response(stream: &TcpStream, mut write_file: WriteFile, started: Arc<AtomicBool>, result: io::Result<()>, message: &str) -> io::Result<u16> {
+	let result = result.and_then(|_| write_file.flush());
+	drop(write_file);
+	match (result, started.load(Ordering::SeqCst)) {
+		(Ok(_), true) => {
+			let mut stream = stream;
+			stream.write_all(b"0\r\n\r\n")?;
+			Ok(200)
+		},
+		(Ok(_), false) => {
+			respond(stream, 200, "application/octet-stream", b"")?;
+			Ok(200)
+		},
+		// Nothing was sent yet, so the error can still be reported
+		(Err(_), false) => {
+			respond(stream, 400, "text/plain", format!("{}\n", message).as_bytes())?;
+			Ok(400)
+		},
+		// Closing without the final chunk tells the client the body is incomplete
+		(Err(e), true) => Err(e)
+	}
+}
+
+fn respond(stream: &TcpStream, status: u16, content_type: &str, body: &[u8]) -> io::Result<()> {
+	let reason = match status {
+		200 => "OK",
+		400 => "Bad Request",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		411 => "Length Required",
+		413 => "Payload Too Large",
+		503 => "Service Unavailable",
+		_ => "Internal Server Error"
+	};
+	let mut stream = stream;
+	write!(
+		stream,
+		"HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		status, reason, content_type, body.len()
+	)?;
+	stream.write_all(body)?;
+	stream.flush()
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+	query.split('&')
+		.filter(|pair| !pair.is_empty())
+		.map(|pair| match pair.find('=') {
+			Some(index) => (percent_decode(&pair[..index]), percent_decode(&pair[index + 1..])),
+			None => (percent_decode(pair), String::from("true"))
+		})
+		.collect()
+}
+
+fn percent_decode(input: &str) -> String {
+	let bytes = input.as_bytes();
+	let mut output = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'+' => output.push(b' '),
+			b'%' if i + 2 < bytes.len() => {
+				let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+				match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+					Some(b) => {
+						output.push(b);
+						i += 2;
+					},
+					_ => output.push(b'%')
+				};
+			},
+			b => output.push(b)
+		};
+		i += 1;
+	}
+	String::from_utf8_lossy(&output).to_string()
+}
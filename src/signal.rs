@@ -0,0 +1,78 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use pngifier::util::CancellationToken;
+
+// Cancelled by the first Ctrl-C or SIGTERM (the input of every encode & decode is read through it)
+static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+// Signal that was received (0 until then)
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+pub fn token() -> &'static CancellationToken {
+	TOKEN.get_or_init(CancellationToken::new)
+}
+
+// Code the process exits with once cancelled (128 + the signal, like a shell reports a killed process)
+pub fn exit_code() -> Option<i32> {
+	match SIGNAL.load(Ordering::SeqCst) {
+		0 => None,
+		signal => Some(128 + signal)
+	}
+}
+
+// Records the signal & cancels the token, so encodes & decodes stop at their next read and drop (ie: remove) their
+// partial outputs. Returns false for a second signal, which exits at once
+fn cancel(signal: i32) -> bool {
+	if SIGNAL.swap(signal, Ordering::SeqCst) != 0 {
+		return false;
+	}
+	if let Some(token) = TOKEN.get() {
+		token.cancel();
+	}
+	true
+}
+
+#[cfg(unix)]
+pub fn install() {
+	extern "C" fn handle(signal: libc::c_int) {
+		if !cancel(signal) {
+			unsafe { libc::_exit(128 + signal) };
+		}
+	}
+
+	token();
+	unsafe {
+		let mut action: libc::sigaction = std::mem::zeroed();
+		action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+		// Without SA_RESTART blocking reads (ie: of stdin) return instead of waiting for more input
+		action.sa_flags = 0;
+		libc::sigemptyset(&mut action.sa_mask);
+		libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+		libc::sigaction(libc::SIGTERM, &action, std::ptr::null_mut());
+	}
+}
+
+#[cfg(windows)]
+pub fn install() {
+	extern crate winapi;
+	use winapi::shared::minwindef::{BOOL, DWORD, TRUE};
+	use winapi::um::consoleapi::SetConsoleCtrlHandler;
+
+	unsafe extern "system" fn handle(_: DWORD) -> BOOL {
+		// Ctrl-C, Ctrl-Break & closing the console are all reported as SIGINT
+		if !cancel(2) {
+			std::process::exit(130);
+		}
+		TRUE
+	}
+
+	token();
+	unsafe {
+		SetConsoleCtrlHandler(Some(handle), TRUE);
+	}
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn install() {
+	token();
+}
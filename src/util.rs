@@ -9,12 +9,36 @@ pub use self::error::*;
 mod error;
 
 pub mod color_type;
-pub use self::chunk::Chunk;
-mod chunk;
+pub mod dimensions;
+pub mod reed_solomon;
+pub mod timestamp;
+pub use self::chunk::{Chunk, Chunks};
+pub mod chunk;
+
+pub use self::png::{ChunkData, ChunkWriter, PngChunk};
+pub mod png;
+
+pub use self::cancel::{Cancellable, CancellationToken, Cancelled};
+mod cancel;
+
+pub use self::limits::{Exceeded, Limits};
+pub mod limits;
 
 pub use self::file::*;
 mod file;
 
+pub use self::sparse::{Hole, HoleMap};
+pub mod sparse;
+
+pub use self::metadata::{Metadata, Preserve};
+pub mod metadata;
+
+pub use self::config::{Config, Source};
+mod config;
+
+#[cfg(test)]
+pub mod testing;
+
 /// Source: https://docs.rs/crate/ansi_term/0.12.1/source/src/windows.rs
 /// Enables ANSI code support on Windows 10.
 ///
@@ -0,0 +1,160 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Stops encodes & decodes from another thread (ie: a signal handler or a request that went away) at the next read
+// of their input, which then fails with Cancelled
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::SeqCst);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::SeqCst)
+	}
+
+	pub fn check(&self) -> io::Result<()> {
+		match self.is_cancelled() {
+			true => Err(io::Error::other(Cancelled)),
+			false => Ok(())
+		}
+	}
+
+	// Wraps the input of an encode or decode so it stops once cancelled
+	pub fn reader<R: Read>(&self, source: R) -> Cancellable<R> {
+		Cancellable {
+			source: source,
+			token: self.clone()
+		}
+	}
+}
+
+// Error an input fails with once its token is cancelled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cancelled;
+
+impl Cancelled {
+	// Whether a failed encode or decode was cancelled rather than anything else going wrong
+	pub fn find(error: &io::Error) -> bool {
+		error.get_ref().is_some_and(|inner| inner.is::<Self>())
+	}
+}
+
+impl fmt::Display for Cancelled {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "cancelled")
+	}
+}
+
+impl std::error::Error for Cancelled {}
+
+pub struct Cancellable<R: Read> {
+	source: R,
+	token: CancellationToken
+}
+
+impl<R: Read> Read for Cancellable<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.token.check()?;
+		match self.source.read(buf) {
+			// Signals interrupt blocking reads (ie: of stdin), which would otherwise be retried
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+				self.token.check()?;
+				Err(io::Error::from(io::ErrorKind::Interrupted))
+			},
+			result => result
+		}
+	}
+}
+
+impl<R: Read + Seek> Seek for Cancellable<R> {
+	fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+		self.source.seek(position)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Mutex;
+
+	use crate::png_convert::{decode, encode, sink, EncodeOptions};
+	use crate::util::testing::bytes;
+	use crate::util::{Limits, WriteFile};
+
+	// Input cancelling its token once offset bytes have been read, counting what was read
+	struct CancelAt<'a> {
+		data: &'a [u8],
+		read: usize,
+		offset: usize,
+		token: CancellationToken
+	}
+
+	impl Read for CancelAt<'_> {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			let size = (&self.data[self.read..]).read(buf)?;
+			self.read += size;
+			if self.read >= self.offset {
+				self.token.cancel();
+			}
+			Ok(size)
+		}
+	}
+
+	fn cancel_at(data: &[u8], offset: usize) -> (CancelAt<'_>, CancellationToken) {
+		let token = CancellationToken::new();
+		(CancelAt { data: data, read: 0, offset: offset, token: token.clone() }, token)
+	}
+
+	fn options(size: usize) -> EncodeOptions {
+		let mut options = EncodeOptions::for_size(size as u64);
+		options.buffer_size = 4096;
+		options
+	}
+
+	#[test]
+	fn cancelling_stops_an_encode_mid_stream() {
+		let data = bytes(0x5be0_cd19, 1 << 20);
+		let (source, token) = cancel_at(&data, 100_000);
+		let mut reader = token.reader(source);
+		let output = Arc::new(Mutex::new(Vec::new()));
+		let options = options(data.len());
+		let result = encode(&mut reader, sink::new(options.format, WriteFile::memory(output.clone())), &options);
+		assert!(Cancelled::find(&result.unwrap_err()));
+		// Nothing is read past the read that cancelled
+		assert!(reader.source.read < 100_000 + 4096, "read {} bytes", reader.source.read);
+		assert!(!output.lock().unwrap().ends_with(b"IEND\xae\x42\x60\x82"));
+
+		let mut reader = token.reader(&data[..]);
+		let result = encode(&mut reader, sink::new(options.format, WriteFile::memory(Arc::new(Mutex::new(Vec::new())))), &options);
+		assert!(Cancelled::find(&result.unwrap_err()));
+	}
+
+	#[test]
+	fn cancelling_stops_a_decode_mid_stream() {
+		let data = bytes(0x1f83_d9ab, 1 << 20);
+		let png = Arc::new(Mutex::new(Vec::new()));
+		let options = options(data.len());
+		encode(&data[..], sink::new(options.format, WriteFile::memory(png.clone())), &options).unwrap();
+		let png = png.lock().unwrap().clone();
+
+		let (source, token) = cancel_at(&png, png.len() / 4);
+		let mut reader = token.reader(source);
+		let mut write_file = WriteFile::memory(Arc::new(Mutex::new(Vec::new())));
+		let result = decode(&mut reader, png.len() as u64, &mut write_file, 4096, &Limits::default());
+		assert!(Cancelled::find(&result.unwrap_err()));
+		assert!(reader.source.read < png.len() / 2, "read {} of {} bytes", reader.source.read, png.len());
+
+		// Other failures aren't mistaken for it
+		assert!(!Cancelled::find(&io::Error::other("interrupted")));
+		assert!(!Cancelled::find(&io::Error::from(io::ErrorKind::Interrupted)));
+	}
+}
@@ -5,8 +5,7 @@ use std::io;
 use std::io::prelude::*;
 use std::convert::TryInto;
 
-use crate::util;
-use util::ReadFile;
+use crate::util::{Limits, PngChunk};
 
 #[derive(Debug)]
 pub struct Chunk {
@@ -18,21 +17,28 @@ pub struct Chunk {
 
 impl Chunk {
 	// Read entire chunk: length + content + crc
-	pub fn new(source: &mut ReadFile) -> io::Result<Option<Self>> {
+	pub fn new<R: Read>(source: &mut R) -> io::Result<Option<Self>> {
+		Self::limited(source, &Limits::default())
+	}
+
+	// Reads a chunk of at most limits.max_chunk_bytes of data
+	pub fn limited<R: Read>(source: &mut R, limits: &Limits) -> io::Result<Option<Self>> {
 		let mut buf = vec![0u8; 4];
 		let initial_size = source.read(&mut buf)?;
 		if initial_size == 0 {
 			return Ok(None)
 		}
+		// Reads from pipes & sockets may return less than asked for
+		source.read_exact(&mut buf[initial_size..])?;
 
 		let size = read_be_u32(&mut &buf[..]) as usize;
-		source.read(&mut buf)?;
+		source.read_exact(&mut buf)?;
+		limits.check_chunk(&buf, size as u64)?;
 
-		let mut data_buf = vec![0u8; size];
-		source.read(&mut data_buf)?;
+		let data_buf = read_data(source, size as u64)?;
 
 		let mut crc_buf = vec![0u8; 4];
-		source.read(&mut crc_buf)?;
+		source.read_exact(&mut crc_buf)?;
 
 		Ok(Some(Chunk {
 			header: buf,
@@ -51,17 +57,103 @@ impl Chunk {
 	}
 
 	pub fn verify_crc(&self) -> bool {
-		let merged = [self.get_header(), self.get_data()].concat();
-		&Chunk::get_crc(&merged) == &self.crc[..]
+		Chunk::crc(self.get_header(), self.get_data())[..] == self.crc[..]
 	}
 
-	pub fn get_crc(data: &[u8]) -> [u8; 4] {
+	// Reads the data into the struct of its type
+	pub fn parse(&self) -> io::Result<PngChunk> {
+		PngChunk::parse(self.get_header(), self.get_data())
+	}
+
+	// CRC of a chunk type & its data, hashed one after the other instead of merged
+	pub fn crc(header_type: &[u8], data: &[u8]) -> [u8; 4] {
 		let mut crc_hash = Hasher::new();
-		crc_hash.update(&data);
+		crc_hash.update(header_type);
+		crc_hash.update(data);
 		crc_hash.finalize().to_be_bytes()
 	}
 }
 
+// Chunk borrowed from the bytes of a whole PNG (ie: a memory map) without copying
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkRef<'a> {
+	header: &'a [u8],
+	data: &'a [u8],
+	crc: &'a [u8],
+	pub total_data: usize
+}
+
+impl<'a> ChunkRef<'a> {
+	pub fn get_header(&self) -> &'a [u8] {
+		self.header
+	}
+
+	pub fn get_data(&self) -> &'a [u8] {
+		self.data
+	}
+
+	pub fn verify_crc(&self) -> bool {
+		Chunk::crc(self.header, self.data)[..] == *self.crc
+	}
+
+	pub fn parse(&self) -> io::Result<PngChunk> {
+		PngChunk::parse(self.header, self.data)
+	}
+}
+
+// Walks the chunks following the signature of a PNG, stopping at the end of the bytes
+pub struct Chunks<'a> {
+	bytes: &'a [u8],
+	offset: usize
+}
+
+impl<'a> Chunks<'a> {
+	pub fn new(bytes: &'a [u8], offset: usize) -> Self {
+		Self {
+			bytes: bytes,
+			offset: offset
+		}
+	}
+}
+
+impl<'a> Iterator for Chunks<'a> {
+	type Item = io::Result<ChunkRef<'a>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let rest = &self.bytes[std::cmp::min(self.offset, self.bytes.len())..];
+		if rest.is_empty() {
+			return None;
+		}
+		if rest.len() < 12 {
+			self.offset = self.bytes.len();
+			return Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+		}
+		let size = read_be_u32(&mut &rest[..4]) as usize;
+		if rest.len() - 12 < size {
+			self.offset = self.bytes.len();
+			return Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+		}
+		let chunk = ChunkRef {
+			header: &rest[4..8],
+			data: &rest[8..8 + size],
+			crc: &rest[8 + size..12 + size],
+			total_data: 12 + size
+		};
+		self.offset += chunk.total_data;
+		Some(Ok(chunk))
+	}
+}
+
+// Reads length bytes of chunk data, growing with the data actually read instead of trusting the length up front
+pub fn read_data<R: Read>(source: &mut R, length: u64) -> io::Result<Vec<u8>> {
+	let mut data = Vec::new();
+	source.take(length).read_to_end(&mut data)?;
+	match data.len() as u64 == length {
+		true => Ok(data),
+		false => Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+	}
+}
+
 fn read_be_u32(input: &mut &[u8]) -> u32 {
 	let (int_bytes, rest) = input.split_at(std::mem::size_of::<u32>());
 	*input = rest;
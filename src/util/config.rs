@@ -0,0 +1,404 @@
+extern crate toml;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::util;
+use util::Error;
+
+// Arguments that may be given defaults outside of the command line (argument name, config key)
+static CONFIG_KEYS: &[(&str, &str)] = &[
+	("WIDTH", "width"),
+	("HEIGHT", "height"),
+	("ASPECT", "aspect"),
+	("FIT", "fit"),
+	("BYTES", "buffer"),
+	("DEPTH", "depth"),
+	("COLOR_TYPE", "type"),
+	("ACCEPT", "yes"),
+	("VERIFY", "verify"),
+	("VERBOSE", "verbose"),
+	("SILENT", "silent"),
+	("PROGRESS", "progress"),
+	("STREAM", "stream"),
+	("TRIM", "trim"),
+	("DEBOUNCE", "debounce"),
+	("BIND", "bind"),
+	("BITS", "bits-per-channel"),
+	("ROBUST", "robust"),
+	("LEVELS", "levels"),
+	("FORMAT", "format"),
+	("APNG", "apng"),
+	("FRAME_SIZE", "frame-size"),
+	("BANNER", "banner"),
+	("BANNER_QR", "banner-qr"),
+	("IDAT_SIZE", "idat-size"),
+	("MTIME", "mtime"),
+	("RESUME", "resume"),
+	("CHECKPOINT_INTERVAL", "checkpoint-interval"),
+	("MMAP", "mmap"),
+	("NO_SPARSE", "no-sparse"),
+	("PRESERVE", "preserve"),
+	("TAG", "tag"),
+	("CONTAINER", "container"),
+	("POLYGLOT", "polyglot"),
+	("MAX_CHUNK_BYTES", "max-chunk-bytes"),
+	("MAX_OUTPUT_BYTES", "max-output-bytes"),
+	("MAX_INPUT_BYTES", "max-input-bytes"),
+	("MAX_DIMENSIONS", "max-dimensions"),
+	("MAX_RATIO", "max-ratio"),
+	("NO_CLOBBER", "no-clobber"),
+	("BACKUP", "backup")
+];
+
+// Tables a config file (or a profile) may hold besides its keys
+static SUBCOMMAND_TABLES: [&str; 2] = ["encode", "decode"];
+
+static PROJECT_CONFIG: &str = ".pngifier.toml";
+static ENV_PREFIX: &str = "PNGIFIER_";
+
+#[derive(Clone)]
+pub enum Source {
+	Flag,
+	Query,
+	Environment(String),
+	Profile(String, PathBuf),
+	File(PathBuf)
+}
+
+impl fmt::Display for Source {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Source::Flag => write!(f, "flag"),
+			Source::Query => write!(f, "query"),
+			Source::Environment(s) => write!(f, "env {}", s),
+			Source::Profile(s, p) => write!(f, "profile '{}' in {}", s, p.display()),
+			Source::File(p) => write!(f, "{}", p.display())
+		}
+	}
+}
+
+#[derive(Clone)]
+struct Layer {
+	source: Source,
+	values: HashMap<String, String>
+}
+
+// Resolves options from overrides, flags, environment variables, profiles and config files (in that order)
+pub struct Config<'a> {
+	matches: &'a clap::ArgMatches<'a>,
+	overrides: Option<Layer>,
+	layers: Vec<Layer>,
+	// Config keys given to --reset, which only flags & overrides can set
+	reset: Vec<String>
+}
+
+impl<'a> Config<'a> {
+	pub fn load(matches: &'a clap::ArgMatches<'a>, subcommand: &str) -> Result<Self, Error> {
+		let paths: Vec<PathBuf> = project_config_path().into_iter().chain(user_config_path()).collect();
+		Self::from_sources(matches, subcommand, &paths, &|name| env::var(name).ok())
+	}
+
+	// Config of the files at paths (most important first) & the environment variables read by var
+	fn from_sources(matches: &'a clap::ArgMatches<'a>, subcommand: &str, paths: &[PathBuf], var: &dyn Fn(&str) -> Option<String>) -> Result<Self, Error> {
+		let mut files: Vec<(PathBuf, toml::value::Table)> = Vec::new();
+		for path in paths.iter() {
+			if path.is_file() {
+				files.push((path.clone(), read_table(path)?));
+			}
+		}
+
+		let mut layers: Vec<Layer> = Vec::new();
+
+		let mut values = HashMap::new();
+		for (_, key) in CONFIG_KEYS.iter() {
+			if let Some(value) = var(&env_name(key)) {
+				values.insert(key.to_string(), value);
+			}
+		}
+		layers.push(Layer { source: Source::Environment(format!("{}*", ENV_PREFIX)), values: values });
+
+		let profile = match matches.value_of("PROFILE") {
+			Some(s) => Some(s.to_string()),
+			None => var(&env_name("profile"))
+		};
+		if let Some(profile) = profile {
+			let mut found = false;
+			for (path, table) in files.iter() {
+				let profile_table = table.get("profile")
+					.and_then(|t| t.get(&profile[..]))
+					.and_then(|t| t.as_table());
+				if let Some(profile_table) = profile_table {
+					found = true;
+					layers.push(Layer {
+						source: Source::Profile(profile.clone(), path.clone()),
+						values: flatten(profile_table, subcommand)
+					});
+				}
+			}
+			if !found {
+				error!(UnknownProfile, profile);
+			}
+		}
+
+		for (path, table) in files.iter() {
+			layers.push(Layer {
+				source: Source::File(path.clone()),
+				values: flatten(table, subcommand)
+			});
+		}
+
+		let mut reset = Vec::new();
+		for key in matches.values_of("RESET").into_iter().flatten() {
+			if !is_key(key) {
+				error!(UnknownConfigKey, format!("'{}' given to --reset", key));
+			}
+			reset.push(key.to_string());
+		}
+
+		Ok(Self {
+			matches: matches,
+			overrides: None,
+			layers: layers,
+			reset: reset
+		})
+	}
+
+	// Copy of the config where the given config keys take precedence over everything else
+	pub fn with_overrides(&self, source: Source, values: HashMap<String, String>) -> Self {
+		Self {
+			matches: self.matches,
+			overrides: Some(Layer { source: source, values: values }),
+			layers: self.layers.clone(),
+			reset: self.reset.clone()
+		}
+	}
+
+	pub fn value_of(&self, name: &str) -> Option<String> {
+		self.lookup(name).map(|(value, _)| value)
+	}
+
+	// Every value of an argument given more than once (anywhere but the command line gives a single value)
+	pub fn values_of(&self, name: &str) -> Vec<String> {
+		match (self.source(name), self.matches.values_of(name)) {
+			(Some(Source::Flag), Some(values)) => values.map(|value| value.to_string()).collect(),
+			_ => self.value_of(name).into_iter().collect()
+		}
+	}
+
+	pub fn is_present(&self, name: &str) -> bool {
+		match self.lookup(name) {
+			Some((value, _)) => is_truthy(&value),
+			None => false
+		}
+	}
+
+	// Where the value of an argument was taken from (None if it uses the built-in default)
+	pub fn source(&self, name: &str) -> Option<Source> {
+		self.lookup(name).map(|(_, source)| source)
+	}
+
+	// Formatted origin of an argument for the verbose configuration printout
+	pub fn origin(&self, name: &str) -> String {
+		match self.source(name) {
+			Some(Source::Flag) | None => String::new(),
+			Some(s) => format!(" \x1b[0;37m({})\x1b[1;33m", s)
+		}
+	}
+
+	fn lookup(&self, name: &str) -> Option<(String, Source)> {
+		if let (Some(overrides), Some(key)) = (&self.overrides, config_key(name)) {
+			if let Some(value) = overrides.values.get(key) {
+				return Some((value.clone(), overrides.source.clone()));
+			}
+		}
+		if self.matches.is_present(name) {
+			let value = match self.matches.value_of(name) {
+				Some(s) => s.to_string(),
+				None => String::from("true")
+			};
+			return Some((value, Source::Flag));
+		}
+		let key = config_key(name)?;
+		if self.reset.iter().any(|reset| reset == key) {
+			return None;
+		}
+		for layer in self.layers.iter() {
+			if let Some(value) = layer.values.get(key) {
+				let source = match &layer.source {
+					Source::Environment(_) => Source::Environment(env_name(key)),
+					s => s.clone()
+				};
+				return Some((value.clone(), source));
+			}
+		}
+		None
+	}
+}
+
+fn config_key(name: &str) -> Option<&'static str> {
+	CONFIG_KEYS.iter().find(|(arg, _)| *arg == name).map(|(_, key)| *key)
+}
+
+fn is_key(key: &str) -> bool {
+	CONFIG_KEYS.iter().any(|(_, known)| *known == key)
+}
+
+fn env_name(key: &str) -> String {
+	format!("{}{}", ENV_PREFIX, key.to_uppercase())
+}
+
+fn is_truthy(value: &str) -> bool {
+	!matches!(&value.to_lowercase()[..], "" | "0" | "false" | "no" | "n" | "off")
+}
+
+// Merges the top level keys of a table with the keys of its subcommand table (ie: [encode])
+fn flatten(table: &toml::value::Table, subcommand: &str) -> HashMap<String, String> {
+	let mut values = HashMap::new();
+	let sub_table = table.get(subcommand).and_then(|t| t.as_table());
+	for t in Some(table).into_iter().chain(sub_table) {
+		for (key, value) in t.iter() {
+			let value = match value {
+				toml::Value::String(s) => s.clone(),
+				toml::Value::Integer(i) => i.to_string(),
+				toml::Value::Float(f) => f.to_string(),
+				toml::Value::Boolean(b) => b.to_string(),
+				_ => continue
+			};
+			values.insert(key.clone(), value);
+		}
+	}
+	values
+}
+
+fn read_table(path: &Path) -> Result<toml::value::Table, Error> {
+	let contents = error_exp!(ReadFail, path.display(), fs::read_to_string(path));
+	let table = match contents.parse::<toml::Value>() {
+		Ok(toml::Value::Table(t)) => t,
+		_ => error!(ParseConfig, path.display())
+	};
+	check_keys(&table, path, "")?;
+	Ok(table)
+}
+
+// Fails on keys no option reads (ie: a misspelled key), which would be ignored otherwise (prefix names the table
+// being checked, ie: profile.archive.)
+fn check_keys(table: &toml::value::Table, path: &Path, prefix: &str) -> Result<(), Error> {
+	for (key, value) in table.iter() {
+		match (value.as_table(), &key[..]) {
+			(Some(profiles), "profile") if prefix.is_empty() => for (name, profile) in profiles.iter() {
+				match profile.as_table() {
+					Some(profile) => check_keys(profile, path, &format!("profile.{}.", name))?,
+					None => error!(UnknownConfigKey, format!("'profile.{}' in {}", name, path.display()))
+				};
+			},
+			(Some(sub_table), _) if SUBCOMMAND_TABLES.contains(&&key[..]) => for sub_key in sub_table.keys() {
+				if !is_key(sub_key) {
+					error!(UnknownConfigKey, format!("'{}{}.{}' in {}", prefix, key, sub_key, path.display()));
+				}
+			},
+			(None, _) if is_key(key) => (),
+			_ => error!(UnknownConfigKey, format!("'{}{}' in {}", prefix, key, path.display()))
+		};
+	}
+	Ok(())
+}
+
+fn project_config_path() -> Option<PathBuf> {
+	let current = env::current_dir().ok()?;
+	current.ancestors()
+		.map(|dir| dir.join(PROJECT_CONFIG))
+		.find(|path| path.is_file())
+}
+
+fn user_config_path() -> Option<PathBuf> {
+	let base = match env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) => PathBuf::from(dir),
+		None if cfg!(windows) => PathBuf::from(env::var_os("APPDATA")?),
+		None => PathBuf::from(env::var_os("HOME")?).join(".config")
+	};
+	Some(base.join("pngifier").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use clap::{App, Arg, ArgMatches};
+
+	fn matches(args: &[&str]) -> ArgMatches<'static> {
+		App::new("pngifier")
+			.arg(Arg::with_name("BYTES").short("b").long("buffer").takes_value(true))
+			.arg(Arg::with_name("VERBOSE").short("v").long("verbose"))
+			.arg(Arg::with_name("PROFILE").long("profile").takes_value(true))
+			.arg(Arg::with_name("RESET").long("reset").takes_value(true).multiple(true).number_of_values(1))
+			.get_matches_from(std::iter::once("pngifier").chain(args.iter().cloned()))
+	}
+
+	// Project & user config files of a test of its own (in that order, like load finds them)
+	fn files(name: &str, project: &str, user: &str) -> Vec<PathBuf> {
+		let directory = env::temp_dir().join(format!("pngifier-config-{}-{}", name, std::process::id()));
+		fs::create_dir_all(&directory).unwrap();
+		let paths = vec![directory.join(PROJECT_CONFIG), directory.join("config.toml")];
+		fs::write(&paths[0], project).unwrap();
+		fs::write(&paths[1], user).unwrap();
+		paths
+	}
+
+	fn load<'a>(matches: &'a ArgMatches<'a>, paths: &[PathBuf], variables: &[(&str, &str)]) -> Result<Config<'a>, Error> {
+		let variables: HashMap<String, String> = variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+		Config::from_sources(matches, "encode", paths, &|name| variables.get(name).cloned())
+	}
+
+	#[test]
+	fn flags_beat_the_environment_profiles_and_files() {
+		let paths = files("order", "buffer = \"3kb\"\n[profile.big]\nbuffer = \"2kb\"\n", "buffer = \"4kb\"\n");
+		let environment = [("PNGIFIER_BUFFER", "1kb"), ("PNGIFIER_PROFILE", "big")];
+		let flag = matches(&["-b", "100"]);
+		let none = matches(&[]);
+		let expected = [
+			(load(&flag, &paths, &environment).unwrap(), "100"),
+			(load(&none, &paths, &environment).unwrap(), "1kb"),
+			(load(&none, &paths, &environment[1..]).unwrap(), "2kb"),
+			(load(&none, &paths, &[]).unwrap(), "3kb"),
+			(load(&none, &paths[1..], &[]).unwrap(), "4kb")
+		];
+		for (config, value) in expected.iter() {
+			assert_eq!(config.value_of("BYTES").as_deref(), Some(*value));
+		}
+		assert!(load(&none, &[], &[]).unwrap().value_of("BYTES").is_none());
+		let _ = fs::remove_dir_all(paths[0].parent().unwrap());
+	}
+
+	#[test]
+	fn unknown_keys_are_reported() {
+		for (project, key) in [("verbos = true\n", "'verbos'"), ("[encode]\ntrimm = 1\n", "'encode.trimm'"), ("[profile.big]\nbufer = \"1kb\"\n", "'profile.big.bufer'"), ("[encodee]\nverbose = true\n", "'encodee'")] {
+			let paths = files("unknown", project, "");
+			let none = matches(&[]);
+			match load(&none, &paths, &[]) {
+				Err(Error::UnknownConfigKey(s)) => assert!(s.starts_with(key), "{} reported as {}", key, s),
+				_ => panic!("{} wasn't reported", key)
+			};
+			let _ = fs::remove_dir_all(paths[0].parent().unwrap());
+		}
+		let reset = matches(&["--reset", "verbos"]);
+		assert!(matches!(load(&reset, &[], &[]), Err(Error::UnknownConfigKey(_))));
+	}
+
+	#[test]
+	fn reset_turns_off_booleans_of_the_config() {
+		let paths = files("reset", "verbose = true\nbuffer = \"1kb\"\n", "");
+		let none = matches(&[]);
+		assert!(load(&none, &paths, &[]).unwrap().is_present("VERBOSE"));
+		let reset = matches(&["--reset", "verbose", "--reset", "buffer"]);
+		let config = load(&reset, &paths, &[("PNGIFIER_VERBOSE", "1")]).unwrap();
+		assert!(!config.is_present("VERBOSE"));
+		assert!(config.value_of("BYTES").is_none());
+		// Flags given along with --reset still count
+		let flag = matches(&["--reset", "verbose", "-v"]);
+		assert!(load(&flag, &paths, &[]).unwrap().is_present("VERBOSE"));
+		let _ = fs::remove_dir_all(paths[0].parent().unwrap());
+	}
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+// Largest width or height allowed in an IHDR chunk (2^31 - 1)
+pub static MAX_DIMENSION: u64 = 0x7fff_ffff;
+
+lazy_static! {
+	static ref PRESETS: HashMap<String, (u64, u64)> = {
+		let mut m = HashMap::new();
+		m.insert(String::from("720p"), (1280, 720));
+		m.insert(String::from("1080p"), (1920, 1080));
+		m.insert(String::from("1440p"), (2560, 1440));
+		m.insert(String::from("4k"), (3840, 2160));
+		m.insert(String::from("8k"), (7680, 4320));
+		m.insert(String::from("instagram"), (1080, 1080));
+		m.insert(String::from("instagram-portrait"), (1080, 1350));
+		m.insert(String::from("a4-150dpi"), (1240, 1754));
+		m.insert(String::from("a4-300dpi"), (2480, 3508));
+		m.insert(String::from("letter-300dpi"), (2550, 3300));
+		m
+	};
+}
+
+pub fn preset(name: &str) -> Option<(u64, u64)> {
+	PRESETS.get(&name.to_lowercase()).copied()
+}
+
+// Parse a pair of positive integers of up to 2^31 - 1 separated by any of the given characters (ie: 16:9, 1920x1080)
+pub fn parse_pair(input: &str, separators: &[char]) -> Option<(u64, u64)> {
+	let lower = input.to_lowercase();
+	let mut parts = lower.splitn(2, |c| separators.contains(&c));
+	let first = parts.next()?.trim().parse::<u64>().ok()?;
+	let second = parts.next()?.trim().parse::<u64>().ok()?;
+	if !is_valid(first, second) {
+		return None;
+	}
+	Some((first, second))
+}
+
+// Rows needed to hold max_bytes with the given width (None once a row overflows)
+pub fn rows_for(max_bytes: u64, width: u64, bytes_per_px: u64) -> Option<u64> {
+	let row = width.checked_mul(bytes_per_px).filter(|&row| row != 0)?;
+	Some(std::cmp::max(1, max_bytes.div_ceil(row)))
+}
+
+// Columns needed to hold max_bytes with the given height (None once a column overflows)
+pub fn columns_for(max_bytes: u64, height: u64, bytes_per_px: u64) -> Option<u64> {
+	let column = height.checked_mul(bytes_per_px).filter(|&column| column != 0)?;
+	Some(std::cmp::max(1, max_bytes.div_ceil(column)))
+}
+
+// Smallest image of the given aspect ratio able to hold max_bytes (None once the sides overflow)
+pub fn fit_aspect(max_bytes: u64, bytes_per_px: u64, ratio: (u64, u64)) -> Option<(u64, u64)> {
+	let (ratio_width, ratio_height) = ratio;
+	if bytes_per_px == 0 || ratio_width == 0 || ratio_height == 0 {
+		return None;
+	}
+	let pixels = std::cmp::max(1, max_bytes.div_ceil(bytes_per_px));
+	let width_for = |height: u64| Some(std::cmp::max(1, height.checked_mul(ratio_width)?.div_ceil(ratio_height)));
+	let mut height = std::cmp::max(1, ((pixels as f64) * (ratio_height as f64) / (ratio_width as f64)).sqrt() as u64);
+	while width_for(height)?.checked_mul(height)? < pixels {
+		height += 1;
+	}
+	Some((width_for(height)?, height))
+}
+
+pub fn is_valid(width: u64, height: u64) -> bool {
+	width > 0 && height > 0 && width <= MAX_DIMENSION && height <= MAX_DIMENSION
+}
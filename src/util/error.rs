@@ -34,38 +34,134 @@ pub enum Error {
 	ParseBuffer(String),
 	ParseColorType(String),
 	ParseBitDepth(String),
-	WidthAndHeightDefined,
+	ConflictingDimensions(String),
+	ParseAspect(String),
+	ParseFit(String),
+	InvalidDimensions(String),
+	InputDoesNotFit(String),
 	ReadFail(String),
 	WriteFail(String),
 	InputDoesNotExist(String),
 	InputNotAFile(String),
+	InputNotADirectory(String),
+	ParseDebounce(String),
+	Watch(String),
 	ReadChunk(String),
 	InvalidCRC(String),
 	InvalidHeader(String),
 	Encode(String),
 	Decode(String),
-	TrimError(String)
+	TrimError(String),
+	ParseConfig(String),
+	UnknownProfile(String),
+	UnknownConfigKey(String),
+	Bind(String),
+	ParseBitsPerChannel(String),
+	Carrier(String),
+	CarrierPayload(String),
+	ParseLevels(String),
+	Robust(String),
+	UnknownFormat(String),
+	ParseFormat(String),
+	UnsupportedFormat(String),
+	ParseFrameSize(String),
+	MissingFrameSize,
+	UnsupportedBanner(String),
+	BannerDoesNotFit(String),
+	ParseIdatSize(String),
+	ParseMtime(String),
+	ParseCheckpointInterval(String),
+	UnsupportedResume(String),
+	ResumeMismatch(String),
+	UnsupportedStdin(String),
+	MissingStdinOutput,
+	OutputExists(String),
+	ParseLimit(String),
+	ChunkLimit(String),
+	OutputLimit(String),
+	DimensionLimit(String),
+	RatioLimit(String),
+	InputLimit(String),
+	NoClobber(String),
+	ParsePreserve(String),
+	ParseTag(String),
+	ParseContainer(String),
+	ParsePolyglot(String),
+	UnsupportedPolyglot(String),
+	NotConformant(String),
+	Cancelled
 }
 
-impl fmt::Debug for Error {
+impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "\n\x1b[1;31mError: {}\x1b[0m", match self {
+		write!(f, "{}", match self {
 			Error::ParseWidth(s) => format!("Invalid width of '{}'.", s),
 			Error::ParseHeight(s) => format!("Invalid height of '{}'.", s),
 			Error::ParseBuffer(s) => format!("Invalid buffer size of '{}'.", s),
 			Error::ParseColorType(s) => format!("Invalid color type of '{}'. Color types of 0, 2, 4 & 6 are supported.", s),
 			Error::ParseBitDepth(s) => format!("Invalid bit depth of '{}'. Bit depths of 8-bit and 16-bit are supported.", s),
-			Error::WidthAndHeightDefined => String::from("Cannot define both a width and height of the image."),
+			Error::ConflictingDimensions(s) => format!("Cannot combine the dimension options of '{}'.", s),
+			Error::ParseAspect(s) => format!("Invalid aspect ratio of '{}'. Aspect ratios are written as 'W:H' (ie: 16:9).", s),
+			Error::ParseFit(s) => format!("Invalid image size of '{}'. Sizes are written as 'WxH' (ie: 1920x1080) or as a preset name.", s),
+			Error::InvalidDimensions(s) => format!("Invalid image dimensions of '{}'. Width and height must be between 1 and 2147483647 pixels.", s),
+			Error::InputDoesNotFit(s) => format!("Unable to fit {}.", s),
 			Error::ReadFail(s) => format!("Unable to open '{}' for reading.", s),
 			Error::WriteFail(s) => format!("Unable to open '{}' for writing.", s),
 			Error::InputDoesNotExist(s) => format!("Input file of '{}' does not exist.", s),
 			Error::InputNotAFile(s) => format!("Input of '{}' is not a file.", s),
+			Error::InputNotADirectory(s) => format!("Input of '{}' is not a directory.", s),
+			Error::ParseDebounce(s) => format!("Invalid debounce of '{}' seconds.", s),
+			Error::Watch(s) => format!("Unable to watch '{}' for changes.", s),
 			Error::ReadChunk(s) => format!("Unable to read chunk from '{}'.", s),
 			Error::InvalidCRC(s) => format!("Unable to verify crc from '{}'.", s),
 			Error::InvalidHeader(s) => format!("Image of '{}' has invalid header.", s),
 			Error::Encode(s) => format!("Unable to encode '{}' as PNG.", s),
 			Error::Decode(s) => format!("Unable to decode '{}' from PNG.", s),
-			Error::TrimError(s) => format!("Unable to trim '{}'.", s)
+			Error::TrimError(s) => format!("Unable to trim '{}'.", s),
+			Error::ParseConfig(s) => format!("Unable to parse config file of '{}'.", s),
+			Error::UnknownProfile(s) => format!("Profile of '{}' is not defined in any config file.", s),
+			Error::UnknownConfigKey(s) => format!("Unknown config key of {}. Keys are named after the long flags (ie: verbose, buffer).", s),
+			Error::Bind(s) => format!("Unable to listen on '{}'.", s),
+			Error::ParseBitsPerChannel(s) => format!("Invalid bits per channel of '{}'. Between 1 and 4 bits are supported.", s),
+			Error::Carrier(s) => format!("Unable to read carrier image '{}'. Non-interlaced greyscale & truecolor PNGs with 8-bit or 16-bit depths are supported.", s),
+			Error::CarrierPayload(s) => format!("Unable to find a valid hidden file in '{}'.", s),
+			Error::ParseLevels(s) => format!("Invalid number of levels of '{}'. Either 2 or 4 levels are supported.", s),
+			Error::Robust(s) => format!("Unable to recover a file from '{}'. The image may be too damaged or was not encoded with --robust.", s),
+			Error::UnknownFormat(s) => format!("Unable to recognize the image format of '{}'. PNG, JPEG, WebP, BMP, TIFF & QOI images are supported.", s),
+			Error::ParseFormat(s) => format!("Invalid format of '{}'. Supported formats are png, qoi, bmp, tiff, tiff-deflate & webp.", s),
+			Error::UnsupportedFormat(s) => format!("Unable to store {} without loss.", s),
+			Error::ParseFrameSize(s) => format!("Invalid frame size of '{}'. Sizes are written as 'WxH' (ie: 1920x1080) or as a preset name.", s),
+			Error::MissingFrameSize => String::from("Animations need the size of each frame (ie: --apng --frame-size 1920x1080)."),
+			Error::UnsupportedBanner(s) => format!("Banners are only supported by still PNGs, not {}.", s),
+			Error::BannerDoesNotFit(s) => format!("Unable to fit the banner into an image {} pixels wide (use --width or --fit for a wider image).", s),
+			Error::ParseIdatSize(s) => format!("Invalid IDAT size of '{}'. Sizes range from 1b to 2gb - 1b (ie: 8kb, 1mb).", s),
+			Error::ParseMtime(s) => format!("Invalid modification time of '{}'. Times are seconds since the unix epoch or 'now'.", s),
+			Error::ParseCheckpointInterval(s) => format!("Invalid checkpoint interval of '{}'. Intervals are at least 1b (ie: 64mb, 1gb).", s),
+			Error::UnsupportedResume(s) => format!("Resuming is only supported by PNGs written to a file, not {}.", s),
+			Error::ResumeMismatch(s) => format!("The checkpoint '{}' belongs to another input, output or options. Delete it to start over.", s),
+			Error::UnsupportedStdin(s) => format!("Decoding from stdin is only supported by PNGs read once from start to end, not with {}.", s),
+			Error::MissingStdinOutput => String::from("Decoding from stdin needs an output file or --stream."),
+			Error::OutputExists(s) => format!("The output file of '{}' currently exists. Use --yes to override it.", s),
+			Error::ParseLimit(s) => format!("Invalid limit of '{}'. Limits are sizes (ie: 16mb), dimensions (ie: 8192x8192) or ratios (ie: 1000) above 0.", s),
+			Error::ChunkLimit(s) => format!("Unable to decode {} within --max-chunk-bytes.", s),
+			Error::OutputLimit(s) => format!("Unable to decode {} within --max-output-bytes.", s),
+			Error::DimensionLimit(s) => format!("Unable to decode {} within --max-dimensions.", s),
+			Error::RatioLimit(s) => format!("Unable to decode {} within --max-ratio.", s),
+			Error::InputLimit(s) => format!("Unable to encode {} within --max-input-bytes.", s),
+			Error::NoClobber(s) => format!("The output file of '{}' currently exists and --no-clobber is set.", s),
+			Error::ParsePreserve(s) => format!("Invalid attributes to preserve of '{}'. Either xattr, acl, owner, mode or all (separated by commas) are supported.", s),
+			Error::ParseTag(s) => format!("Invalid tag of '{}'. Tags are written as 'key=value' with a key of 1 to 79 printable ASCII characters.", s),
+			Error::ParseContainer(s) => format!("Invalid container of '{}'. Either pixels or chunk are supported.", s),
+			Error::ParsePolyglot(s) => format!("Invalid polyglot of '{}'. Only zip is supported.", s),
+			Error::UnsupportedPolyglot(s) => format!("ZIP polyglots are only supported by PNGs of inputs up to 2032mb, not {}.", s),
+			Error::NotConformant(s) => format!("Image of '{}' breaks the PNG specification.", s),
+			Error::Cancelled => String::from("Cancelled. Partial outputs were removed (outputs written with --resume are kept to be continued).")
 		})
 	}
+}
+
+impl fmt::Debug for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "\n\x1b[1;31mError: {}\x1b[0m", self)
+	}
 }
\ No newline at end of file
@@ -1,11 +1,18 @@
+extern crate memmap2;
+#[cfg(target_os = "linux")]
+extern crate libc;
+
 use std::io::{self, Read, Write, BufWriter, SeekFrom};
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fmt;
 use std::fs::{File, OpenOptions};
+use std::sync::{Arc, Mutex};
+pub use memmap2::Mmap;
 
 use crate::util;
-use util::{Error, Chunk};
+use util::{Error, Chunk, ChunkData, ChunkWriter, Chunks, Hole};
+use util::sparse::Skip;
 
 pub static PNG_HEADER: [u8; 8] = [
 		0x89,
@@ -15,14 +22,36 @@ pub static PNG_HEADER: [u8; 8] = [
 		0x0a
 	];
 
+// Default size of the data of each IDAT chunk
+pub static IDAT_SIZE: usize = 1024 * 1024;
+
+// Reads the PNG signature from the start of a stream
+pub fn read_header<R: Read>(source: &mut R) -> io::Result<()> {
+	let mut buffer = [0u8; 8];
+	source.read_exact(&mut buffer)?;
+	if buffer[..] == PNG_HEADER[..] {
+		return Ok(());
+	}
+	Err(std::io::Error::from(std::io::ErrorKind::InvalidData))
+}
+
 pub struct WriteFile {
+	// File being written (a temporary file next to the destination until it is committed)
 	path: PathBuf,
-	output: BufWriter<Box<dyn Write>>
+	// Where the temporary file is renamed to once it is committed
+	destination: Option<PathBuf>,
+	output: BufWriter<Box<dyn Write + Send>>,
+	// Handle sharing the offset of output, for seeking past holes (None for streams & memory)
+	file: Option<File>,
+	// Offset the next byte is written at
+	position: u64
 }
 
 impl Write for WriteFile {
 	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-		self.output.write(&buf)
+		let size = self.output.write(buf)?;
+		self.position += size as u64;
+		Ok(size)
 	}
 
 	fn flush(&mut self) -> io::Result<()> {
@@ -30,27 +59,189 @@ impl Write for WriteFile {
 	}
 }
 
+// Seeks past holes & extends the file over them, so they take up no disk space (streams get zeros)
+impl Skip for WriteFile {
+	fn skip(&mut self, length: u64) -> io::Result<()> {
+		self.flush()?;
+		self.position += length;
+		let file = match self.file.as_mut() {
+			Some(file) => file,
+			None => return io::copy(&mut io::repeat(0).take(length), &mut self.output).map(|_| ())
+		};
+		let end = file.seek(SeekFrom::Current(length as i64))?;
+		// Files opened to append always write at their end
+		if end > file.metadata()?.len() {
+			file.set_len(end)?;
+		}
+		Ok(())
+	}
+}
+
 impl WriteFile {
 	pub fn stdout() -> io::Result<Self> {
-		let out: BufWriter<Box<dyn Write>> = BufWriter::new(Box::new(std::io::stdout()));
+		let out: BufWriter<Box<dyn Write + Send>> = BufWriter::new(Box::new(std::io::stdout()));
 		Ok(Self {
 			path: PathBuf::from("stdout"),
-			output: out
+			destination: None,
+			output: out,
+			file: None,
+			position: 0
 		})
 	}
 
+	// Writes to any destination (ie: a socket), named for display purposes
+	pub fn from_writer(name: &str, writer: Box<dyn Write + Send>) -> Self {
+		Self {
+			path: PathBuf::from(name),
+			destination: None,
+			output: BufWriter::new(writer),
+			file: None,
+			position: 0
+		}
+	}
+
+	// Writes unbuffered into memory shared with whoever drains it (ie: the async adapters)
+	pub fn memory(buffer: Arc<Mutex<Vec<u8>>>) -> Self {
+		Self {
+			path: PathBuf::from("memory"),
+			destination: None,
+			output: BufWriter::with_capacity(0, Box::new(SharedBuffer(buffer))),
+			file: None,
+			position: 0
+		}
+	}
+
 	pub fn from_string(s: String) -> io::Result<Self> {
 		Self::from_pathbuf(PathBuf::from(s))
 	}
 
 	pub fn from_pathbuf(p: PathBuf) -> io::Result<Self> {
-		let file: BufWriter<Box<dyn Write>> = BufWriter::new(Box::new(File::create(p.as_path())?));
+		let file = File::create(p.as_path())?;
 		Ok(Self {
-			output: file,
-			path: p
+			file: Some(file.try_clone()?),
+			output: BufWriter::new(Box::new(file)),
+			path: p,
+			destination: None,
+			position: 0
 		})
 	}
 
+	// Writes to a hidden temporary file in the same directory, leaving an existing file at p untouched until commit
+	// (the temporary file is removed if the WriteFile is dropped before then)
+	pub fn atomic(p: PathBuf) -> io::Result<Self> {
+		let name = match p.file_name() {
+			Some(name) => name.to_string_lossy().to_string(),
+			None => return Err(io::Error::from(io::ErrorKind::InvalidInput))
+		};
+		let temporary = p.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+		let file = OpenOptions::new().write(true).create_new(true).open(temporary.as_path())?;
+		Ok(Self {
+			file: Some(file.try_clone()?),
+			output: BufWriter::new(Box::new(file)),
+			path: temporary,
+			destination: Some(p),
+			position: 0
+		})
+	}
+
+	// Syncs the temporary file of atomic to disk and renames it over the destination, first renaming an existing
+	// destination to its name + backup (does nothing for other outputs beyond flushing). With no_clobber, fails with
+	// AlreadyExists instead of replacing a destination created since the output was checked
+	pub fn commit(&mut self, backup: Option<&str>, no_clobber: bool) -> io::Result<()> {
+		self.flush()?;
+		let destination = match self.destination.clone() {
+			Some(destination) => destination,
+			None => return Ok(())
+		};
+		OpenOptions::new().write(true).open(self.path.as_path())?.sync_all()?;
+		if let Some(suffix) = backup {
+			let mut backup_path = destination.as_os_str().to_owned();
+			backup_path.push(suffix);
+			match std::fs::rename(&destination, &backup_path) {
+				Err(ref e) if e.kind() != io::ErrorKind::NotFound => return Err(io::Error::new(e.kind(), e.to_string())),
+				_ => ()
+			};
+		}
+		match no_clobber {
+			true => rename_new(&self.path, &destination)?,
+			false => std::fs::rename(&self.path, &destination)?
+		};
+		self.path = destination;
+		self.destination = None;
+		// The rename only survives a crash once the directory is synced too
+		#[cfg(unix)]
+		{
+			if let Some(parent) = self.path.parent() {
+				let parent = if parent.as_os_str().is_empty() { std::path::Path::new(".") } else { parent };
+				File::open(parent)?.sync_all()?;
+			}
+		}
+		Ok(())
+	}
+
+	// Writes after the existing contents of a file (ie: continuing from a checkpoint)
+	pub fn append(p: PathBuf) -> io::Result<Self> {
+		let file = OpenOptions::new().create(true).append(true).open(p.as_path())?;
+		Ok(Self {
+			position: file.metadata()?.len(),
+			file: Some(file.try_clone()?),
+			output: BufWriter::new(Box::new(file)),
+			path: p,
+			destination: None
+		})
+	}
+
+	// Cuts the file down to size (everything written so far is flushed first)
+	pub fn truncate(&mut self, size: u64) -> io::Result<()> {
+		self.flush()?;
+		OpenOptions::new().write(true).open(self.path.as_path())?.set_len(size)?;
+		self.position = size;
+		Ok(())
+	}
+
+	// Reserves disk space for size bytes without changing the size of the file, so large outputs aren't fragmented
+	// (only on Linux, elsewhere & on filesystems without fallocate it does nothing). Only a hint: sizes are estimates
+	// (compressed outputs end up far smaller), so running out of space is left to the writes that really need it
+	pub fn preallocate(&self, size: u64) -> io::Result<()> {
+		#[cfg(target_os = "linux")]
+		{
+			use std::os::unix::io::AsRawFd;
+			let file = OpenOptions::new().write(true).open(self.path.as_path())?;
+			let result = unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, size as libc::off_t) };
+			if result != 0 {
+				let error = io::Error::last_os_error();
+				return match error.raw_os_error() {
+					Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) | Some(libc::ENOSPC) => Ok(()),
+					_ => Err(error)
+				};
+			}
+		}
+		#[cfg(not(target_os = "linux"))]
+		let _ = size;
+		Ok(())
+	}
+
+	// Frees the space reserved past the end of the file by preallocate
+	pub fn release(&mut self) -> io::Result<()> {
+		self.flush()?;
+		let file = OpenOptions::new().write(true).open(self.path.as_path())?;
+		let size = file.metadata()?.len();
+		file.set_len(size)
+	}
+
+	// Flushes everything written so far to disk, returning the size of the file
+	pub fn sync(&mut self) -> io::Result<u64> {
+		self.flush()?;
+		let file = OpenOptions::new().write(true).open(self.path.as_path())?;
+		file.sync_data()?;
+		Ok(file.metadata()?.len())
+	}
+
+	// Bytes before where the next byte is written (the size of the output so far)
+	pub fn position(&self) -> u64 {
+		self.position
+	}
+
 	pub fn read(&self) -> io::Result<ReadFile> {
 		ReadFile::from_pathbuf(self.path.clone())
 	}
@@ -62,23 +253,108 @@ impl WriteFile {
 	}
 
 	pub fn write_header(&mut self) -> io::Result<usize> {
-		self.write(&PNG_HEADER)
+		ChunkWriter::new(self).write_signature()
 	}
 
 	pub fn write_chunk(&mut self, header_type: &[u8], data: &[u8]) -> io::Result<usize> {
-		self.write(&(data.len() as u32).to_be_bytes()[..4])?;
-		let merged = [header_type, data].concat();
-		self.write(&merged)?;
-		self.write(&util::Chunk::get_crc(&merged)[..])
+		ChunkWriter::new(self).write_chunk(header_type, data)
+	}
+
+	// Writes a chunk whose data is split across slices (ie: a sequence number ahead of frame data) without merging them
+	pub fn write_chunk_parts(&mut self, header_type: &[u8], parts: &[&[u8]]) -> io::Result<usize> {
+		ChunkWriter::new(self).write_chunk_parts(header_type, parts)
+	}
+
+	// Validates & writes a typed chunk
+	pub fn write_typed<C: ChunkData>(&mut self, chunk: &C) -> io::Result<usize> {
+		ChunkWriter::new(self).write_typed(chunk)
+	}
+
+	// Animation control of an APNG (0 plays loops forever)
+	pub fn write_actl(&mut self, frames: u32, plays: u32) -> io::Result<usize> {
+		self.write_chunk(b"acTL", &[&frames.to_be_bytes()[..], &plays.to_be_bytes()[..]].concat())
+	}
+
+	// Frame control of a frame covering the whole APNG, shown for a tenth of a second
+	pub fn write_fctl(&mut self, sequence: u32, width: u32, height: u32) -> io::Result<usize> {
+		self.write_chunk(
+			b"fcTL",
+			&[
+				&sequence.to_be_bytes()[..],
+				&width.to_be_bytes()[..],
+				&height.to_be_bytes()[..],
+				&0u32.to_be_bytes()[..],
+				&0u32.to_be_bytes()[..],
+				&1u16.to_be_bytes()[..],
+				&10u16.to_be_bytes()[..],
+				// No disposal, source blending
+				&[0u8, 0u8][..]
+			].concat()
+		)
+	}
+
+	// Frame data of an APNG (the image data of every frame after the first)
+	pub fn write_fdat(&mut self, sequence: u32, data: &[u8]) -> io::Result<usize> {
+		self.write_chunk_parts(b"fdAT", &[&sequence.to_be_bytes()[..], data])
+	}
+}
+
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self.0.lock() {
+			Ok(mut buffer) => {
+				buffer.extend_from_slice(buf);
+				Ok(buf.len())
+			},
+			Err(_) => Err(io::Error::other("poisoned output buffer"))
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+// Outputs that were never committed (ie: a failed encode) leave nothing behind
+// Renames from to to unless to exists, even if it was created just before (renameat2 on Linux, elsewhere & on
+// filesystems without it a hard link that fails on an existing file, then unlinking from)
+fn rename_new(from: &Path, to: &Path) -> io::Result<()> {
+	#[cfg(target_os = "linux")]
+	{
+		use std::ffi::CString;
+		use std::os::unix::ffi::OsStrExt;
+		let from_c = CString::new(from.as_os_str().as_bytes())?;
+		let to_c = CString::new(to.as_os_str().as_bytes())?;
+		let result = unsafe { libc::renameat2(libc::AT_FDCWD, from_c.as_ptr(), libc::AT_FDCWD, to_c.as_ptr(), libc::RENAME_NOREPLACE) };
+		if result == 0 {
+			return Ok(());
+		}
+		let error = io::Error::last_os_error();
+		match error.raw_os_error() {
+			Some(libc::EINVAL) | Some(libc::ENOSYS) => (),
+			_ => return Err(error)
+		};
+	}
+	std::fs::hard_link(from, to)?;
+	std::fs::remove_file(from)
+}
+
+impl Drop for WriteFile {
+	fn drop(&mut self) {
+		if self.destination.is_some() {
+			// Closes the file first (files that are still open can't be removed on Windows)
+			drop(std::mem::replace(&mut self.output, BufWriter::new(Box::new(io::sink()))));
+			self.file = None;
+			let _ = std::fs::remove_file(&self.path);
+		}
 	}
 }
 
 impl fmt::Debug for WriteFile {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{}", match self.path.to_str() {
-			Some(s) => s,
-			None => "Unknown"
-		})
+		write!(f, "{}", self.destination.as_ref().unwrap_or(&self.path).to_str().unwrap_or("Unknown"))
 	}
 }
 
@@ -95,7 +371,6 @@ pub struct ReadFile {
 }
 
 impl ReadFile {
-	// Currently Unused
 	pub fn from_string(s: String) -> io::Result<Self> {
 		Self::from_pathbuf(PathBuf::from(s))
 	}
@@ -114,10 +389,64 @@ impl ReadFile {
 		let path = self.path.clone();
 		Ok(WriteFile{
 			output: BufWriter::new(Box::new(OpenOptions::new().write(true).open(path.as_path())?)),
-			path: path
+			path: path,
+			destination: None,
+			file: None,
+			position: 0
 		})
 	}
 
+	// Finds the holes of a sparse file with SEEK_HOLE & SEEK_DATA (only on Linux, elsewhere & on filesystems without
+	// holes there are none)
+	pub fn holes(&self) -> io::Result<Vec<Hole>> {
+		#[cfg(target_os = "linux")]
+		{
+			use std::os::unix::io::AsRawFd;
+			let mut holes = Vec::new();
+			// Opened again, as seeking the input would move its offset too
+			let file = File::open(self.path.as_path())?;
+			let mut offset: u64 = 0;
+			while offset < self.size {
+				let hole = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, libc::SEEK_HOLE) };
+				if hole < 0 {
+					let error = io::Error::last_os_error();
+					return match error.raw_os_error() {
+						Some(libc::EINVAL) | Some(libc::ENXIO) => Ok(holes),
+						_ => Err(error)
+					};
+				}
+				let hole = hole as u64;
+				if hole >= self.size {
+					break;
+				}
+				// No data after the last hole
+				let data = match unsafe { libc::lseek(file.as_raw_fd(), hole as libc::off_t, libc::SEEK_DATA) } {
+					data if data < 0 => {
+						let error = io::Error::last_os_error();
+						match error.raw_os_error() {
+							Some(libc::ENXIO) => self.size,
+							_ => return Err(error)
+						}
+					},
+					data => std::cmp::min(data as u64, self.size)
+				};
+				holes.push(Hole {
+					offset: hole,
+					length: data - hole
+				});
+				offset = data;
+			}
+			Ok(holes)
+		}
+		#[cfg(not(target_os = "linux"))]
+		Ok(Vec::new())
+	}
+
+	// Maps the whole file into memory (the file must not be truncated while the map is in use)
+	pub fn map(&self) -> io::Result<Mmap> {
+		unsafe { Mmap::map(&self.input) }
+	}
+
 	pub fn reset(&mut self) -> Result<u64, Error> {
 		Ok(error_exp!(ReadFail, &self, self.input.seek(SeekFrom::Start(0))))
 	}
@@ -134,7 +463,7 @@ impl ReadFile {
 				start = len - (buffer_size as u64);
 			}
 			self.seek(SeekFrom::Start(start))?;
-			self.read(&mut buf[..cut])?;
+			self.read_exact(&mut buf[..cut])?;
 			let mut i = cut - 1;
 			while i > 0 {
 				if buf[i] != 0 {
@@ -152,19 +481,18 @@ impl ReadFile {
 	}
 
 	pub fn read_header(&mut self) -> io::Result<()> {
-		let mut buffer = [0u8; 8];
-		self.read(&mut buffer)?;
-		if &buffer[..] == &PNG_HEADER[..] {
-			return Ok(());
-		}
-		Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+		read_header(self)
 	}
 
 	pub fn read_chunk(&mut self) -> io::Result<Option<Chunk>> {
 		Chunk::new(self)
 	}
 
-	pub fn verify_png(&mut self) -> Result<(), Error> {
+	// Checks the signature & the CRC of every chunk (in place when mapped, or if it can't be mapped through reads)
+	pub fn verify_png(&mut self, map: bool) -> Result<(), Error> {
+		if let Some(Ok(map)) = map.then(|| self.map()) {
+			return self.verify_mapped(&map);
+		}
 		let mut progress_bar = util::ProgressBar::new(self.size, "Verifying PNG");
 		error_exp!(InvalidHeader, &self, self.read_header());
 		progress!({progress_bar.tick(8);});
@@ -174,6 +502,30 @@ impl ReadFile {
 				false => error!(InvalidCRC, &self)
 			};
 			progress!({progress_bar.tick(k.total_data as u64);});
+			// Anything following IEND isn't a chunk (ie: the central directory of a ZIP polyglot)
+			if k.get_header() == b"IEND" {
+				break;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl ReadFile {
+	// Verifies the chunks of a mapped PNG in place, without copying their data
+	fn verify_mapped(&self, map: &[u8]) -> Result<(), Error> {
+		let mut progress_bar = util::ProgressBar::new(self.size, "Verifying PNG");
+		error_exp!(InvalidHeader, &self, read_header(&mut &map[..]));
+		progress!({progress_bar.tick(8);});
+		for chunk in Chunks::new(map, 8) {
+			let chunk = error_exp!(ReadChunk, &self, chunk);
+			if !chunk.verify_crc() {
+				error!(InvalidCRC, &self);
+			}
+			progress!({progress_bar.tick(chunk.total_data as u64);});
+			if chunk.get_header() == b"IEND" {
+				break;
+			}
 		}
 		Ok(())
 	}
@@ -193,10 +545,7 @@ impl Seek for ReadFile {
 
 impl fmt::Debug for ReadFile {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{}", match self.path.to_str() {
-			Some(s) => s,
-			None => "Unknown"
-		})
+		write!(f, "{}", self.path.to_str().unwrap_or("Unknown"))
 	}
 }
 
@@ -204,4 +553,62 @@ impl fmt::Display for ReadFile {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		fmt::Debug::fmt(&self, f)
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+
+	// Atomic output of a test of its own, with a file created at its destination after it was opened (like another
+	// process racing the check of --no-clobber)
+	fn raced(name: &str) -> (WriteFile, PathBuf) {
+		let destination = std::env::temp_dir().join(format!("pngifier-{}-{}", name, std::process::id()));
+		let _ = fs::remove_file(&destination);
+		let mut write_file = WriteFile::atomic(destination.clone()).unwrap();
+		write_file.write_all(b"output").unwrap();
+		fs::write(&destination, b"raced").unwrap();
+		(write_file, destination)
+	}
+
+	#[test]
+	fn no_clobber_keeps_files_created_while_writing() {
+		let (mut write_file, destination) = raced("no-clobber");
+		let temporary = write_file.path.clone();
+		assert_eq!(write_file.commit(None, true).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+		drop(write_file);
+		assert_eq!(fs::read(&destination).unwrap(), b"raced");
+		assert!(!temporary.exists());
+		fs::remove_file(&destination).unwrap();
+	}
+
+	#[test]
+	fn commit_replaces_files_created_while_writing() {
+		let (mut write_file, destination) = raced("clobber");
+		write_file.commit(None, false).unwrap();
+		assert_eq!(fs::read(&destination).unwrap(), b"output");
+		fs::remove_file(&destination).unwrap();
+	}
+
+	#[test]
+	fn no_clobber_commits_new_files() {
+		let destination = std::env::temp_dir().join(format!("pngifier-new-{}", std::process::id()));
+		let _ = fs::remove_file(&destination);
+		let mut write_file = WriteFile::atomic(destination.clone()).unwrap();
+		write_file.write_all(b"output").unwrap();
+		write_file.commit(None, true).unwrap();
+		assert_eq!(fs::read(&destination).unwrap(), b"output");
+		fs::remove_file(&destination).unwrap();
+	}
+
+	#[test]
+	fn preallocating_more_than_the_disk_holds_is_ignored() {
+		let destination = std::env::temp_dir().join(format!("pngifier-preallocate-{}", std::process::id()));
+		let mut write_file = WriteFile::atomic(destination.clone()).unwrap();
+		write_file.preallocate(1 << 42).unwrap();
+		write_file.write_all(b"output").unwrap();
+		write_file.release().unwrap();
+		write_file.commit(None, false).unwrap();
+		assert_eq!(fs::read(&destination).unwrap(), b"output");
+		fs::remove_file(&destination).unwrap();
+	}
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::io;
+
+use crate::util::Error;
+
+// Largest chunk length allowed by the PNG specification
+pub static MAX_CHUNK_LENGTH: u64 = 0x7fff_ffff;
+// Largest private chunk read whole by default (hole maps, resume indexes & attributes, unlike the image data and
+// payloads, which are inflated as they arrive)
+pub static MAX_PRIVATE_CHUNK_LENGTH: u64 = 16 * 1024 * 1024;
+// Inflated bytes before --max-ratio is checked (the start of a stream says little about the rest)
+static RATIO_GRACE: u64 = 1024 * 1024;
+
+// Bounds on what decoding an untrusted image may allocate or produce (None is unlimited)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+	pub max_chunk_bytes: u64,
+	// Data of the private chunks read whole into memory
+	pub max_private_chunk_bytes: u64,
+	pub max_output_bytes: Option<u64>,
+	pub max_dimensions: Option<(u64, u64)>,
+	// Inflated bytes for each compressed byte
+	pub max_ratio: Option<u64>,
+	// Largest buffer a decode inflates into, whatever buffer size it is asked for
+	pub max_buffer_bytes: Option<u64>,
+	// Largest input an encode takes (request bodies of the server, whose size sets the size of the image)
+	pub max_input_bytes: Option<u64>
+}
+
+impl Default for Limits {
+	fn default() -> Self {
+		Self {
+			max_chunk_bytes: MAX_CHUNK_LENGTH,
+			max_private_chunk_bytes: MAX_PRIVATE_CHUNK_LENGTH,
+			max_output_bytes: None,
+			max_dimensions: None,
+			max_ratio: None,
+			max_buffer_bytes: None,
+			max_input_bytes: None
+		}
+	}
+}
+
+impl Limits {
+	// Buffer size bounded by max_buffer_bytes
+	pub fn buffer_size(&self, buffer_size: usize) -> usize {
+		match self.max_buffer_bytes {
+			Some(limit) => std::cmp::min(buffer_size as u64, limit) as usize,
+			None => buffer_size
+		}
+	}
+
+	pub fn check_chunk(&self, chunk_type: &[u8], length: u64) -> io::Result<()> {
+		check_length(chunk_type, length, self.max_chunk_bytes)
+	}
+
+	// Checks a private chunk about to be read whole
+	pub fn check_private_chunk(&self, chunk_type: &[u8], length: u64) -> io::Result<()> {
+		check_length(chunk_type, length, std::cmp::min(self.max_chunk_bytes, self.max_private_chunk_bytes))
+	}
+
+	pub fn check_dimensions(&self, width: u64, height: u64) -> io::Result<()> {
+		match self.max_dimensions {
+			Some((max_width, max_height)) if width > max_width || height > max_height => Err(exceeded(Exceeded::Dimensions {
+				width: width,
+				height: height,
+				limit: (max_width, max_height)
+			})),
+			_ => Ok(())
+		}
+	}
+
+	// Checks the bytes of the original file written so far (including those about to be)
+	pub fn check_output(&self, size: u64) -> io::Result<()> {
+		match self.max_output_bytes {
+			Some(limit) if size > limit => Err(exceeded(Exceeded::OutputBytes { limit: limit })),
+			_ => Ok(())
+		}
+	}
+
+	// Checks the size of an input before it is encoded
+	pub fn check_input(&self, size: u64) -> io::Result<()> {
+		match self.max_input_bytes {
+			Some(limit) if size > limit => Err(exceeded(Exceeded::InputBytes { size: size, limit: limit })),
+			_ => Ok(())
+		}
+	}
+
+	pub fn check_ratio(&self, inflated: u64, compressed: u64) -> io::Result<()> {
+		match self.max_ratio {
+			Some(limit) if inflated > RATIO_GRACE && inflated > compressed.saturating_mul(limit) => Err(exceeded(Exceeded::Ratio {
+				inflated: inflated,
+				compressed: compressed,
+				limit: limit
+			})),
+			_ => Ok(())
+		}
+	}
+}
+
+// Limit that stopped a decode, carried inside the io::Error it fails with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exceeded {
+	ChunkBytes { chunk_type: String, length: u64, limit: u64 },
+	OutputBytes { limit: u64 },
+	Dimensions { width: u64, height: u64, limit: (u64, u64) },
+	Ratio { inflated: u64, compressed: u64, limit: u64 },
+	InputBytes { size: u64, limit: u64 }
+}
+
+impl Exceeded {
+	// Finds the limit behind a failed decode (None for any other failure)
+	pub fn find(error: &io::Error) -> Option<&Self> {
+		error.get_ref().and_then(|inner| inner.downcast_ref::<Self>())
+	}
+
+	// Error reported for name (ie: the input file) running into the limit
+	pub fn to_error(&self, name: &dyn fmt::Display) -> Error {
+		let message = format!("'{}' ({})", name, self);
+		match self {
+			Exceeded::ChunkBytes { .. } => Error::ChunkLimit(message),
+			Exceeded::OutputBytes { .. } => Error::OutputLimit(message),
+			Exceeded::Dimensions { .. } => Error::DimensionLimit(message),
+			Exceeded::Ratio { .. } => Error::RatioLimit(message),
+			Exceeded::InputBytes { .. } => Error::InputLimit(message)
+		}
+	}
+}
+
+impl fmt::Display for Exceeded {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Exceeded::ChunkBytes { chunk_type, length, limit } => write!(f, "the {} chunk of {} bytes is over {} bytes", chunk_type, length, limit),
+			Exceeded::OutputBytes { limit } => write!(f, "the output is over {} bytes", limit),
+			Exceeded::Dimensions { width, height, limit } => write!(f, "{}x{} is over {}x{}", width, height, limit.0, limit.1),
+			Exceeded::Ratio { inflated, compressed, limit } => write!(f, "{} bytes inflated from {} bytes is over {}:1", inflated, compressed, limit),
+			Exceeded::InputBytes { size, limit } => write!(f, "the input of {} bytes is over {} bytes", size, limit)
+		}
+	}
+}
+
+impl std::error::Error for Exceeded {}
+
+fn check_length(chunk_type: &[u8], length: u64, limit: u64) -> io::Result<()> {
+	match length > limit {
+		true => Err(exceeded(Exceeded::ChunkBytes {
+			chunk_type: String::from_utf8_lossy(chunk_type).to_string(),
+			length: length,
+			limit: limit
+		})),
+		false => Ok(())
+	}
+}
+
+fn exceeded(limit: Exceeded) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, limit)
+}
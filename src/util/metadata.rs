@@ -0,0 +1,388 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// Private ancillary chunk with the attributes of the input captured by --preserve (written before the image data)
+pub static CHUNK_TYPE: &[u8; 4] = b"pgMD";
+
+// Records of the chunk: a kind, the length of its value, then the value (unknown kinds are skipped)
+const MODE: u8 = b'M';
+const OWNER: u8 = b'O';
+const XATTR: u8 = b'X';
+
+// Setuid, setgid & sticky bits of a mode
+#[cfg(unix)]
+const SPECIAL_BITS: u32 = 0o7000;
+
+// POSIX ACLs are stored by Linux as extended attributes of these names
+static ACL_PREFIX: &[u8] = b"system.posix_acl_";
+
+// Attributes captured on encode & restored on decode by --preserve
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Preserve {
+	pub xattr: bool,
+	pub acl: bool,
+	pub owner: bool,
+	pub mode: bool
+}
+
+impl Preserve {
+	pub fn all() -> Self {
+		Self {
+			xattr: true,
+			acl: true,
+			owner: true,
+			mode: true
+		}
+	}
+
+	// Parses a comma separated list (ie: xattr,acl,owner)
+	pub fn parse(s: &str) -> Option<Self> {
+		let mut preserve = Self::default();
+		for name in s.split(',').map(|name| name.trim().to_lowercase()) {
+			match &name[..] {
+				"xattr" => preserve.xattr = true,
+				"acl" => preserve.acl = true,
+				"owner" => preserve.owner = true,
+				"mode" => preserve.mode = true,
+				"all" => preserve = Self::all(),
+				_ => return None
+			};
+		}
+		Some(preserve)
+	}
+
+	fn attribute(&self, name: &[u8]) -> bool {
+		match is_acl(name) {
+			true => self.acl,
+			false => self.xattr
+		}
+	}
+}
+
+// Permissions, ownership & extended attributes (including ACLs) of a file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+	pub mode: Option<u32>,
+	// uid & gid
+	pub owner: Option<(u32, u32)>,
+	// Names & values of the extended attributes
+	pub xattrs: Vec<(Vec<u8>, Vec<u8>)>
+}
+
+impl Metadata {
+	// Reads what preserve asks for from the file at path, returning warnings for anything that couldn't be read
+	// (ie: trusted.* attributes without privileges)
+	pub fn capture(path: &Path, preserve: &Preserve) -> io::Result<(Self, Vec<String>)> {
+		let mut metadata = Self::default();
+		let mut warnings = Vec::new();
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::MetadataExt;
+			let stat = std::fs::metadata(path)?;
+			if preserve.mode {
+				metadata.mode = Some(stat.mode() & 0o7777);
+			}
+			if preserve.owner {
+				metadata.owner = Some((stat.uid(), stat.gid()));
+			}
+		}
+		#[cfg(not(unix))]
+		{
+			if preserve.mode || preserve.owner {
+				warnings.push(String::from("file modes and owners are only read on unix"));
+			}
+		}
+
+		if preserve.xattr || preserve.acl {
+			#[cfg(target_os = "linux")]
+			{
+				for name in xattr::list(path)?.into_iter().filter(|name| preserve.attribute(name)) {
+					match xattr::get(path, &name) {
+						Ok(value) => metadata.xattrs.push((name, value)),
+						Err(e) => warnings.push(format!("unable to read {} ({})", String::from_utf8_lossy(&name), e))
+					};
+				}
+			}
+			#[cfg(not(target_os = "linux"))]
+			{
+				warnings.push(String::from("extended attributes and ACLs are only read on Linux"));
+			}
+		}
+		Ok((metadata, warnings))
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.mode.is_none() && self.owner.is_none() && self.xattrs.is_empty()
+	}
+
+	pub fn acls(&self) -> usize {
+		self.xattrs.iter().filter(|(name, _)| is_acl(name)).count()
+	}
+
+	// Applies what preserve asks for to the file at path, returning warnings for anything that couldn't be restored
+	// (ie: ownership without root, or attributes the file system doesn't support) instead of failing
+	pub fn restore(&self, path: &Path, preserve: &Preserve) -> Vec<String> {
+		let mut warnings = Vec::new();
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			let mut owned = false;
+			if let (true, Some((uid, gid))) = (preserve.owner, self.owner) {
+				match std::os::unix::fs::chown(path, Some(uid), Some(gid)) {
+					Ok(_) => owned = true,
+					Err(e) => warnings.push(format!("unable to change the owner to {}:{} ({})", uid, gid, e))
+				};
+			}
+			// After the owner, as changing it clears the setuid & setgid bits. Like tar without --same-owner, those &
+			// the sticky bit are only kept on files given back their owner (or anyone could decode a setuid binary
+			// of their own from an image made elsewhere)
+			if let (true, Some(mode)) = (preserve.mode, self.mode) {
+				let mode = match (owned, mode & SPECIAL_BITS) {
+					(false, special) if special != 0 => {
+						warnings.push(format!("dropped the setuid, setgid & sticky bits of mode {:o} without restoring the owner", mode));
+						mode & !SPECIAL_BITS
+					},
+					_ => mode
+				};
+				if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)) {
+					warnings.push(format!("unable to change the mode to {:o} ({})", mode, e));
+				}
+			}
+		}
+		#[cfg(not(unix))]
+		{
+			if (preserve.owner && self.owner.is_some()) || (preserve.mode && self.mode.is_some()) {
+				warnings.push(String::from("file modes and owners are only restored on unix"));
+			}
+		}
+
+		let xattrs: Vec<&(Vec<u8>, Vec<u8>)> = self.xattrs.iter().filter(|(name, _)| preserve.attribute(name)).collect();
+		#[cfg(target_os = "linux")]
+		{
+			for (name, value) in xattrs {
+				if let Err(e) = xattr::set(path, name, value) {
+					warnings.push(format!("unable to restore {} ({})", String::from_utf8_lossy(name), e));
+				}
+			}
+		}
+		#[cfg(not(target_os = "linux"))]
+		{
+			if !xattrs.is_empty() {
+				warnings.push(format!("{} extended attributes and ACLs are only restored on Linux", xattrs.len()));
+			}
+		}
+		warnings
+	}
+
+	// Data of the chunk
+	pub fn to_data(&self) -> Vec<u8> {
+		let mut data = Vec::new();
+		if let Some(mode) = self.mode {
+			record(&mut data, MODE, &mode.to_be_bytes());
+		}
+		if let Some((uid, gid)) = self.owner {
+			record(&mut data, OWNER, &[uid.to_be_bytes(), gid.to_be_bytes()].concat());
+		}
+		for (name, value) in self.xattrs.iter() {
+			// Names of extended attributes are at most 255 bytes
+			record(&mut data, XATTR, &[&[name.len() as u8][..], name, value].concat());
+		}
+		data
+	}
+
+	// None unless every record is complete
+	pub fn read(mut data: &[u8]) -> Option<Self> {
+		let mut metadata = Self::default();
+		while !data.is_empty() {
+			if data.len() < 5 {
+				return None;
+			}
+			let length = u32::from_be_bytes(data[1..5].try_into().unwrap()) as usize;
+			let value = data.get(5..5 + length)?;
+			match data[0] {
+				MODE if length == 4 => metadata.mode = Some(u32::from_be_bytes(value.try_into().unwrap())),
+				OWNER if length == 8 => metadata.owner = Some((
+					u32::from_be_bytes(value[..4].try_into().unwrap()),
+					u32::from_be_bytes(value[4..].try_into().unwrap())
+				)),
+				XATTR if !value.is_empty() && (value[0] as usize) < length => {
+					let (name, value) = value[1..].split_at(value[0] as usize);
+					metadata.xattrs.push((name.to_vec(), value.to_vec()));
+				},
+				MODE | OWNER | XATTR => return None,
+				_ => ()
+			};
+			data = &data[5 + length..];
+		}
+		Some(metadata)
+	}
+}
+
+// Summary of what was captured (ie: mode 644, owner 1000:1000, 2 extended attributes, 2 ACLs)
+impl fmt::Display for Metadata {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut described = Vec::new();
+		if let Some(mode) = self.mode {
+			described.push(format!("mode {:o}", mode));
+		}
+		if let Some((uid, gid)) = self.owner {
+			described.push(format!("owner {}:{}", uid, gid));
+		}
+		let acls = self.acls();
+		if self.xattrs.len() > acls {
+			described.push(format!("{} extended attributes", self.xattrs.len() - acls));
+		}
+		if acls != 0 {
+			described.push(format!("{} ACLs", acls));
+		}
+		match described.is_empty() {
+			true => write!(f, "none"),
+			false => write!(f, "{}", described.join(", "))
+		}
+	}
+}
+
+fn record(data: &mut Vec<u8>, kind: u8, value: &[u8]) {
+	data.push(kind);
+	data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+	data.extend_from_slice(value);
+}
+
+fn is_acl(name: &[u8]) -> bool {
+	name.starts_with(ACL_PREFIX)
+}
+
+// Extended attributes of a path through the xattr system calls (following symbolic links)
+#[cfg(target_os = "linux")]
+mod xattr {
+	extern crate libc;
+
+	use std::ffi::CString;
+	use std::io;
+	use std::os::unix::ffi::OsStrExt;
+	use std::path::Path;
+
+	fn c_string(bytes: &[u8]) -> io::Result<CString> {
+		CString::new(bytes).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+	}
+
+	// Calls read with a buffer until it fits what it reads (sizes are asked for first, but may grow in between)
+	fn read_sized<F: Fn(&mut [u8]) -> isize>(read: F) -> io::Result<Vec<u8>> {
+		loop {
+			let size = read(&mut []);
+			if size < 0 {
+				return Err(io::Error::last_os_error());
+			}
+			let mut buf = vec![0u8; size as usize];
+			let size = read(&mut buf);
+			if size < 0 {
+				let e = io::Error::last_os_error();
+				if e.raw_os_error() == Some(libc::ERANGE) {
+					continue;
+				}
+				return Err(e);
+			}
+			buf.truncate(size as usize);
+			return Ok(buf);
+		}
+	}
+
+	// File systems without extended attributes have none to list
+	pub fn list(path: &Path) -> io::Result<Vec<Vec<u8>>> {
+		let path = c_string(path.as_os_str().as_bytes())?;
+		let names = match read_sized(|buf| unsafe { libc::listxattr(path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) }) {
+			Ok(names) => names,
+			Err(ref e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+			Err(e) => return Err(e)
+		};
+		Ok(names.split(|&b| b == 0).filter(|name| !name.is_empty()).map(|name| name.to_vec()).collect())
+	}
+
+	pub fn get(path: &Path, name: &[u8]) -> io::Result<Vec<u8>> {
+		let path = c_string(path.as_os_str().as_bytes())?;
+		let name = c_string(name)?;
+		read_sized(|buf| unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) })
+	}
+
+	pub fn set(path: &Path, name: &[u8], value: &[u8]) -> io::Result<()> {
+		let path = c_string(path.as_os_str().as_bytes())?;
+		let name = c_string(name)?;
+		match unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr() as *const libc::c_void, value.len(), 0) } {
+			0 => Ok(()),
+			_ => Err(io::Error::last_os_error())
+		}
+	}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+	use std::fs;
+	use std::os::unix::fs::PermissionsExt;
+	use std::path::PathBuf;
+
+	// Empty file of a test of its own with the given mode
+	fn file(name: &str, mode: u32) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("pngifier-metadata-{}-{}", name, std::process::id()));
+		fs::write(&path, b"").unwrap();
+		fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+		path
+	}
+
+	fn mode(path: &Path) -> u32 {
+		fs::metadata(path).unwrap().permissions().mode() & 0o7777
+	}
+
+	#[test]
+	fn mode_and_xattrs_survive_the_chunk() {
+		let source = file("source", 0o640);
+		// File systems without user.* attributes (ie: tmpfs on older kernels) only round trip the mode
+		let xattr = xattr_set(&source, b"user.pngifier", b"value");
+		let (captured, warnings) = Metadata::capture(&source, &Preserve::all()).unwrap();
+		assert!(warnings.is_empty(), "{:?}", warnings);
+		let read = Metadata::read(&captured.to_data()).unwrap();
+		assert_eq!(read, captured);
+
+		let destination = file("destination", 0o600);
+		let preserve = Preserve { mode: true, xattr: true, ..Preserve::default() };
+		assert!(read.restore(&destination, &preserve).is_empty());
+		assert_eq!(mode(&destination), 0o640);
+		if xattr {
+			assert_eq!(xattr::get(&destination, b"user.pngifier").unwrap(), b"value");
+		}
+		let _ = fs::remove_file(&source);
+		let _ = fs::remove_file(&destination);
+	}
+
+	#[test]
+	fn special_bits_need_the_owner_restored() {
+		let path = file("special", 0o644);
+		let (uid, gid) = {
+			use std::os::unix::fs::MetadataExt;
+			let stat = fs::metadata(&path).unwrap();
+			(stat.uid(), stat.gid())
+		};
+		let metadata = Metadata { mode: Some(0o4755), owner: Some((uid, gid)), xattrs: Vec::new() };
+
+		let warnings = metadata.restore(&path, &Preserve { mode: true, ..Preserve::default() });
+		assert_eq!(mode(&path), 0o755);
+		assert_eq!(warnings.len(), 1);
+
+		// The owner of the file is the one it already has, so restoring it works without root
+		assert!(metadata.restore(&path, &Preserve { mode: true, owner: true, ..Preserve::default() }).is_empty());
+		assert_eq!(mode(&path), 0o4755);
+		let _ = fs::remove_file(&path);
+	}
+
+	#[cfg(target_os = "linux")]
+	fn xattr_set(path: &Path, name: &[u8], value: &[u8]) -> bool {
+		xattr::set(path, name, value).is_ok()
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	fn xattr_set(_path: &Path, _name: &[u8], _value: &[u8]) -> bool {
+		false
+	}
+}
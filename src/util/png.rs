@@ -0,0 +1,651 @@
+extern crate flate2;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use crc32fast::Hasher;
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+use crate::util::color_type::*;
+use crate::util::dimensions::MAX_DIMENSION;
+use crate::util::timestamp;
+use crate::util::limits::MAX_CHUNK_LENGTH;
+use crate::util::PNG_HEADER;
+
+// Inflated text is cut off past this (compressed text from elsewhere may be a lot larger than its chunk)
+pub static MAX_TEXT: u64 = 1024 * 1024;
+
+// Data of a chunk of a known type, read & written without its length, type or crc
+pub trait ChunkData: Sized {
+	const CHUNK_TYPE: [u8; 4];
+
+	// Reads & validates the data of a chunk
+	fn parse(data: &[u8]) -> io::Result<Self>;
+
+	fn to_data(&self) -> Vec<u8>;
+
+	// Whether the fields can be written as they are (checked by ChunkWriter::write_typed)
+	fn validate(&self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+// Image header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ihdr {
+	pub width: u32,
+	pub height: u32,
+	pub bit_depth: u8,
+	pub color_type: u8,
+	// Adam7 interlacing (the compression & filter methods can only be 0)
+	pub interlaced: bool
+}
+
+impl Ihdr {
+	pub fn new(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Self {
+		Self {
+			width: width,
+			height: height,
+			bit_depth: bit_depth,
+			color_type: color_type,
+			interlaced: false
+		}
+	}
+
+	// Bytes of each pixel, None for palettes, depths under 8 bits & interlaced images (which pngifier doesn't pack
+	// files into)
+	pub fn bytes_per_pixel(&self) -> Option<usize> {
+		match type_exists(self.color_type) && self.bit_depth >= 8 && !self.interlaced {
+			true => Some(total_bytes(self.color_type, self.bit_depth)),
+			false => None
+		}
+	}
+}
+
+impl ChunkData for Ihdr {
+	const CHUNK_TYPE: [u8; 4] = *b"IHDR";
+
+	fn parse(data: &[u8]) -> io::Result<Self> {
+		if data.len() != 13 {
+			return Err(invalid("IHDR chunks hold 13 bytes"));
+		}
+		if data[10] != 0 || data[11] != 0 || data[12] > 1 {
+			return Err(invalid("unknown compression, filter or interlace method"));
+		}
+		let ihdr = Self {
+			width: u32::from_be_bytes(data[..4].try_into().unwrap()),
+			height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+			bit_depth: data[8],
+			color_type: data[9],
+			interlaced: data[12] == 1
+		};
+		ihdr.validate()?;
+		Ok(ihdr)
+	}
+
+	fn to_data(&self) -> Vec<u8> {
+		[
+			&self.width.to_be_bytes()[..],
+			&self.height.to_be_bytes()[..],
+			&[self.bit_depth, self.color_type, 0, 0, self.interlaced as u8][..]
+		].concat()
+	}
+
+	fn validate(&self) -> io::Result<()> {
+		if self.width == 0 || self.height == 0 || self.width as u64 > MAX_DIMENSION || self.height as u64 > MAX_DIMENSION {
+			return Err(invalid(&format!("dimensions of {}x{}px", self.width, self.height)));
+		}
+		let depths: &[u8] = match self.color_type {
+			0 => &[1, 2, 4, 8, 16],
+			3 => &[1, 2, 4, 8],
+			2 | 4 | 6 => &[8, 16],
+			_ => &[]
+		};
+		match depths.contains(&self.bit_depth) {
+			true => Ok(()),
+			false => Err(invalid(&format!("color type {} at {}-bit", self.color_type, self.bit_depth)))
+		}
+	}
+}
+
+// Palette of RGB entries
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plte {
+	pub entries: Vec<[u8; 3]>
+}
+
+impl ChunkData for Plte {
+	const CHUNK_TYPE: [u8; 4] = *b"PLTE";
+
+	fn parse(data: &[u8]) -> io::Result<Self> {
+		let entries = data.chunks_exact(3);
+		if !entries.remainder().is_empty() {
+			return Err(invalid("PLTE chunks hold whole RGB entries"));
+		}
+		let plte = Self {
+			entries: entries.map(|entry| [entry[0], entry[1], entry[2]]).collect()
+		};
+		plte.validate()?;
+		Ok(plte)
+	}
+
+	fn to_data(&self) -> Vec<u8> {
+		self.entries.concat()
+	}
+
+	fn validate(&self) -> io::Result<()> {
+		match self.entries.len() {
+			1..=256 => Ok(()),
+			entries => Err(invalid(&format!("palette of {} entries", entries)))
+		}
+	}
+}
+
+// Latin-1 text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+	pub keyword: String,
+	pub text: String
+}
+
+impl ChunkData for Text {
+	const CHUNK_TYPE: [u8; 4] = *b"tEXt";
+
+	fn parse(data: &[u8]) -> io::Result<Self> {
+		let (keyword, text) = read_keyword(data)?;
+		Ok(Self {
+			keyword: keyword,
+			text: latin1(text)
+		})
+	}
+
+	fn to_data(&self) -> Vec<u8> {
+		[&to_latin1(&self.keyword)[..], &[0], &to_latin1(&self.text)[..]].concat()
+	}
+
+	fn validate(&self) -> io::Result<()> {
+		validate_keyword(&self.keyword)?;
+		validate_latin1(&self.text)
+	}
+}
+
+// Compressed Latin-1 text
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZText {
+	pub keyword: String,
+	pub text: String
+}
+
+impl ChunkData for ZText {
+	const CHUNK_TYPE: [u8; 4] = *b"zTXt";
+
+	fn parse(data: &[u8]) -> io::Result<Self> {
+		let (keyword, rest) = read_keyword(data)?;
+		match rest.split_first() {
+			Some((0, compressed)) => Ok(Self {
+				keyword: keyword,
+				text: latin1(&inflate(compressed)?)
+			}),
+			_ => Err(invalid("unknown compression method"))
+		}
+	}
+
+	fn to_data(&self) -> Vec<u8> {
+		[&to_latin1(&self.keyword)[..], &[0, 0], &deflate(&to_latin1(&self.text))[..]].concat()
+	}
+
+	fn validate(&self) -> io::Result<()> {
+		validate_keyword(&self.keyword)?;
+		validate_latin1(&self.text)
+	}
+}
+
+// International (UTF-8) text, optionally compressed
+#[derive(Debug, Clone, PartialEq)]
+pub struct IText {
+	pub keyword: String,
+	pub compressed: bool,
+	// Language tag (ie: en-US), empty when unknown
+	pub language: String,
+	pub translated_keyword: String,
+	pub text: String
+}
+
+impl IText {
+	// Uncompressed text without a language or translated keyword
+	pub fn new(keyword: &str, text: &str) -> Self {
+		Self {
+			keyword: keyword.to_string(),
+			compressed: false,
+			language: String::new(),
+			translated_keyword: String::new(),
+			text: text.to_string()
+		}
+	}
+}
+
+impl ChunkData for IText {
+	const CHUNK_TYPE: [u8; 4] = *b"iTXt";
+
+	fn parse(data: &[u8]) -> io::Result<Self> {
+		let (keyword, rest) = read_keyword(data)?;
+		if rest.len() < 2 {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+		}
+		let (compressed, method) = (rest[0], rest[1]);
+		let mut parts = rest[2..].splitn(3, |&b| b == 0);
+		let language = parts.next().unwrap_or(&[]);
+		let translated_keyword = parts.next().ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+		let text = parts.next().ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+		let text = match (compressed, method) {
+			(0, _) => text.to_vec(),
+			(1, 0) => inflate(text)?,
+			_ => return Err(invalid("unknown compression method"))
+		};
+		Ok(Self {
+			keyword: keyword,
+			compressed: compressed == 1,
+			language: utf8(language)?,
+			translated_keyword: utf8(translated_keyword)?,
+			text: utf8(&text)?
+		})
+	}
+
+	fn to_data(&self) -> Vec<u8> {
+		let text = match self.compressed {
+			true => deflate(self.text.as_bytes()),
+			false => self.text.as_bytes().to_vec()
+		};
+		[
+			&to_latin1(&self.keyword)[..],
+			&[0, self.compressed as u8, 0],
+			self.language.as_bytes(),
+			&[0],
+			self.translated_keyword.as_bytes(),
+			&[0],
+			&text[..]
+		].concat()
+	}
+
+	fn validate(&self) -> io::Result<()> {
+		validate_keyword(&self.keyword)?;
+		match self.language.contains('\0') || self.translated_keyword.contains('\0') {
+			true => Err(invalid("null characters in the language or translated keyword")),
+			false => Ok(())
+		}
+	}
+}
+
+// UTC time of the last modification
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Time {
+	pub year: u16,
+	pub month: u8,
+	pub day: u8,
+	pub hour: u8,
+	pub minute: u8,
+	pub second: u8
+}
+
+impl Time {
+	// Time of seconds since the unix epoch (years past 65535 are clamped)
+	pub fn from_seconds(seconds: u64) -> Self {
+		let (year, month, day, hour, minute, second) = timestamp::civil(seconds);
+		Self {
+			year: std::cmp::min(year, u16::MAX as u64) as u16,
+			month: month,
+			day: day,
+			hour: hour,
+			minute: minute,
+			second: second
+		}
+	}
+}
+
+impl ChunkData for Time {
+	const CHUNK_TYPE: [u8; 4] = *b"tIME";
+
+	fn parse(data: &[u8]) -> io::Result<Self> {
+		if data.len() != 7 {
+			return Err(invalid("tIME chunks hold 7 bytes"));
+		}
+		let time = Self {
+			year: u16::from_be_bytes(data[..2].try_into().unwrap()),
+			month: data[2],
+			day: data[3],
+			hour: data[4],
+			minute: data[5],
+			second: data[6]
+		};
+		time.validate()?;
+		Ok(time)
+	}
+
+	fn to_data(&self) -> Vec<u8> {
+		[&self.year.to_be_bytes()[..], &[self.month, self.day, self.hour, self.minute, self.second][..]].concat()
+	}
+
+	fn validate(&self) -> io::Result<()> {
+		// Leap seconds are allowed
+		let valid = (1..=12).contains(&self.month) && (1..=31).contains(&self.day) && self.hour < 24 && self.minute < 60
+			&& self.second <= 60;
+		match valid {
+			true => Ok(()),
+			false => Err(invalid("time out of range"))
+		}
+	}
+}
+
+// Any other chunk, kept as it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unknown {
+	pub chunk_type: [u8; 4],
+	pub data: Vec<u8>
+}
+
+// A chunk read into the struct of its type
+#[derive(Debug, Clone, PartialEq)]
+pub enum PngChunk {
+	Ihdr(Ihdr),
+	Plte(Plte),
+	Text(Text),
+	ZText(ZText),
+	IText(IText),
+	Time(Time),
+	Unknown(Unknown)
+}
+
+impl PngChunk {
+	// Reads the data of a chunk of any type (types without a struct are Unknown)
+	pub fn parse(chunk_type: &[u8], data: &[u8]) -> io::Result<Self> {
+		let chunk_type: [u8; 4] = chunk_type.try_into().map_err(|_| invalid("chunk types are 4 bytes"))?;
+		Ok(match chunk_type {
+			Ihdr::CHUNK_TYPE => PngChunk::Ihdr(Ihdr::parse(data)?),
+			Plte::CHUNK_TYPE => PngChunk::Plte(Plte::parse(data)?),
+			Text::CHUNK_TYPE => PngChunk::Text(Text::parse(data)?),
+			ZText::CHUNK_TYPE => PngChunk::ZText(ZText::parse(data)?),
+			IText::CHUNK_TYPE => PngChunk::IText(IText::parse(data)?),
+			Time::CHUNK_TYPE => PngChunk::Time(Time::parse(data)?),
+			_ => PngChunk::Unknown(Unknown {
+				chunk_type: chunk_type,
+				data: data.to_vec()
+			})
+		})
+	}
+
+	pub fn chunk_type(&self) -> [u8; 4] {
+		match self {
+			PngChunk::Ihdr(_) => Ihdr::CHUNK_TYPE,
+			PngChunk::Plte(_) => Plte::CHUNK_TYPE,
+			PngChunk::Text(_) => Text::CHUNK_TYPE,
+			PngChunk::ZText(_) => ZText::CHUNK_TYPE,
+			PngChunk::IText(_) => IText::CHUNK_TYPE,
+			PngChunk::Time(_) => Time::CHUNK_TYPE,
+			PngChunk::Unknown(unknown) => unknown.chunk_type
+		}
+	}
+
+	pub fn to_data(&self) -> Vec<u8> {
+		match self {
+			PngChunk::Ihdr(chunk) => chunk.to_data(),
+			PngChunk::Plte(chunk) => chunk.to_data(),
+			PngChunk::Text(chunk) => chunk.to_data(),
+			PngChunk::ZText(chunk) => chunk.to_data(),
+			PngChunk::IText(chunk) => chunk.to_data(),
+			PngChunk::Time(chunk) => chunk.to_data(),
+			PngChunk::Unknown(unknown) => unknown.data.clone()
+		}
+	}
+
+	pub fn validate(&self) -> io::Result<()> {
+		match self {
+			PngChunk::Ihdr(chunk) => chunk.validate(),
+			PngChunk::Plte(chunk) => chunk.validate(),
+			PngChunk::Text(chunk) => chunk.validate(),
+			PngChunk::ZText(chunk) => chunk.validate(),
+			PngChunk::IText(chunk) => chunk.validate(),
+			PngChunk::Time(chunk) => chunk.validate(),
+			PngChunk::Unknown(_) => Ok(())
+		}
+	}
+}
+
+// Writes chunks (length, type, data & crc) to any output, hashing the crc as the data goes by so the data of a
+// chunk can be written in pieces
+pub struct ChunkWriter<W: Write> {
+	output: W,
+	hasher: Hasher,
+	// Bytes of data the open chunk still expects
+	left: u64
+}
+
+impl<W: Write> ChunkWriter<W> {
+	pub fn new(output: W) -> Self {
+		Self {
+			output: output,
+			hasher: Hasher::new(),
+			left: 0
+		}
+	}
+
+	pub fn write_signature(&mut self) -> io::Result<usize> {
+		self.output.write_all(&PNG_HEADER)?;
+		Ok(PNG_HEADER.len())
+	}
+
+	// Starts a chunk of length bytes of data, written through write_data
+	pub fn begin(&mut self, chunk_type: &[u8], length: u32) -> io::Result<()> {
+		if chunk_type.len() != 4 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk types are 4 bytes"));
+		}
+		if length as u64 > MAX_CHUNK_LENGTH {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk lengths are limited to 2^31 - 1 bytes"));
+		}
+		self.output.write_all(&length.to_be_bytes())?;
+		self.output.write_all(chunk_type)?;
+		self.hasher = Hasher::new();
+		self.hasher.update(chunk_type);
+		self.left = length as u64;
+		Ok(())
+	}
+
+	pub fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+		if data.len() as u64 > self.left {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "more data than the length of the chunk"));
+		}
+		self.output.write_all(data)?;
+		self.hasher.update(data);
+		self.left -= data.len() as u64;
+		Ok(())
+	}
+
+	// Ends the open chunk with its crc
+	pub fn end(&mut self) -> io::Result<()> {
+		if self.left != 0 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "less data than the length of the chunk"));
+		}
+		let crc = std::mem::replace(&mut self.hasher, Hasher::new()).finalize();
+		self.output.write_all(&crc.to_be_bytes())
+	}
+
+	// Returns the bytes written for the chunk
+	pub fn write_chunk(&mut self, chunk_type: &[u8], data: &[u8]) -> io::Result<usize> {
+		self.write_chunk_parts(chunk_type, &[data])
+	}
+
+	// Writes a chunk whose data is split across slices (ie: a sequence number ahead of frame data) without merging them
+	pub fn write_chunk_parts(&mut self, chunk_type: &[u8], parts: &[&[u8]]) -> io::Result<usize> {
+		let length: usize = parts.iter().map(|part| part.len()).sum();
+		if length as u64 > MAX_CHUNK_LENGTH {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk lengths are limited to 2^31 - 1 bytes"));
+		}
+		self.begin(chunk_type, length as u32)?;
+		for part in parts {
+			self.write_data(part)?;
+		}
+		self.end()?;
+		Ok(12 + length)
+	}
+
+	// Validates & writes a typed chunk
+	pub fn write_typed<C: ChunkData>(&mut self, chunk: &C) -> io::Result<usize> {
+		chunk.validate()?;
+		self.write_chunk(&C::CHUNK_TYPE, &chunk.to_data())
+	}
+
+	pub fn write_png_chunk(&mut self, chunk: &PngChunk) -> io::Result<usize> {
+		chunk.validate()?;
+		self.write_chunk(&chunk.chunk_type(), &chunk.to_data())
+	}
+
+	pub fn get_mut(&mut self) -> &mut W {
+		&mut self.output
+	}
+
+	pub fn into_inner(self) -> W {
+		self.output
+	}
+}
+
+// Keywords are 1 to 79 printable Latin-1 characters without leading, trailing or consecutive spaces
+pub fn valid_keyword(keyword: &str) -> bool {
+	let printable = |c: char| (' '..='~').contains(&c) || ('\u{a1}'..='\u{ff}').contains(&c);
+	let length = keyword.chars().count();
+	(1..=79).contains(&length) && keyword.chars().all(printable)
+		&& !keyword.starts_with(' ') && !keyword.ends_with(' ') && !keyword.contains("  ")
+}
+
+fn validate_keyword(keyword: &str) -> io::Result<()> {
+	match valid_keyword(keyword) {
+		true => Ok(()),
+		false => Err(invalid(&format!("keyword of '{}'", keyword)))
+	}
+}
+
+fn validate_latin1(text: &str) -> io::Result<()> {
+	match text.chars().all(|c| (c as u32) <= 0xff) {
+		true => Ok(()),
+		false => Err(invalid("text outside of Latin-1"))
+	}
+}
+
+// Keyword preceding the first null byte & everything following it
+fn read_keyword(data: &[u8]) -> io::Result<(String, &[u8])> {
+	let separator = data.iter().position(|&b| b == 0).ok_or_else(|| invalid("missing keyword"))?;
+	let keyword = latin1(&data[..separator]);
+	validate_keyword(&keyword)?;
+	Ok((keyword, &data[separator + 1..]))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+	bytes.iter().map(|&b| b as char).collect()
+}
+
+// Characters past Latin-1 are caught by validate
+fn to_latin1(s: &str) -> Vec<u8> {
+	s.chars().map(|c| c as u32 as u8).collect()
+}
+
+fn utf8(bytes: &[u8]) -> io::Result<String> {
+	String::from_utf8(bytes.to_vec()).map_err(|_| invalid("text that isn't UTF-8"))
+}
+
+fn inflate(compressed: &[u8]) -> io::Result<Vec<u8>> {
+	let mut inflated = Vec::new();
+	ZlibDecoder::new(compressed).take(MAX_TEXT).read_to_end(&mut inflated)?;
+	Ok(inflated)
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+	let mut deflater = ZlibEncoder::new(Vec::new(), Compression::default());
+	// Writing into memory can't fail
+	deflater.write_all(data).unwrap();
+	deflater.finish().unwrap()
+}
+
+fn invalid(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::Chunk;
+
+	// Writes the chunk & reads it back as it would be read from a file
+	fn round_trip(chunk: &PngChunk) -> PngChunk {
+		let mut writer = ChunkWriter::new(Vec::new());
+		writer.write_png_chunk(chunk).unwrap();
+		let written = writer.into_inner();
+		let read = Chunk::new(&mut &written[..]).unwrap().unwrap();
+		assert!(read.verify_crc());
+		read.parse().unwrap()
+	}
+
+	#[test]
+	fn typed_chunks_round_trip() {
+		let mut interlaced = Ihdr::new(640, 480, 1, 0);
+		interlaced.interlaced = true;
+		let mut compressed = IText::new("Comment", "éàü — 日本語 ".repeat(50).as_str());
+		compressed.compressed = true;
+		compressed.language = String::from("ja");
+		compressed.translated_keyword = String::from("コメント");
+		let chunks = [
+			PngChunk::Ihdr(Ihdr::new(1, u32::MAX >> 1, 16, 6)),
+			PngChunk::Ihdr(interlaced),
+			PngChunk::IText(IText::new("pngifier:project", "")),
+			PngChunk::IText(compressed),
+			PngChunk::Time(Time::from_seconds(1593820800)),
+			PngChunk::Time(Time { year: 1998, month: 12, day: 31, hour: 23, minute: 59, second: 60 })
+		];
+		for chunk in &chunks {
+			assert_eq!(&round_trip(chunk), chunk);
+		}
+		assert_eq!(Time::from_seconds(1593820800), Time { year: 2020, month: 7, day: 4, hour: 0, minute: 0, second: 0 });
+	}
+
+	#[test]
+	fn invalid_chunks_are_refused() {
+		// Zero dimensions, illegal depth & color type pairs and methods other than 0
+		for data in [
+			Ihdr::new(0, 1, 8, 2).to_data(),
+			Ihdr::new(1, 1, 4, 2).to_data(),
+			Ihdr::new(1, 1, 16, 3).to_data(),
+			Ihdr::new(1, 1, 8, 5).to_data(),
+			[&Ihdr::new(1, 1, 8, 2).to_data()[..12], &[2]].concat(),
+			Ihdr::new(1, 1, 8, 2).to_data()[..12].to_vec()
+		] {
+			assert!(Ihdr::parse(&data).is_err(), "{:?}", data);
+		}
+		assert!(Time::parse(&Time { year: 2020, month: 13, day: 1, hour: 0, minute: 0, second: 0 }.to_data()).is_err());
+		assert!(Time::parse(&[0; 6]).is_err());
+		assert!(IText::parse(b"Comment\0\x01\x01\0\0text").is_err());
+		assert!(IText::parse(b"\0\0\0\0\0text").is_err());
+		assert!(IText::parse(b"Comment\0\0\0\xff\0\0").is_err());
+
+		let mut writer = ChunkWriter::new(Vec::new());
+		assert!(writer.write_typed(&IText::new(" leading", "text")).is_err());
+		assert!(writer.write_typed(&Ihdr::new(1, 1, 3, 0)).is_err());
+		assert!(writer.into_inner().is_empty());
+	}
+
+	#[test]
+	fn crcs_are_hashed_across_pieces() {
+		let mut whole = ChunkWriter::new(Vec::new());
+		whole.write_chunk(b"IDAT", b"split across pieces").unwrap();
+		let mut pieces = ChunkWriter::new(Vec::new());
+		pieces.begin(b"IDAT", 19).unwrap();
+		for piece in [&b"split "[..], b"across", b"", b" pieces"] {
+			pieces.write_data(piece).unwrap();
+		}
+		assert!(pieces.write_data(b"!").is_err());
+		pieces.end().unwrap();
+		assert_eq!(pieces.into_inner(), whole.into_inner());
+
+		let mut short = ChunkWriter::new(Vec::new());
+		short.begin(b"IDAT", 2).unwrap();
+		short.write_data(b"a").unwrap();
+		assert!(short.end().is_err());
+	}
+}
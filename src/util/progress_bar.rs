@@ -4,6 +4,8 @@ pub struct ProgressBar {
 	header: String,
 	current: u64,
 	total: u64,
+	// Whether an unfinished bar is left on the current line
+	drawn: bool
 }
 
 // Custom progress bar
@@ -12,7 +14,8 @@ impl ProgressBar {
 		Self {
 			header: String::from(h),
 			current: 0,
-			total: t
+			total: t,
+			drawn: false
 		}
 	}
 	
@@ -29,11 +32,13 @@ impl ProgressBar {
 		};
 		if fill == size as usize {
 			println!("\r{} [\x1b[1;37m{}>\x1b[0m] 100.00%", self.header, t);
+			self.drawn = false;
 			return Some(());
 		}
 		let k = " ".repeat(size as usize - fill);
 		print!("\r{} [\x1b[1;37m{}>{}\x1b[0m] {:.2}%", self.header, t, k, (self.current as f64 / self.total as f64) * 100f64);
 		io::stdout().flush().expect("Unable to Flush to Stdout");
+		self.drawn = true;
 		None
 	}
 
@@ -47,14 +52,24 @@ impl ProgressBar {
 		self.tick(1)
 	}
 
-	// Currently Unused
-	pub fn _interrupt(&mut self) {
-		print!("\r\x1b[K");
-		io::stdout().flush().expect("Unable to Flush to Stdout");
+	// Clears an unfinished bar from the line (ie: before an error is printed)
+	pub fn interrupt(&mut self) {
+		if self.drawn {
+			print!("\r\x1b[K");
+			let _ = io::stdout().flush();
+			self.drawn = false;
+		}
 	}
 
 	// Currently Unused
 	pub fn _resume(&mut self) {
 		self.tick(0);
 	}
-}
\ No newline at end of file
+}
+
+// Bars of encodes & decodes that failed or were cancelled don't leave a half-drawn line behind
+impl Drop for ProgressBar {
+	fn drop(&mut self) {
+		self.interrupt();
+	}
+}
@@ -0,0 +1,242 @@
+// Reed-Solomon codes over GF(2^8) (primitive polynomial 0x11d, first consecutive root 0)
+
+pub static CODEWORD_SIZE: usize = 255;
+pub static PARITY_SIZE: usize = 32;
+pub static DATA_SIZE: usize = CODEWORD_SIZE - PARITY_SIZE;
+
+lazy_static! {
+	static ref EXP: [u8; 512] = {
+		let mut exp = [0u8; 512];
+		let mut x: u16 = 1;
+		for value in exp.iter_mut().take(255) {
+			*value = x as u8;
+			x <<= 1;
+			if x & 0x100 != 0 {
+				x ^= 0x11d;
+			}
+		}
+		for i in 255..512 {
+			exp[i] = exp[i - 255];
+		}
+		exp
+	};
+	static ref LOG: [u8; 256] = {
+		let mut log = [0u8; 256];
+		for i in 0..255 {
+			log[EXP[i] as usize] = i as u8;
+		}
+		log
+	};
+	static ref GENERATOR: Vec<u8> = {
+		// Highest degree first
+		let mut generator = vec![1u8];
+		for i in 0..PARITY_SIZE {
+			let mut next = vec![0u8; generator.len() + 1];
+			for (j, &g) in generator.iter().enumerate() {
+				next[j] ^= g;
+				next[j + 1] ^= mul(g, EXP[i]);
+			}
+			generator = next;
+		}
+		generator
+	};
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+	if a == 0 || b == 0 {
+		return 0;
+	}
+	EXP[LOG[a as usize] as usize + LOG[b as usize] as usize]
+}
+
+fn div(a: u8, b: u8) -> u8 {
+	if a == 0 {
+		return 0;
+	}
+	EXP[LOG[a as usize] as usize + 255 - LOG[b as usize] as usize]
+}
+
+fn pow(power: usize) -> u8 {
+	EXP[power % 255]
+}
+
+// Evaluates a polynomial stored lowest degree first
+fn eval_low(poly: &[u8], x: u8) -> u8 {
+	poly.iter().rev().fold(0u8, |acc, &c| mul(acc, x) ^ c)
+}
+
+// Encodes DATA_SIZE bytes into a CODEWORD_SIZE byte codeword (data followed by parity)
+pub fn encode(data: &[u8]) -> Vec<u8> {
+	let mut codeword = vec![0u8; CODEWORD_SIZE];
+	codeword[..data.len()].copy_from_slice(data);
+	let mut remainder = vec![0u8; PARITY_SIZE];
+	for &byte in codeword[..DATA_SIZE].iter() {
+		let factor = byte ^ remainder[0];
+		remainder.remove(0);
+		remainder.push(0);
+		for (r, &g) in remainder.iter_mut().zip(GENERATOR[1..].iter()) {
+			*r ^= mul(g, factor);
+		}
+	}
+	codeword[DATA_SIZE..].copy_from_slice(&remainder);
+	codeword
+}
+
+// Corrects up to PARITY_SIZE / 2 damaged bytes of a codeword in place, returning the number corrected
+pub fn decode(codeword: &mut [u8]) -> Option<usize> {
+	let n = codeword.len();
+	let syndromes: Vec<u8> = (0..PARITY_SIZE)
+		.map(|i| codeword.iter().fold(0u8, |acc, &c| mul(acc, pow(i)) ^ c))
+		.collect();
+	if syndromes.iter().all(|&s| s == 0) {
+		return Some(0);
+	}
+
+	// Berlekamp-Massey (lowest degree first)
+	let mut locator = vec![1u8];
+	let mut previous = vec![1u8];
+	let mut length = 0;
+	let mut shift = 1;
+	let mut last_discrepancy = 1u8;
+	for step in 0..PARITY_SIZE {
+		let mut discrepancy = syndromes[step];
+		for i in 1..=length {
+			if i < locator.len() {
+				discrepancy ^= mul(locator[i], syndromes[step - i]);
+			}
+		}
+		if discrepancy == 0 {
+			shift += 1;
+			continue;
+		}
+		let scale = div(discrepancy, last_discrepancy);
+		let mut next = locator.clone();
+		if next.len() < previous.len() + shift {
+			next.resize(previous.len() + shift, 0);
+		}
+		for (i, &p) in previous.iter().enumerate() {
+			next[i + shift] ^= mul(scale, p);
+		}
+		if 2 * length <= step {
+			previous = locator;
+			length = step + 1 - length;
+			last_discrepancy = discrepancy;
+			shift = 1;
+		} else {
+			shift += 1;
+		}
+		locator = next;
+	}
+	if length > PARITY_SIZE / 2 {
+		return None;
+	}
+
+	// Error evaluator = syndromes * locator mod x^PARITY_SIZE
+	let mut evaluator = vec![0u8; PARITY_SIZE];
+	for (i, &l) in locator.iter().enumerate() {
+		for (j, &s) in syndromes.iter().enumerate() {
+			if i + j < PARITY_SIZE {
+				evaluator[i + j] ^= mul(l, s);
+			}
+		}
+	}
+	let derivative: Vec<u8> = locator.iter().enumerate()
+		.skip(1)
+		.map(|(i, &l)| if i % 2 == 1 { l } else { 0 })
+		.collect();
+
+	// Chien search & Forney
+	let mut corrected = 0;
+	for (index, byte) in codeword.iter_mut().enumerate().take(n) {
+		let power = n - 1 - index;
+		let x_inverse = pow(255 - power % 255);
+		if eval_low(&locator, x_inverse) != 0 {
+			continue;
+		}
+		let denominator = eval_low(&derivative, x_inverse);
+		if denominator == 0 {
+			return None;
+		}
+		let magnitude = mul(pow(power), div(eval_low(&evaluator, x_inverse), denominator));
+		*byte ^= magnitude;
+		corrected += 1;
+	}
+	if corrected != length {
+		return None;
+	}
+	Some(corrected)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::util::testing::bytes;
+
+	// Flips count distinct bytes of a codeword (to values that always differ from the originals)
+	fn damage(codeword: &mut [u8], seed: u32, count: usize) {
+		let mut positions: Vec<usize> = Vec::new();
+		for (i, &b) in bytes(seed, CODEWORD_SIZE * 4).iter().enumerate() {
+			let position = (b as usize + i * 7) % codeword.len();
+			if !positions.contains(&position) {
+				positions.push(position);
+			}
+			if positions.len() == count {
+				break;
+			}
+		}
+		for (i, &position) in positions.iter().enumerate() {
+			codeword[position] ^= (i % 255 + 1) as u8;
+		}
+	}
+
+	#[test]
+	fn encode_keeps_data_and_has_no_syndromes() {
+		let data = bytes(1, DATA_SIZE);
+		let codeword = encode(&data);
+		assert_eq!(codeword.len(), CODEWORD_SIZE);
+		assert_eq!(&codeword[..DATA_SIZE], &data[..]);
+		assert_eq!(decode(&mut codeword.clone()), Some(0));
+	}
+
+	#[test]
+	fn short_data_is_padded_with_zeros() {
+		let codeword = encode(&[1, 2, 3]);
+		assert_eq!(&codeword[..3], &[1, 2, 3]);
+		assert!(codeword[3..DATA_SIZE].iter().all(|&b| b == 0));
+	}
+
+	#[test]
+	fn corrects_up_to_half_the_parity() {
+		for seed in 1..20 {
+			let codeword = encode(&bytes(seed, DATA_SIZE));
+			for count in [1, 2, PARITY_SIZE / 2 - 1, PARITY_SIZE / 2] {
+				let mut damaged = codeword.clone();
+				damage(&mut damaged, seed * 31 + count as u32, count);
+				assert_eq!(decode(&mut damaged), Some(count), "seed {} with {} errors", seed, count);
+				assert_eq!(damaged, codeword, "seed {} with {} errors", seed, count);
+			}
+		}
+	}
+
+	#[test]
+	fn corrects_damaged_parity() {
+		let codeword = encode(&bytes(7, DATA_SIZE));
+		let mut damaged = codeword.clone();
+		for byte in damaged[CODEWORD_SIZE - PARITY_SIZE / 2..].iter_mut() {
+			*byte ^= 0x5a;
+		}
+		assert_eq!(decode(&mut damaged), Some(PARITY_SIZE / 2));
+		assert_eq!(damaged, codeword);
+	}
+
+	#[test]
+	fn refuses_more_errors_than_it_can_correct() {
+		for seed in 1..20 {
+			let codeword = encode(&bytes(seed, DATA_SIZE));
+			let mut damaged = codeword.clone();
+			damage(&mut damaged, seed, PARITY_SIZE / 2 + 1);
+			assert_eq!(decode(&mut damaged), None, "seed {}", seed);
+		}
+	}
+}
@@ -0,0 +1,245 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::convert::TryInto;
+
+use crate::util::Limits;
+
+// Private ancillary chunk listing the holes of a sparse input (written before the image data, which skips them)
+pub static CHUNK_TYPE: &[u8; 4] = b"pgSP";
+
+// Range of a sparse file that reads as zeros without taking up disk space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hole {
+	pub offset: u64,
+	pub length: u64
+}
+
+// Holes of a file of size bytes, sorted by offset (only the rest of the file is encoded)
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoleMap {
+	pub size: u64,
+	pub holes: Vec<Hole>
+}
+
+impl HoleMap {
+	// None for files without holes
+	pub fn new(size: u64, holes: Vec<Hole>) -> Option<Self> {
+		match holes.is_empty() {
+			true => None,
+			false => Some(Self {
+				size: size,
+				holes: holes
+			})
+		}
+	}
+
+	pub fn hole_bytes(&self) -> u64 {
+		self.holes.iter().map(|hole| hole.length).sum()
+	}
+
+	// Bytes of the file outside of its holes
+	pub fn data_size(&self) -> u64 {
+		self.size - self.hole_bytes()
+	}
+
+	// Offset in the file of the data following packed bytes of data (past any holes starting there)
+	pub fn unpacked(&self, packed: u64) -> u64 {
+		let mut offset = packed;
+		for hole in self.holes.iter() {
+			if hole.offset > offset {
+				break;
+			}
+			offset += hole.length;
+		}
+		std::cmp::min(offset, self.size)
+	}
+
+	// Data of the chunk (the size of the file, then the offset & length of each hole)
+	pub fn to_data(&self) -> Vec<u8> {
+		let mut data = self.size.to_be_bytes().to_vec();
+		for hole in self.holes.iter() {
+			data.extend_from_slice(&hole.offset.to_be_bytes());
+			data.extend_from_slice(&hole.length.to_be_bytes());
+		}
+		data
+	}
+
+	// None unless the holes are in order & within the file, and the rest of the file fits in capacity bytes (the data
+	// the image can hold)
+	pub fn read(data: &[u8], capacity: u64) -> Option<Self> {
+		if data.len() % 16 != 8 {
+			return None;
+		}
+		let size = u64::from_be_bytes(data[..8].try_into().unwrap());
+		let mut end = 0u64;
+		let mut holes = Vec::with_capacity((data.len() - 8) / 16);
+		for hole in data[8..].chunks_exact(16) {
+			let hole = Hole {
+				offset: u64::from_be_bytes(hole[..8].try_into().unwrap()),
+				length: u64::from_be_bytes(hole[8..].try_into().unwrap())
+			};
+			if hole.offset < end || hole.length == 0 || hole.offset.checked_add(hole.length)? > size {
+				return None;
+			}
+			end = hole.offset + hole.length;
+			holes.push(hole);
+		}
+		Self::new(size, holes).filter(|map| map.data_size() <= capacity)
+	}
+}
+
+// Output that can leave a hole instead of writing zeros (ie: a file that can seek past them)
+pub trait Skip: Write {
+	fn skip(&mut self, length: u64) -> io::Result<()>;
+}
+
+// Writes holes as zeros (ie: to a stream or memory)
+pub struct Dense<W: Write>(pub W);
+
+impl<W: Write> Write for Dense<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.0.flush()
+	}
+}
+
+impl<W: Write> Skip for Dense<W> {
+	fn skip(&mut self, length: u64) -> io::Result<()> {
+		io::copy(&mut io::repeat(0).take(length), &mut self.0).map(|_| ())
+	}
+}
+
+// Reads the data of a sparse file, seeking past its holes
+pub struct SkipHoles<'a, R: Read + Seek> {
+	source: R,
+	holes: &'a [Hole],
+	// Offset in the file the source is at
+	position: u64
+}
+
+impl<'a, R: Read + Seek> SkipHoles<'a, R> {
+	// The source must already be at position (ie: continuing from a checkpoint)
+	pub fn new(source: R, map: &'a HoleMap, position: u64) -> Self {
+		let next = map.holes.partition_point(|hole| hole.offset + hole.length <= position);
+		Self {
+			source: source,
+			holes: &map.holes[next..],
+			position: position
+		}
+	}
+}
+
+impl<'a, R: Read + Seek> Read for SkipHoles<'a, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		while let Some(hole) = self.holes.first() {
+			if hole.offset > self.position {
+				break;
+			}
+			let end = hole.offset + hole.length;
+			self.source.seek(SeekFrom::Current((end - self.position) as i64))?;
+			self.position = end;
+			self.holes = &self.holes[1..];
+		}
+		let size = match self.holes.first() {
+			Some(hole) => std::cmp::min(buf.len() as u64, hole.offset - self.position) as usize,
+			None => buf.len()
+		};
+		let read = self.source.read(&mut buf[..size])?;
+		self.position += read as u64;
+		Ok(read)
+	}
+}
+
+// Places the data decoded from a sparse file at its offsets, leaving the holes in between (anything past the end of
+// the file, ie: the padding of the image, is dropped)
+#[derive(Debug)]
+pub struct Unpacker {
+	map: HoleMap,
+	// Index of the next hole
+	next: usize,
+	// Offset in the file the output is at
+	position: u64,
+	// Bounds the file written, holes included (a map can claim far more than the image holds)
+	limits: Limits
+}
+
+impl Unpacker {
+	pub fn new(map: HoleMap, limits: Limits) -> Self {
+		Self::at(map, 0, limits)
+	}
+
+	// Continues after packed bytes of data were written (ie: from a checkpoint, with the output cut to the unpacked
+	// offset), while an empty output still has any hole at the start of the file to come
+	pub fn at(map: HoleMap, packed: u64, limits: Limits) -> Self {
+		let position = match packed {
+			0 => 0,
+			packed => map.unpacked(packed)
+		};
+		Self {
+			next: map.holes.partition_point(|hole| hole.offset < position),
+			map: map,
+			position: position,
+			limits: limits
+		}
+	}
+
+	pub fn map(&self) -> &HoleMap {
+		&self.map
+	}
+
+	// Writes the next bytes of data
+	pub fn write<O: Skip + ?Sized>(&mut self, output: &mut O, mut data: &[u8]) -> io::Result<()> {
+		loop {
+			self.skip_holes(output)?;
+			if data.is_empty() || self.position >= self.map.size {
+				return Ok(());
+			}
+			let end = self.map.holes.get(self.next).map_or(self.map.size, |hole| hole.offset);
+			let size = std::cmp::min(data.len() as u64, end - self.position) as usize;
+			self.limits.check_output(self.position + size as u64)?;
+			output.write_all(&data[..size])?;
+			self.position += size as u64;
+			data = &data[size..];
+		}
+	}
+
+	// Skips the holes starting where the output is (once the data ends, this leaves the holes at the end of the file)
+	pub fn skip_holes<O: Skip + ?Sized>(&mut self, output: &mut O) -> io::Result<()> {
+		while let Some(hole) = self.map.holes.get(self.next) {
+			if hole.offset != self.position {
+				break;
+			}
+			self.limits.check_output(self.position + hole.length)?;
+			output.skip(hole.length)?;
+			self.position += hole.length;
+			self.next += 1;
+		}
+		Ok(())
+	}
+
+	// Writer passing everything through write
+	pub fn writer<'a, O: Skip + ?Sized>(&'a mut self, output: &'a mut O) -> Unpacking<'a, O> {
+		Unpacking {
+			unpacker: self,
+			output: output
+		}
+	}
+}
+
+pub struct Unpacking<'a, O: Skip + ?Sized> {
+	unpacker: &'a mut Unpacker,
+	output: &'a mut O
+}
+
+impl<'a, O: Skip + ?Sized> Write for Unpacking<'a, O> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.unpacker.write(self.output, buf)?;
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.output.flush()
+	}
+}
@@ -0,0 +1,12 @@
+// Fixtures shared by the unit tests of the crate (tests/common has the ones of the integration tests)
+
+// Deterministic bytes (xorshift) so failures can be reproduced
+pub fn bytes(seed: u32, count: usize) -> Vec<u8> {
+	let mut state = seed;
+	(0..count).map(|_| {
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		state as u8
+	}).collect()
+}
@@ -0,0 +1,45 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Seconds since the unix epoch
+pub fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// UTC (year, month, day, hour, minute, second) of seconds since the unix epoch
+pub fn civil(seconds: u64) -> (u64, u8, u8, u8, u8, u8) {
+	// Days to civil date (https://howardhinnant.github.io/date_algorithms.html)
+	let days = seconds / 86400 + 719468;
+	let era = days / 146097;
+	let day_of_era = days - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+	let year = year_of_era + era * 400 + (month <= 2) as u64;
+	let time = seconds % 86400;
+	(year, month as u8, day as u8, (time / 3600) as u8, (time / 60 % 60) as u8, (time % 60) as u8)
+}
+
+// Formats seconds since the unix epoch as a UTC date (ie: 2020-07-04)
+pub fn date(seconds: u64) -> String {
+	let (year, month, day, _, _, _) = civil(seconds);
+	format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// MS-DOS (time, date) of seconds since the unix epoch, as stored by ZIP archives (years outside 1980 to 2107 are
+// clamped, and seconds are halved)
+pub fn dos_time(seconds: u64) -> (u16, u16) {
+	let (year, month, day, hour, minute, second) = civil(seconds);
+	if year < 1980 {
+		return (0, (1 << 5) | 1);
+	}
+	let (year, month, day, hour, minute, second) = match year > 2107 {
+		true => (2107, 12, 31, 23, 59, 58),
+		false => (year, month, day, hour, minute, second)
+	};
+	(
+		((hour as u16) << 11) | ((minute as u16) << 5) | (second as u16 / 2),
+		(((year - 1980) as u16) << 9) | ((month as u16) << 5) | day as u16
+	)
+}
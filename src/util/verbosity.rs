@@ -3,13 +3,13 @@ pub static mut PROGRESS: bool = false;
 pub static mut SKIP: bool = false;
 pub static mut SILENT: bool = false;
 
+// The macros below only read their flag unsafely: the blocks given to them aren't made unsafe as well
+
 #[macro_export]
 macro_rules! verbose {
 	($body: block) => {
-		unsafe {
-			if util::verbosity::VERBOSE {
-				$body
-			}
+		if unsafe { util::verbosity::VERBOSE } {
+			$body
 		}
 	};
 }
@@ -17,10 +17,8 @@ macro_rules! verbose {
 #[macro_export]
 macro_rules! progress {
 	($body: block) => {
-		unsafe {
-			if util::verbosity::PROGRESS {
-				$body
-			}
+		if unsafe { util::verbosity::PROGRESS } {
+			$body
 		}
 	};
 }
@@ -28,10 +26,8 @@ macro_rules! progress {
 #[macro_export]
 macro_rules! skip {
 	($body: block) => {
-		unsafe {
-			if !util::verbosity::SKIP {
-				$body
-			}
+		if !unsafe { util::verbosity::SKIP } {
+			$body
 		}
 	};
 }
@@ -39,10 +35,8 @@ macro_rules! skip {
 #[macro_export]
 macro_rules! silent {
 	($body: block) => {
-		unsafe {
-			if !util::verbosity::SILENT {
-				$body
-			}
+		if !unsafe { util::verbosity::SILENT } {
+			$body
 		}
 	};
 }
\ No newline at end of file
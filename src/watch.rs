@@ -0,0 +1,162 @@
+extern crate notify;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::util;
+use crate::png_convert;
+use util::Error;
+
+pub struct WatchOptions {
+	pub input_dir: PathBuf,
+	pub output_dir: PathBuf,
+	pub error_dir: PathBuf,
+	pub state_path: PathBuf,
+	pub debounce: Duration,
+	pub decode: bool,
+	// Extension of encoded outputs (ie: png, qoi)
+	pub extension: &'static str
+}
+
+// Files that were already processed, recorded as "name\tsize\tmodified" lines
+struct State {
+	path: PathBuf,
+	entries: HashMap<String, (u64, u64)>
+}
+
+impl State {
+	fn load(path: PathBuf) -> Self {
+		let mut entries = HashMap::new();
+		if let Ok(contents) = fs::read_to_string(&path) {
+			for line in contents.lines() {
+				let fields: Vec<&str> = line.split('\t').collect();
+				if fields.len() != 3 {
+					continue;
+				}
+				if let (Ok(size), Ok(modified)) = (fields[1].parse::<u64>(), fields[2].parse::<u64>()) {
+					entries.insert(fields[0].to_string(), (size, modified));
+				}
+			}
+		}
+		Self {
+			path: path,
+			entries: entries
+		}
+	}
+
+	fn contains(&self, name: &str, stamp: (u64, u64)) -> bool {
+		self.entries.get(name) == Some(&stamp)
+	}
+
+	fn record(&mut self, name: &str, stamp: (u64, u64)) -> io::Result<()> {
+		let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+		writeln!(file, "{}\t{}\t{}", name, stamp.0, stamp.1)?;
+		self.entries.insert(name.to_string(), stamp);
+		Ok(())
+	}
+}
+
+// Watches a directory and converts every file added to it with the given process
+pub fn watch<F>(options: &WatchOptions, process: F) -> Result<(), Error>
+	where F: Fn(&Path, &Path) -> Result<(), Error> {
+	error_exp!(WriteFail, options.output_dir.display(), fs::create_dir_all(&options.output_dir));
+	let mut state = State::load(options.state_path.clone());
+
+	let (tx, rx) = channel();
+	let mut watcher = error_exp!(Watch, options.input_dir.display(), watcher(tx, options.debounce));
+	error_exp!(Watch, options.input_dir.display(), watcher.watch(&options.input_dir, RecursiveMode::NonRecursive));
+
+	// Catch up on files added while not running
+	let mut existing: Vec<PathBuf> = error_exp!(Watch, options.input_dir.display(), fs::read_dir(&options.input_dir))
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.path())
+		.collect();
+	existing.sort();
+	for path in existing {
+		handle(options, &mut state, &path, &process)?;
+	}
+
+	silent!({println!("Watching \x1b[1;36m'{}'\x1b[0m for new files.", options.input_dir.display())});
+	loop {
+		match rx.recv() {
+			Ok(DebouncedEvent::Create(path)) |
+			Ok(DebouncedEvent::Write(path)) |
+			Ok(DebouncedEvent::Rename(_, path)) => handle(options, &mut state, &path, &process)?,
+			Ok(DebouncedEvent::Error(e, _)) => verbose!({println!("\x1b[1;33mWarning: {}\x1b[0m", e)}),
+			Ok(_) => (),
+			Err(_) => error!(Watch, options.input_dir.display())
+		};
+	}
+}
+
+fn handle<F>(options: &WatchOptions, state: &mut State, path: &Path, process: &F) -> Result<(), Error>
+	where F: Fn(&Path, &Path) -> Result<(), Error> {
+	let name = match path.file_name().and_then(|s| s.to_str()) {
+		Some(name) => name.to_string(),
+		None => return Ok(())
+	};
+	// Hidden files are usually temporary files that are still being written
+	if !path.is_file() || name.starts_with('.') {
+		return Ok(());
+	}
+
+	let output_name = match options.decode {
+		true => match png_convert::container::strip_extension(&name) {
+			Some(stripped) => stripped.to_string(),
+			None => return Ok(())
+		},
+		false => format!("{}.{}", name, options.extension)
+	};
+
+	match stamp(path) {
+		Some(stamp) if !state.contains(&name, stamp) => (),
+		_ => return Ok(())
+	};
+	let stamp = match wait_until_stable(path, options.debounce) {
+		Some(stamp) => stamp,
+		None => return Ok(())
+	};
+
+	let output = options.output_dir.join(&output_name);
+	match process(path, &output) {
+		Ok(_) => error_exp!(WriteFail, options.state_path.display(), state.record(&name, stamp)),
+		Err(e) => {
+			// The previous output (if any) is left as it was by the atomic write
+			silent!({println!("{:?}", e)});
+			let _ = fs::create_dir_all(&options.error_dir);
+			let failed = options.error_dir.join(&name);
+			match fs::rename(path, &failed) {
+				Ok(_) => silent!({println!("Moved \x1b[1;36m'{}'\x1b[0m to \x1b[1;36m'{}'\x1b[0m.", path.display(), failed.display())}),
+				_ => silent!({println!("\x1b[1;33mWarning: Unable to move '{}' to '{}'.\x1b[0m", path.display(), failed.display())})
+			};
+		}
+	};
+	Ok(())
+}
+
+// Waits until the size and modified time of a file stop changing
+fn wait_until_stable(path: &Path, delay: Duration) -> Option<(u64, u64)> {
+	let mut previous = stamp(path)?;
+	loop {
+		thread::sleep(delay);
+		let current = stamp(path)?;
+		if current == previous {
+			return Some(current);
+		}
+		previous = current;
+	}
+}
+
+fn stamp(path: &Path) -> Option<(u64, u64)> {
+	let metadata = fs::metadata(path).ok()?;
+	let modified = metadata.modified().ok()?
+		.duration_since(UNIX_EPOCH).ok()?
+		.as_secs();
+	Some((metadata.len(), modified))
+}
@@ -0,0 +1,143 @@
+// Helpers shared by the tests running the pngifier binary
+#![allow(dead_code)]
+#![allow(clippy::redundant_field_names)]
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+// Empty directory of the test's own (removed again when dropped)
+pub struct TempDir {
+	pub path: PathBuf
+}
+
+impl TempDir {
+	pub fn new(name: &str) -> Self {
+		let path = std::env::temp_dir().join(format!("pngifier-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&path);
+		fs::create_dir_all(&path).unwrap();
+		Self { path: path }
+	}
+
+	pub fn join(&self, name: &str) -> String {
+		self.path.join(name).to_str().unwrap().to_string()
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.path);
+	}
+}
+
+// Runs pngifier, panicking with its output unless it succeeds, and returns what it printed
+pub fn pngifier(args: &[&str]) -> String {
+	let output = Command::new(env!("CARGO_BIN_EXE_pngifier")).args(args).output().unwrap();
+	assert!(
+		output.status.success(),
+		"pngifier {} failed:\n{}{}",
+		args.join(" "), String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)
+	);
+	String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+// Runs pngifier, panicking unless it fails with an error of its own (rather than a panic or an abort). It gets 1gb of
+// address space, so allocating what a crafted image claims fails even where memory is overcommitted
+pub fn pngifier_fails(args: &[&str]) {
+	let mut command = Command::new(env!("CARGO_BIN_EXE_pngifier"));
+	command.args(args);
+	#[cfg(unix)]
+	unsafe {
+		use std::os::unix::process::CommandExt;
+		command.pre_exec(|| {
+			let limit = libc::rlimit { rlim_cur: 1 << 30, rlim_max: 1 << 30 };
+			match libc::setrlimit(libc::RLIMIT_AS, &limit) {
+				0 => Ok(()),
+				_ => Err(std::io::Error::last_os_error())
+			}
+		});
+	}
+	let output = command.output().unwrap();
+	let printed = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+	assert!(output.status.code() == Some(1), "pngifier {} didn't fail cleanly:\n{}", args.join(" "), printed);
+	assert!(!printed.contains("panicked"), "pngifier {} panicked:\n{}", args.join(" "), printed);
+}
+
+// Deterministic bytes (xorshift) so failures can be reproduced
+pub fn bytes(seed: u32, count: usize) -> Vec<u8> {
+	let mut state = seed;
+	(0..count).map(|_| {
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		state as u8
+	}).collect()
+}
+
+// 16-bit RGBA PNG whose pgBN chunk claims rows of banner (a little image data follows, if not enough for the size)
+pub fn crafted_banner(width: u32, height: u32, rows: u32) -> Vec<u8> {
+	let mut ihdr = Vec::new();
+	ihdr.extend_from_slice(&width.to_be_bytes());
+	ihdr.extend_from_slice(&height.to_be_bytes());
+	ihdr.extend_from_slice(&[16, 6, 0, 0, 0]);
+	let mut zlib = ZlibEncoder::new(Vec::new(), Compression::fast());
+	zlib.write_all(&[0u8; 1024]).unwrap();
+
+	let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+	chunk(&mut png, b"IHDR", &ihdr);
+	chunk(&mut png, b"pgBN", &rows.to_be_bytes());
+	chunk(&mut png, b"IDAT", &zlib.finish().unwrap());
+	chunk(&mut png, b"IEND", b"");
+	png
+}
+
+// 8-bit RGB PNG of 1x1 whose pgSP chunk claims a file of size bytes with holes (offset & length), above a pixel of data
+pub fn crafted_holes(size: u64, holes: &[(u64, u64)]) -> Vec<u8> {
+	let mut ihdr = Vec::new();
+	ihdr.extend_from_slice(&1u32.to_be_bytes());
+	ihdr.extend_from_slice(&1u32.to_be_bytes());
+	ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+	let mut map = size.to_be_bytes().to_vec();
+	for (offset, length) in holes.iter() {
+		map.extend_from_slice(&offset.to_be_bytes());
+		map.extend_from_slice(&length.to_be_bytes());
+	}
+	let mut zlib = ZlibEncoder::new(Vec::new(), Compression::fast());
+	zlib.write_all(&[0, 1, 2, 3]).unwrap();
+
+	let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+	chunk(&mut png, b"IHDR", &ihdr);
+	chunk(&mut png, b"pgSP", &map);
+	chunk(&mut png, b"IDAT", &zlib.finish().unwrap());
+	chunk(&mut png, b"IEND", b"");
+	png
+}
+
+// 8-bit RGB PNG of 1x1 ending in the header of a chunk that claims length bytes of data (only a few follow)
+pub fn crafted_length(chunk_type: &[u8; 4], length: u32) -> Vec<u8> {
+	let mut ihdr = Vec::new();
+	ihdr.extend_from_slice(&1u32.to_be_bytes());
+	ihdr.extend_from_slice(&1u32.to_be_bytes());
+	ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+	let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+	chunk(&mut png, b"IHDR", &ihdr);
+	png.extend_from_slice(&length.to_be_bytes());
+	png.extend_from_slice(chunk_type);
+	png.extend_from_slice(&[0u8; 20]);
+	png
+}
+
+fn chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+	png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	png.extend_from_slice(chunk_type);
+	png.extend_from_slice(data);
+	let mut hasher = crc32fast::Hasher::new();
+	hasher.update(chunk_type);
+	hasher.update(data);
+	png.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
@@ -0,0 +1,45 @@
+// Chunk containers keep the file in private chunks next to a 1x1 placeholder, and decode back to exactly the file
+mod common;
+
+use std::fs;
+
+use common::{pngifier, TempDir};
+
+// Type & data of every chunk of a PNG
+fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+	let mut chunks = Vec::new();
+	let mut rest = &png[8..];
+	while rest.len() >= 12 {
+		let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+		chunks.push(([rest[4], rest[5], rest[6], rest[7]], rest[8..8 + length].to_vec()));
+		rest = &rest[12 + length..];
+	}
+	chunks
+}
+
+#[test]
+fn chunk_containers_round_trip() {
+	let dir = TempDir::new("containers");
+	let input = dir.join("input.bin");
+	let image = dir.join("input.png");
+	let output = dir.join("output.bin");
+	// Compresses to more than one chunk of 1kb
+	let payload = common::bytes(0x7f4a_7c15, 20_000);
+	fs::write(&input, &payload).unwrap();
+	pngifier(&["encode", "-y", "-s", "--container", "chunk", "--idat-size", "1kb", &input, &image]);
+
+	let png = fs::read(&image).unwrap();
+	let chunks = chunks(&png);
+	assert_eq!(&chunks[0].0, b"IHDR");
+	assert_eq!(chunks[0].1[..8], [0, 0, 0, 1, 0, 0, 0, 1]);
+	assert!(chunks.iter().filter(|(chunk_type, _)| chunk_type == b"pgDT").count() > 1);
+	assert!(chunks.iter().all(|(chunk_type, data)| chunk_type != b"pgDT" || data.len() <= 1024));
+	assert!(chunks.iter().filter(|(chunk_type, _)| chunk_type == b"IDAT").map(|(_, data)| data.len()).sum::<usize>() < 100);
+	assert!(pngifier(&["inspect", &image]).contains("strip ancillary chunks"));
+
+	// No padding follows the file, unlike with the pixels container
+	pngifier(&["decode", "-y", "-s", &image, &output]);
+	assert!(fs::read(&output).unwrap() == payload);
+	pngifier(&["decode", "-y", "-s", "--verify", &image, &output]);
+	assert!(fs::read(&output).unwrap() == payload);
+}
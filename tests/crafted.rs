@@ -0,0 +1,77 @@
+// Crafted PNGs fail to decode with an error instead of crashing, whichever way they're decoded (and real ones still decode)
+mod common;
+
+use std::fs;
+
+use common::{bytes, crafted_banner, crafted_holes, crafted_length, pngifier, pngifier_fails, TempDir};
+
+static DECODES: [&[&str]; 3] = [&[], &["--resume"], &["--preserve"]];
+
+fn assert_fails(dir: &TempDir, image: &[u8]) {
+	let input = dir.join("crafted.png");
+	let output = dir.join("output.bin");
+	fs::write(&input, image).unwrap();
+	for flags in DECODES.iter() {
+		let mut args = vec!["decode", "-y"];
+		args.extend_from_slice(flags);
+		args.extend_from_slice(&[&input, &output]);
+		pngifier_fails(&args);
+	}
+}
+
+#[test]
+fn banner_rows_overflowing_the_scanlines() {
+	let dir = TempDir::new("crafted-overflow");
+	assert_fails(&dir, &crafted_banner(0x7fff_ffff, 1, 0xffff_ffff));
+}
+
+#[test]
+fn banner_taller_than_the_image() {
+	let dir = TempDir::new("crafted-taller");
+	assert_fails(&dir, &crafted_banner(16, 4, 5));
+}
+
+#[test]
+fn holes_count_towards_the_output_limit() {
+	let dir = TempDir::new("crafted-holes");
+	let input = dir.join("crafted.png");
+	fs::write(&input, crafted_holes(1 << 26, &[(0, (1 << 26) - 3)])).unwrap();
+	pngifier_fails(&["decode", "-y", "--stream", "--max-output-bytes", "1kb", &input]);
+	pngifier_fails(&["decode", "-y", "--max-output-bytes", "1kb", &input, &dir.join("output.bin")]);
+}
+
+#[test]
+fn hole_maps_larger_than_the_image() {
+	let dir = TempDir::new("crafted-map");
+	// A 3 byte image can't hold the rest of a 2^40 byte file
+	assert_fails(&dir, &crafted_holes(1 << 40, &[(0, 1)]));
+}
+
+#[test]
+fn private_chunks_claiming_more_than_follows() {
+	let dir = TempDir::new("crafted-length");
+	for chunk_type in [b"pgSP", b"pgFI", b"pgMD"] {
+		// Past the limit of private chunks, and within it
+		for length in [0x7fff_fff0, 8 * 1024 * 1024] {
+			assert_fails(&dir, &crafted_length(chunk_type, length));
+		}
+	}
+}
+
+#[test]
+fn real_banners_still_decode() {
+	let dir = TempDir::new("crafted-real");
+	let input = dir.join("input.bin");
+	let image = dir.join("input.png");
+	let output = dir.join("output.bin");
+	let payload = bytes(43, 30_000);
+	fs::write(&input, &payload).unwrap();
+	pngifier(&["encode", "-y", "-s", "--banner", "Real", &input, &image]);
+	for flags in DECODES.iter() {
+		let mut args = vec!["decode", "-y", "-s"];
+		args.extend_from_slice(flags);
+		args.extend_from_slice(&[&image, &output]);
+		pngifier(&args);
+		assert!(fs::read(&output).unwrap()[..payload.len()] == *payload, "decode {:?} lost data", flags);
+	}
+}
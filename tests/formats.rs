@@ -0,0 +1,63 @@
+// Round trips through every output format, with payloads that look like opaque alpha or greyscale expanded to RGB
+mod common;
+
+use std::fs;
+
+use common::{pngifier, TempDir};
+
+// Formats writing 8-bit RGB (TIFF-deflate is TIFF with compressed strips)
+static RGB_FORMATS: [&str; 5] = ["bmp", "tiff", "tiff-deflate", "webp", "qoi"];
+static RGBA_FORMATS: [&str; 4] = ["bmp", "tiff", "webp", "qoi"];
+
+// Encodes & decodes the payload, returning what decode wrote
+fn round_trip(dir: &TempDir, payload: &[u8], color_type: &str, format: &str) -> Vec<u8> {
+	let input = dir.join("input.bin");
+	let image = dir.join(&format!("input.{}", format));
+	let output = dir.join("output.bin");
+	fs::write(&input, payload).unwrap();
+	pngifier(&["encode", "-y", "-s", "-t", color_type, "--format", format, &input, &image]);
+	pngifier(&["decode", "-y", "-s", &image, &output]);
+	fs::read(&output).unwrap()
+}
+
+// The payload, followed by nothing but the zeros padding the last row
+fn assert_payload(decoded: &[u8], payload: &[u8], case: &str) {
+	assert!(decoded.len() >= payload.len(), "{}: decoded {} of {} bytes", case, decoded.len(), payload.len());
+	assert!(decoded[..payload.len()] == *payload, "{}: payload differs", case);
+	assert!(decoded[payload.len()..].iter().all(|&b| b == 0), "{}: padding isn't zeros", case);
+}
+
+#[test]
+fn equal_triples_keep_every_channel() {
+	let dir = TempDir::new("formats-triples");
+	let payload: Vec<u8> = (0..6000u32).flat_map(|i| vec![(i % 251) as u8 + 1; 3]).collect();
+	for format in RGB_FORMATS.iter() {
+		let decoded = round_trip(&dir, &payload, "rgb", format);
+		assert_payload(&decoded, &payload, &format!("rgb {}", format));
+	}
+}
+
+#[test]
+fn opaque_alpha_is_kept() {
+	let dir = TempDir::new("formats-alpha");
+	let zeros = vec![0u8; 100];
+	let opaque: Vec<u8> = (0..4000u32).flat_map(|i| vec![(i % 7) as u8, (i % 13) as u8, (i % 17) as u8, 0xff]).collect();
+	for format in RGBA_FORMATS.iter() {
+		for payload in [&zeros, &opaque] {
+			let decoded = round_trip(&dir, payload, "rgba", format);
+			assert_payload(&decoded, payload, &format!("rgba {} of {} bytes", format, payload.len()));
+		}
+	}
+}
+
+#[test]
+fn random_payloads_round_trip() {
+	let dir = TempDir::new("formats-random");
+	let payload = common::bytes(0x2545_f491, 10_000);
+	for format in RGB_FORMATS.iter() {
+		assert_payload(&round_trip(&dir, &payload, "rgb", format), &payload, &format!("rgb {}", format));
+	}
+	for format in RGBA_FORMATS.iter() {
+		assert_payload(&round_trip(&dir, &payload, "rgba", format), &payload, &format!("rgba {}", format));
+	}
+}
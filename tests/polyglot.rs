@@ -0,0 +1,72 @@
+// ZIP polyglots read back as a ZIP archive of exactly the input (whatever surrounds it in the pixels) and still decode
+mod common;
+
+use std::fs;
+use std::io::Read;
+
+use flate2::read::DeflateDecoder;
+
+use common::{pngifier, TempDir};
+
+fn u16_at(data: &[u8], offset: usize) -> usize {
+	u16::from_le_bytes([data[offset], data[offset + 1]]) as usize
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+// Name & contents of the single entry of the archive, read the way unzip does: from the end of central directory
+// record, through the central directory, to the local header (checking both headers agree, the sizes & the crc)
+fn unzip(zip: &[u8]) -> (String, Vec<u8>) {
+	let end = &zip[zip.len() - 22..];
+	assert_eq!(u32_at(end, 0), 0x0605_4b50, "no end of central directory record");
+	assert_eq!((u16_at(end, 8), u16_at(end, 10)), (1, 1));
+	let central_offset = u32_at(end, 16) as usize;
+	assert_eq!(central_offset + u32_at(end, 12) as usize, zip.len() - 22, "central directory doesn't reach the end");
+
+	let central = &zip[central_offset..];
+	assert_eq!(u32_at(central, 0), 0x0201_4b50);
+	let local = &zip[u32_at(central, 42) as usize..];
+	assert_eq!(u32_at(local, 0), 0x0403_4b50);
+	// Flags through the length of the name
+	assert_eq!(local[6..28], central[8..30], "local & central headers differ");
+	let (crc, compressed, size) = (u32_at(local, 14), u32_at(local, 18) as usize, u32_at(local, 22) as usize);
+	let name_length = u16_at(local, 26);
+	let data_offset = 30 + name_length + u16_at(local, 28);
+
+	let mut contents = Vec::new();
+	DeflateDecoder::new(&local[data_offset..data_offset + compressed]).read_to_end(&mut contents).unwrap();
+	assert_eq!(contents.len(), size);
+	let mut hasher = crc32fast::Hasher::new();
+	hasher.update(&contents);
+	assert_eq!(hasher.finalize(), crc, "crc of the entry differs");
+	(String::from_utf8(local[30..30 + name_length].to_vec()).unwrap(), contents)
+}
+
+#[test]
+fn polyglots_unzip_to_the_input() {
+	let dir = TempDir::new("polyglot");
+	let input = dir.join("report.bin");
+	let output = dir.join("output.bin");
+	let payload = [common::bytes(0x3c6e_f372, 30_000), vec![0; 30_000]].concat();
+	fs::write(&input, &payload).unwrap();
+	// Banners precede the input in the pixels & rows are padded, neither of which belongs in the archive
+	for args in [&["--container", "chunk"][..], &[], &["--banner", "Report"]] {
+		let image = dir.join("report.png");
+		let mut encode = vec!["encode", "-y", "-s", "--polyglot", "zip"];
+		encode.extend_from_slice(args);
+		encode.extend_from_slice(&[&input, &image]);
+		pngifier(&encode);
+
+		let png = fs::read(&image).unwrap();
+		let (name, contents) = unzip(&png);
+		assert_eq!(name, "report.bin", "{:?}", args);
+		assert!(contents == payload, "{:?}: the archive doesn't hold the input", args);
+		assert!(pngifier(&["inspect", &image]).contains("Polyglot"), "{:?}", args);
+
+		pngifier(&["decode", "-y", "-s", &image, &output]);
+		let decoded = fs::read(&output).unwrap();
+		assert!(decoded.len() >= payload.len() && decoded[..payload.len()] == payload[..], "{:?}: decoded differs", args);
+	}
+}
@@ -0,0 +1,81 @@
+// Encoding the same input gives the same bytes, whatever the buffer, the time of the file or how it is read
+mod common;
+
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use common::{bytes, pngifier, TempDir};
+
+static BUFFERS: [&str; 4] = ["100", "4kb", "64kb", "10mb"];
+
+// Encodes the input with the given arguments, returning the image
+fn encode(dir: &TempDir, input: &str, args: &[&str]) -> Vec<u8> {
+	let image = dir.join("output.png");
+	let mut all = vec!["encode", "-y", "-s"];
+	all.extend_from_slice(args);
+	all.extend_from_slice(&[input, &image]);
+	pngifier(&all);
+	fs::read(&image).unwrap()
+}
+
+#[test]
+fn buffer_size_doesnt_change_the_image() {
+	let dir = TempDir::new("reproducible-buffer");
+	let input = dir.join("input.bin");
+	fs::write(&input, bytes(36, 300_000)).unwrap();
+	for args in [vec![], vec!["--idat-size", "8kb"], vec!["-t", "rgb", "--idat-size", "1000"]].iter() {
+		let expected = encode(&dir, &input, args);
+		for buffer in BUFFERS.iter() {
+			let mut with_buffer = args.clone();
+			with_buffer.extend_from_slice(&["--buffer", buffer]);
+			assert!(encode(&dir, &input, &with_buffer) == expected, "--buffer {} changed the image of {:?}", buffer, args);
+			with_buffer.truncate(args.len());
+			with_buffer.extend_from_slice(&["-b", buffer]);
+			assert!(encode(&dir, &input, &with_buffer) == expected, "-b {} changed the image of {:?}", buffer, args);
+		}
+	}
+}
+
+#[test]
+fn idat_size_only_moves_chunk_boundaries() {
+	let dir = TempDir::new("reproducible-idat");
+	let input = dir.join("input.bin");
+	let payload = bytes(37, 100_000);
+	fs::write(&input, &payload).unwrap();
+	for idat_size in ["1000", "8kb", "1mb"].iter() {
+		let image = encode(&dir, &input, &["--idat-size", idat_size]);
+		assert!(encode(&dir, &input, &["--idat-size", idat_size]) == image, "--idat-size {} isn't reproducible", idat_size);
+		let output = dir.join("output.bin");
+		pngifier(&["decode", "-y", "-s", &dir.join("output.png"), &output]);
+		assert!(fs::read(&output).unwrap()[..payload.len()] == *payload, "--idat-size {} changed the payload", idat_size);
+	}
+}
+
+#[test]
+fn touching_the_input_doesnt_change_the_banner() {
+	let dir = TempDir::new("reproducible-banner");
+	let input = dir.join("input.bin");
+	fs::write(&input, bytes(38, 20_000)).unwrap();
+	let args = ["--banner", "Reproducible"];
+	let expected = encode(&dir, &input, &args);
+	let file = fs::OpenOptions::new().write(true).open(&input).unwrap();
+	file.set_modified(SystemTime::now() - Duration::from_secs(86400 * 400)).unwrap();
+	assert!(encode(&dir, &input, &args) == expected, "the time of the input changed the banner");
+	// A given time is still shown
+	assert!(encode(&dir, &input, &["--banner", "Reproducible", "--mtime", "1593820800"]) != expected);
+}
+
+#[test]
+fn mapping_the_input_doesnt_change_the_image() {
+	let dir = TempDir::new("reproducible-mmap");
+	let input = dir.join("input.bin");
+	let payload = bytes(38, 200_000);
+	fs::write(&input, &payload).unwrap();
+	let image = encode(&dir, &input, &[]);
+	assert!(encode(&dir, &input, &["--mmap", "--verify"]) == image, "--mmap changed the image");
+	pngifier(&["inspect", "--mmap", &dir.join("output.png")]);
+	pngifier(&["verify", "--mmap", "--strict", &dir.join("output.png")]);
+	let output = dir.join("output.bin");
+	pngifier(&["decode", "-y", "-s", "--mmap", "--verify", &dir.join("output.png"), &output]);
+	assert!(fs::read(&output).unwrap()[..payload.len()] == *payload, "--mmap changed the payload");
+}
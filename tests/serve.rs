@@ -0,0 +1,163 @@
+// Requests that used to take the whole server down: oversized buffers & crafted images
+#![allow(clippy::redundant_field_names)]
+
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use common::{bytes, pngifier, TempDir};
+
+// pngifier serve on a free port (stopped when dropped)
+struct Server {
+	child: Child,
+	address: String
+}
+
+impl Server {
+	fn start(args: &[&str]) -> Self {
+		let address = {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			listener.local_addr().unwrap().to_string()
+		};
+		let child = Command::new(env!("CARGO_BIN_EXE_pngifier"))
+			.args(["serve", "-s", "--bind", &address])
+			.args(args)
+			.stdout(Stdio::null())
+			.spawn()
+			.unwrap();
+		for _ in 0..100 {
+			if TcpStream::connect(&address).is_ok() {
+				break;
+			}
+			thread::sleep(Duration::from_millis(50));
+		}
+		Self { child: child, address: address }
+	}
+
+	// Status & body of the response to a request
+	fn request(&self, method: &str, path: &str, body: &[u8]) -> (u16, Vec<u8>) {
+		let mut stream = TcpStream::connect(&self.address).unwrap();
+		write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n", method, path, self.address, body.len()).unwrap();
+		stream.write_all(body).unwrap();
+		let mut response = Vec::new();
+		let _ = stream.read_to_end(&mut response);
+		let head_end = response.windows(4).position(|window| window == b"\r\n\r\n").expect("no response");
+		let head = String::from_utf8_lossy(&response[..head_end]).to_string();
+		let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+		let mut body = response[head_end + 4..].to_vec();
+		if head.to_lowercase().contains("transfer-encoding: chunked") {
+			body = unchunk(&body);
+		}
+		(status, body)
+	}
+
+	// Raw response to a request claiming a body of length bytes, of which only body is sent
+	fn request_short(&self, method: &str, path: &str, length: u64, body: &[u8]) -> Vec<u8> {
+		let mut stream = TcpStream::connect(&self.address).unwrap();
+		write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n", method, path, self.address, length).unwrap();
+		stream.write_all(body).unwrap();
+		stream.shutdown(Shutdown::Write).unwrap();
+		let mut response = Vec::new();
+		let _ = stream.read_to_end(&mut response);
+		response
+	}
+
+	fn assert_healthy(&mut self) {
+		assert!(self.child.try_wait().unwrap().is_none(), "the server exited");
+		assert_eq!(self.request("GET", "/health", b"").0, 200);
+	}
+}
+
+impl Drop for Server {
+	fn drop(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+}
+
+fn unchunk(mut data: &[u8]) -> Vec<u8> {
+	let mut body = Vec::new();
+	while let Some(end) = data.windows(2).position(|window| window == b"\r\n") {
+		let size = usize::from_str_radix(std::str::from_utf8(&data[..end]).unwrap().trim(), 16).unwrap();
+		if size == 0 {
+			break;
+		}
+		body.extend_from_slice(&data[end + 2..end + 2 + size]);
+		data = &data[end + 2 + size + 2..];
+	}
+	body
+}
+
+// PNG of payload, encoded by the binary
+fn png(dir: &TempDir, payload: &[u8]) -> Vec<u8> {
+	let input = dir.join("input.bin");
+	let image = dir.join("input.png");
+	fs::write(&input, payload).unwrap();
+	pngifier(&["encode", "-y", "-s", &input, &image]);
+	fs::read(&image).unwrap()
+}
+
+#[test]
+fn queries_cant_size_the_buffer() {
+	let dir = TempDir::new("serve-query");
+	let payload = bytes(41, 50_000);
+	let image = png(&dir, &payload);
+	let mut server = Server::start(&[]);
+	let (status, body) = server.request("POST", "/decode?buffer=100000gb", &image);
+	assert_eq!(status, 200);
+	assert!(body[..payload.len()] == *payload);
+	server.assert_healthy();
+}
+
+#[test]
+fn decode_buffers_are_bounded() {
+	let dir = TempDir::new("serve-buffer");
+	let payload = bytes(42, 50_000);
+	let image = png(&dir, &payload);
+	let mut server = Server::start(&["-b", "100000gb"]);
+	let (status, body) = server.request("POST", "/decode", &image);
+	assert_eq!(status, 200);
+	assert!(body[..payload.len()] == *payload);
+	server.assert_healthy();
+}
+
+#[test]
+fn crafted_banners_fail_the_request_only() {
+	let mut server = Server::start(&[]);
+	// A banner of 0xffffffff rows above the widest 16-bit RGBA rows
+	let image = common::crafted_banner(0x7fff_ffff, 1, 0xffff_ffff);
+	let (status, _) = server.request("POST", "/decode", &image);
+	assert_ne!(status, 200);
+	server.assert_healthy();
+}
+
+#[test]
+fn encode_bodies_are_limited() {
+	let mut server = Server::start(&["--max-input-bytes", "1kb"]);
+	let (status, _) = server.request("POST", "/encode", &bytes(43, 2000));
+	assert_eq!(status, 413);
+	let (status, _) = server.request("POST", "/encode", &bytes(43, 1000));
+	assert_eq!(status, 200);
+	server.assert_healthy();
+
+	// Without --max-input-bytes, a body claiming 1tb is refused before the image is sized for it
+	let mut server = Server::start(&[]);
+	let response = server.request_short("POST", "/encode", 1 << 40, b"");
+	assert!(response.starts_with(b"HTTP/1.1 413"), "{}", String::from_utf8_lossy(&response));
+	server.assert_healthy();
+}
+
+#[test]
+fn short_encode_bodies_fail() {
+	let mut server = Server::start(&[]);
+	let response = server.request_short("POST", "/encode", 100_000, &bytes(44, 1000));
+	// Either an error, or a chunked body cut off without its final chunk
+	let complete = response.starts_with(b"HTTP/1.1 200") && response.ends_with(b"0\r\n\r\n");
+	assert!(!complete, "{}", String::from_utf8_lossy(&response));
+	server.assert_healthy();
+}
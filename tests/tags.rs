@@ -0,0 +1,46 @@
+// Tags written by encode are read back by tags & found by find, across subdirectories and past files that aren't PNGs
+mod common;
+
+use std::fs;
+
+use common::{pngifier, TempDir};
+
+#[test]
+fn tagged_images_are_found() {
+	let dir = TempDir::new("tags");
+	let input = dir.join("input.bin");
+	fs::write(&input, common::bytes(0x1d87_2b41, 5000)).unwrap();
+	fs::create_dir_all(dir.path.join("images/nested")).unwrap();
+	let images = [
+		("images/a.png", vec!["--tag", "project=foo", "--tag", "owner=ana"]),
+		("images/nested/b.png", vec!["--tag", "project=foo"]),
+		("images/c.png", vec!["--tag", "project=bar"]),
+		("images/untagged.png", vec![])
+	];
+	for (image, tags) in images.iter() {
+		let mut args = vec!["encode", "-y", "-s"];
+		args.extend_from_slice(tags);
+		let image = dir.join(image);
+		args.extend_from_slice(&[&input, &image]);
+		pngifier(&args);
+	}
+	fs::write(dir.join("images/notes.png"), b"not a png").unwrap();
+
+	assert_eq!(
+		pngifier(&["tags", "--json", &dir.join("images/a.png")]).trim(),
+		r#"[{"key":"project","value":"foo"},{"key":"owner","value":"ana"}]"#
+	);
+	let found = |tags: &[&str]| -> Vec<String> {
+		let images = dir.join("images");
+		let mut args = vec!["find", &images[..]];
+		for tag in tags {
+			args.extend_from_slice(&["--tag", tag]);
+		}
+		let prefix = format!("{}/", images);
+		pngifier(&args).lines().map(|line| line.trim_start_matches(&prefix).to_string()).collect()
+	};
+	assert_eq!(found(&["project=foo"]), vec!["a.png", "nested/b.png"]);
+	assert_eq!(found(&["project"]), vec!["a.png", "c.png", "nested/b.png"]);
+	assert_eq!(found(&["project=foo", "owner"]), vec!["a.png"]);
+	assert!(found(&["owner=bob"]).is_empty());
+}
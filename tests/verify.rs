@@ -0,0 +1,30 @@
+// verify --strict passes the images encode writes, and fails (reporting where) on ones breaking the specification
+mod common;
+
+use std::fs;
+
+use common::{pngifier, pngifier_fails, TempDir};
+
+#[test]
+fn strict_verification() {
+	let dir = TempDir::new("verify");
+	let input = dir.join("input.bin");
+	let image = dir.join("input.png");
+	fs::write(&input, common::bytes(0x6a09_e667, 50_000)).unwrap();
+	for args in [&[][..], &["--container", "chunk"], &["--apng", "--frame-size", "64x64"], &["--polyglot", "zip"]] {
+		let mut encode = vec!["encode", "-y", "-s", "--idat-size", "8kb"];
+		encode.extend_from_slice(args);
+		encode.extend_from_slice(&[&input, &image]);
+		pngifier(&encode);
+		let report = pngifier(&["verify", "--strict", "--json", &image]);
+		assert!(report.starts_with(r#"{"valid":true,"errors":0,"#), "{:?}: {}", args, report);
+	}
+
+	// Without its IEND chunk
+	let png = fs::read(&image).unwrap();
+	let iend = png.windows(4).rposition(|window| window == b"IEND").unwrap() - 4;
+	let truncated = dir.join("truncated.png");
+	fs::write(&truncated, &png[..iend]).unwrap();
+	pngifier(&["verify", &truncated]);
+	pngifier_fails(&["verify", "--strict", &truncated]);
+}
@@ -0,0 +1,83 @@
+// Watching converts files already there & dropped in later, skips what the state file records as done after a
+// restart and moves inputs that fail to the error directory
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::TempDir;
+
+// Running pngifier watch, killed when dropped
+struct Watch(Child);
+
+impl Watch {
+	// Starts watching & waits until the files already in the directory are converted
+	fn start(args: &[&str]) -> Self {
+		let mut child = Command::new(env!("CARGO_BIN_EXE_pngifier"))
+			.arg("watch").arg("--debounce").arg("1").args(args)
+			.stdout(Stdio::piped())
+			.spawn().unwrap();
+		let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+		let watch = Watch(child);
+		while let Some(line) = lines.next() {
+			if line.unwrap().contains("Watching") {
+				// Keeps reading what it prints, which would fail once nothing reads it
+				thread::spawn(move || lines.for_each(drop));
+				return watch;
+			}
+		}
+		panic!("pngifier watch {} exited", args.join(" "));
+	}
+}
+
+impl Drop for Watch {
+	fn drop(&mut self) {
+		let _ = self.0.kill();
+		let _ = self.0.wait();
+	}
+}
+
+// Waits for a file to show up, panicking after 30s
+fn wait_for(path: &str) {
+	let start = Instant::now();
+	while !Path::new(path).exists() {
+		assert!(start.elapsed() < Duration::from_secs(30), "'{}' never showed up", path);
+		thread::sleep(Duration::from_millis(100));
+	}
+}
+
+#[test]
+fn watched_files_are_converted_once() {
+	let dir = TempDir::new("watch");
+	for name in ["in", "out", "decoded"] {
+		fs::create_dir(dir.path.join(name)).unwrap();
+	}
+	let (input, output, decoded) = (dir.join("in"), dir.join("out"), dir.join("decoded"));
+	let early = common::bytes(0x9b05_688c, 10_000);
+	let late = common::bytes(0x510e_527f, 20_000);
+	fs::write(dir.join("in/early.bin"), &early).unwrap();
+
+	let watch = Watch::start(&[&input, &output]);
+	assert!(Path::new(&dir.join("out/early.bin.png")).exists());
+	// Written under a hidden name first, as hidden files are left alone until renamed
+	fs::write(dir.join("in/.late.bin"), &late).unwrap();
+	fs::rename(dir.join("in/.late.bin"), dir.join("in/late.bin")).unwrap();
+	wait_for(&dir.join("out/late.bin.png"));
+	drop(watch);
+
+	// Restarting doesn't convert early.bin again
+	fs::remove_file(dir.join("out/early.bin.png")).unwrap();
+	drop(Watch::start(&[&input, &output]));
+	assert!(!Path::new(&dir.join("out/early.bin.png")).exists());
+
+	fs::write(dir.join("out/broken.png"), b"\x89PNG\r\n\x1a\nnot really").unwrap();
+	let _watch = Watch::start(&["--decode", &output, &decoded]);
+	assert!(fs::read(dir.join("decoded/late.bin")).unwrap()[..late.len()] == late[..]);
+	assert!(Path::new(&dir.join("out/errors/broken.png")).exists());
+	assert!(!Path::new(&dir.join("out/broken.png")).exists());
+	assert!(!Path::new(&dir.join("decoded/broken")).exists());
+}
@@ -0,0 +1,58 @@
+#!/bin/bash
+
+# Times encoding, verifying, inspecting & decoding a large file with regular reads and with memory maps, reporting
+# the mean, standard deviation & range over several runs (builds without --mmap only run with their own reads)
+# usage: travis/bench.sh [size in mb (default: 4096)] [pngifier binary (default: target/release/pngifier)] [runs (default: 5)]
+SIZE_MB=${1:-4096}
+BIN=${2:-target/release/pngifier}
+RUNS=${3:-5}
+DIR=$(mktemp -d)
+trap 'rm -r $DIR' EXIT
+
+if [ "$BIN" = target/release/pngifier ]; then
+	cargo build --quiet --release || exit 1
+fi
+
+# Incompressible data, the worst case for the size of the output
+head -c $((SIZE_MB * 1024 * 1024)) /dev/urandom > $DIR/input.bin
+
+bench() {
+	name=$1
+	shift
+	times=""
+	for run in $(seq $RUNS); do
+		sync
+		start=$(date +%s.%N)
+		"$@" > /dev/null || echo "Failed: $*"
+		end=$(date +%s.%N)
+		times="$times $(awk -v a=$start -v b=$end 'BEGIN { print b - a }')"
+	done
+	echo $times | awk -v name="$name" -v size=$SIZE_MB '{
+		for (i = 1; i <= NF; i++) {
+			sum += $i
+			if (i == 1 || $i < min) min = $i
+			if (i == 1 || $i > max) max = $i
+		}
+		mean = sum / NF
+		for (i = 1; i <= NF; i++) deviation += ($i - mean) ^ 2
+		printf "%-24s %8.2fs ± %5.2fs (%.2fs - %.2fs) %10.1f MB/s\n", name, mean, sqrt(deviation / NF), min, max, size / mean
+	}'
+}
+
+modes="read mmap"
+if ! $BIN decode --help | grep -q -- --mmap; then
+	modes="read"
+fi
+
+echo "pngifier on $SIZE_MB MB, $RUNS runs"
+for mode in $modes; do
+	flag=""
+	if [ $mode = mmap ]; then
+		flag="--mmap"
+	fi
+	bench "encode ($mode)" $BIN encode -y -s $flag $DIR/input.bin $DIR/output.png
+	bench "decode --verify ($mode)" $BIN decode -y -s --verify $flag $DIR/output.png $DIR/output.bin
+	bench "decode ($mode)" $BIN decode -y -s $flag $DIR/output.png $DIR/output.bin
+	cmp -s -n $((SIZE_MB * 1024 * 1024)) $DIR/input.bin $DIR/output.bin || echo "Round trip failed"
+	bench "inspect ($mode)" $BIN inspect $flag $DIR/output.png
+done